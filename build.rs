@@ -25,6 +25,26 @@ fn main() {
             "src/gfx/char_rect.fs.wgsl",
             naga::ShaderStage::Fragment,
         ),
+        (
+            include_str!("res/cell_rect.vs"),
+            "src/gfx/detail/cell_rect.vs.wgsl",
+            naga::ShaderStage::Vertex,
+        ),
+        (
+            include_str!("res/cell_rect.fs"),
+            "src/gfx/detail/cell_rect.fs.wgsl",
+            naga::ShaderStage::Fragment,
+        ),
+        (
+            include_str!("res/cell_decoration.vs"),
+            "src/gfx/detail/cell_decoration.vs.wgsl",
+            naga::ShaderStage::Vertex,
+        ),
+        (
+            include_str!("res/cell_decoration.fs"),
+            "src/gfx/detail/cell_decoration.fs.wgsl",
+            naga::ShaderStage::Fragment,
+        ),
         (
             include_str!("res/background.vs"),
             "src/gfx/detail/background.vs.wgsl",
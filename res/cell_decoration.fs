@@ -0,0 +1,83 @@
+#version 450
+
+layout (location = 0) out vec4 o_Color;
+layout (location = 0) in vec2 v_LocalPosition;
+layout (location = 1) in vec4 v_UnderlineColor;
+layout (location = 2) in vec4 v_StrikeoutColor;
+layout (location = 3) in vec4 v_Metrics;
+layout (location = 4) in vec4 v_StrikeoutMetrics;
+layout (location = 5) flat in uvec4 v_Style;
+
+// 下線の種類
+const uint UNDERLINE_NONE = 0;
+const uint UNDERLINE_SINGLE = 1;
+const uint UNDERLINE_DOUBLE = 2;
+const uint UNDERLINE_UNDERCURL = 3;
+const uint UNDERLINE_DOTTED = 4;
+const uint UNDERLINE_DASHED = 5;
+
+const float PI = 3.14159265;
+
+bool isInLine(float y, float top, float thickness)
+{
+    return top <= y && y < top + thickness;
+}
+
+float underlineAlpha(vec2 position)
+{
+    float cellWidth = v_Metrics.x;
+    float top = v_Metrics.z;
+    float thickness = v_Metrics.w;
+    uint style = v_Style.x;
+
+    if (style == UNDERLINE_SINGLE)
+    {
+        return isInLine(position.y, top, thickness) ? 1.0 : 0.0;
+    }
+    else if (style == UNDERLINE_DOUBLE)
+    {
+        // 下線の位置を挟むように 2 本引く
+        bool upper = isInLine(position.y, top - thickness, thickness);
+        bool lower = isInLine(position.y, top + thickness, thickness);
+        return (upper || lower) ? 1.0 : 0.0;
+    }
+    else if (style == UNDERLINE_UNDERCURL)
+    {
+        // セル幅で 1 周期の波
+        float amplitude = max(thickness, 1.0);
+        float center = top + thickness * 0.5 + amplitude * cos(2.0 * PI * position.x / cellWidth) * 0.5;
+        float distance = abs(position.y - center);
+        return 1.0 - smoothstep(thickness * 0.5, thickness * 0.5 + 1.0, distance);
+    }
+    else if (style == UNDERLINE_DOTTED)
+    {
+        bool isDot = mod(floor(position.x / thickness), 2.0) == 0.0;
+        return (isDot && isInLine(position.y, top, thickness)) ? 1.0 : 0.0;
+    }
+    else if (style == UNDERLINE_DASHED)
+    {
+        // セルの中央に半セル幅の線を引いて隣のセルとの間を空ける
+        bool isDash = cellWidth * 0.25 <= position.x && position.x < cellWidth * 0.75;
+        return (isDash && isInLine(position.y, top, thickness)) ? 1.0 : 0.0;
+    }
+
+    return 0.0;
+}
+
+void main()
+{
+    float alpha = underlineAlpha(v_LocalPosition);
+    if (0.0 < alpha)
+    {
+        o_Color = vec4(v_UnderlineColor.xyz, v_UnderlineColor.a * alpha);
+        return;
+    }
+
+    if (v_Style.y != 0 && isInLine(v_LocalPosition.y, v_StrikeoutMetrics.x, v_StrikeoutMetrics.y))
+    {
+        o_Color = v_StrikeoutColor;
+        return;
+    }
+
+    discard;
+}
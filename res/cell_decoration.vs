@@ -0,0 +1,48 @@
+#version 450
+
+layout (location = 0) out vec2 v_LocalPosition;
+layout (location = 1) out vec4 v_UnderlineColor;
+layout (location = 2) out vec4 v_StrikeoutColor;
+layout (location = 3) out vec4 v_Metrics;
+layout (location = 4) out vec4 v_StrikeoutMetrics;
+layout (location = 5) flat out uvec4 v_Style;
+layout (location = 0) in vec2 i_Position;
+
+struct CellDecorationData
+{
+    vec4 transform[2];
+    vec4 underlineColor;
+    vec4 strikeoutColor;
+
+    // セルの幅、セルの高さ、下線の上端、下線の太さ
+    vec4 metrics;
+
+    // 取り消し線の上端、取り消し線の太さ
+    vec4 strikeoutMetrics;
+
+    // 下線の種類、取り消し線の有無
+    uvec4 style;
+};
+
+layout(std430, binding = 0) readonly buffer CellDecorationDataBuffer
+{
+    CellDecorationData u_CellDecorationDatas[];
+};
+
+void main()
+{
+    CellDecorationData data = u_CellDecorationDatas[gl_InstanceIndex];
+    vec2 position = vec2(
+        dot(data.transform[0].xyz, vec3(i_Position, 1.0)),
+        dot(data.transform[1].xyz, vec3(i_Position, 1.0))
+        );
+    gl_Position = vec4(position, 0.0, 1.0);
+
+    // セルの左上を原点としたピクセル座標
+    v_LocalPosition = i_Position * data.metrics.xy;
+    v_UnderlineColor = data.underlineColor;
+    v_StrikeoutColor = data.strikeoutColor;
+    v_Metrics = data.metrics;
+    v_StrikeoutMetrics = data.strikeoutMetrics;
+    v_Style = data.style;
+}
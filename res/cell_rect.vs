@@ -0,0 +1,26 @@
+#version 450
+
+layout (location = 0) out vec4 v_Color;
+layout (location = 0) in vec2 i_Position;
+
+struct CellData
+{
+    vec4 transform[2];
+    vec4 color;
+};
+
+layout(std430, binding = 0) readonly buffer CellDataBuffer
+{
+    CellData u_CellDatas[];
+};
+
+void main()
+{
+    CellData cellData = u_CellDatas[gl_InstanceIndex];
+    vec2 position = vec2(
+        dot(cellData.transform[0].xyz, vec3(i_Position, 1.0)),
+        dot(cellData.transform[1].xyz, vec3(i_Position, 1.0))
+        );
+    gl_Position = vec4(position, 0.0, 1.0);
+    v_Color = cellData.color;
+}
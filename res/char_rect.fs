@@ -3,14 +3,29 @@
 layout (location = 0) out vec4 o_Color;
 layout (location = 0) in vec2 v_Uv;
 layout (location = 1) in vec4 v_ForeGroundColor;
+layout (location = 2) flat in uint v_IsColor;
+layout (location = 3) flat in uint v_Page;
 
-layout (binding = 1) uniform texture2D u_GlyphTexture;
+// アトラスのページをレイヤーに持つ
+layout (binding = 1) uniform texture2DArray u_GlyphTexture;
 layout (binding = 2) uniform sampler u_GlyphSampler;
+layout (binding = 3) uniform texture2DArray u_ColorGlyphTexture;
 
 void main()
 {
+    // 分岐の中でサンプリングできないので両方読んでおく
+    vec3 uv = vec3(v_Uv, float(v_Page));
+    vec4 alpha = texture(sampler2DArray(u_GlyphTexture, u_GlyphSampler), uv);
+    vec4 color = texture(sampler2DArray(u_ColorGlyphTexture, u_GlyphSampler), uv);
+
+    // カラー絵文字は前景色で染めずにそのままの色で描く
+    // アルファ乗算済みなのでブレンドに合わせて戻す
+    if (v_IsColor != 0)
+    {
+        o_Color = vec4(color.rgb / max(color.a, 0.0001), color.a);
+        return;
+    }
+
     // グリフをアルファで抜く
-    // TODO: 色に対応する
-    vec4 alpha = texture(sampler2D(u_GlyphTexture, u_GlyphSampler), v_Uv);
     o_Color = vec4(v_ForeGroundColor.xyz, alpha);
 }
@@ -2,6 +2,8 @@
 
 layout (location = 0) out vec2 v_Uv;
 layout (location = 1) out vec4 v_ForeGroundColor;
+layout (location = 2) flat out uint v_IsColor;
+layout (location = 3) flat out uint v_Page;
 layout (location = 0) in vec2 i_Position;
 
 struct CharacterData
@@ -10,6 +12,10 @@ struct CharacterData
     vec4 foreGroundColor;
     vec2 uv0;
     vec2 uv1;
+
+    // x: カラーのアトラスを使うなら 1
+    // y: アトラスのページ
+    uvec4 glyphParams;
 };
 
 layout(std430, binding = 0) readonly buffer CharacterDataBuffer
@@ -26,6 +32,8 @@ void main()
         );
     gl_Position = vec4(position, 0.0, 1.0);
     v_ForeGroundColor = characterData.foreGroundColor;
+    v_IsColor = characterData.glyphParams.x;
+    v_Page = characterData.glyphParams.y;
 
     // TODO: 条件分岐を消したい
     // TODO: UV の上下反転をちゃんと考えたい
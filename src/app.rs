@@ -1,24 +1,29 @@
 use std::time::{Duration, Instant};
 
+use tokio::runtime::Handle;
 use winit::{
-    event::{ElementState, Event, StartCause, WindowEvent},
+    event::{Event, StartCause, WindowEvent},
     event_loop::{ControlFlow, EventLoopBuilder},
-    platform::modifier_supplement::KeyEventExtModifierSupplement,
+    keyboard::ModifiersState,
 };
 
-use crate::workspace::Workspace;
+use crate::{cli::CommandLineOptions, workspace::Workspace};
 
 pub struct App;
 
 impl App {
-    pub async fn run() {
+    pub async fn run(options: CommandLineOptions) {
         let event_loop = EventLoopBuilder::new().build().unwrap();
 
         // ひとつだけウィンドウを起動しておく
-        let mut workspace = Workspace::new();
+        let hold = options.hold;
+        let mut workspace = Workspace::new(options);
         workspace.spawn_window(&event_loop).await;
 
         let timer_length = Duration::from_millis(10);
+
+        // キーとマウスの入力の変換に使う修飾キーの状態
+        let mut modifiers = ModifiersState::empty();
         event_loop
             .run(move |event, target| match event {
                 Event::NewEvents(StartCause::Init) => {
@@ -28,7 +33,9 @@ impl App {
                     target.set_control_flow(ControlFlow::WaitUntil(Instant::now() + timer_length));
                     workspace.update();
 
-                    if workspace.is_empty() {
+                    // --hold ならウィンドウを閉じるまで待つ
+                    // ペインを閉じてウィンドウがなくなったら終了する
+                    if (workspace.is_empty() && !hold) || !workspace.has_window() {
                         target.exit();
                     }
                 }
@@ -41,15 +48,31 @@ impl App {
                     WindowEvent::RedrawRequested => {
                         workspace.render(window_id);
                     }
+                    WindowEvent::ModifiersChanged(new_modifiers) => {
+                        modifiers = new_modifiers.state();
+                    }
                     WindowEvent::KeyboardInput { event, .. } => {
-                        if event.state != ElementState::Pressed {
-                            return;
-                        }
+                        // キーを離したことも kitty keyboard protocol で使う
+                        workspace.send_key(window_id, &event, modifiers);
 
-                        let Some(text) = event.text_with_all_modifiers() else {
-                            return;
-                        };
-                        workspace.send(window_id, text);
+                        // イベントループの中では待てないのでランタイムをブロックしてウィンドウを作る
+                        if workspace.take_spawn_window_request() {
+                            tokio::task::block_in_place(|| {
+                                Handle::current().block_on(workspace.spawn_window(target))
+                            });
+                        }
+                    }
+                    WindowEvent::Ime(ime) => {
+                        workspace.ime(window_id, ime);
+                    }
+                    WindowEvent::CursorMoved { position, .. } => {
+                        workspace.mouse_moved(window_id, position.x, position.y, modifiers);
+                    }
+                    WindowEvent::MouseInput { state, button, .. } => {
+                        workspace.mouse_input(window_id, state, button, modifiers);
+                    }
+                    WindowEvent::MouseWheel { delta, .. } => {
+                        workspace.mouse_wheel(window_id, delta, modifiers);
                     }
                     WindowEvent::CloseRequested => {
                         target.exit();
@@ -0,0 +1,190 @@
+use std::path::PathBuf;
+
+use crate::config::Shell;
+
+// 起動するときの引数
+// 例: shalacritty --working-directory ~/src -o font.size=14 -e htop
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CommandLineOptions {
+    // シェルの代わりに起動するプログラムとその引数
+    pub command: Vec<String>,
+
+    pub working_directory: Option<PathBuf>,
+
+    // 未指定なら既定の場所の config.toml
+    pub config_file: Option<PathBuf>,
+
+    pub title: Option<String>,
+
+    // X11 と Wayland のウィンドウのクラス名
+    pub class: Option<String>,
+
+    // プログラムが終了してもウィンドウを閉じない
+    pub hold: bool,
+
+    // 設定ファイルより優先する "font.size=14" の形式の設定
+    pub config_overrides: Vec<String>,
+
+    pub is_help: bool,
+
+    pub is_version: bool,
+}
+
+impl CommandLineOptions {
+    pub fn parse<TArgs: IntoIterator<Item = String>>(args: TArgs) -> Result<Self, String> {
+        let mut options = Self::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let mut value = |name: &str| {
+                args.next()
+                    .ok_or_else(|| format!("{} requires a value", name))
+            };
+            match arg.as_str() {
+                // 残りの引数はすべてプログラムに渡す
+                "-e" | "--command" => {
+                    options.command = args.by_ref().collect();
+                    if options.command.is_empty() {
+                        return Err(format!("{} requires a program", arg));
+                    }
+                }
+                "--working-directory" => {
+                    options.working_directory = Some(PathBuf::from(value(&arg)?))
+                }
+                "--config-file" => options.config_file = Some(PathBuf::from(value(&arg)?)),
+                "-T" | "--title" => options.title = Some(value(&arg)?),
+                "--class" => options.class = Some(value(&arg)?),
+                "--hold" => options.hold = true,
+                "-o" | "--option" => {
+                    let option = value(&arg)?;
+                    if !option.contains('=') {
+                        return Err(format!("{} expects key=value: {}", arg, option));
+                    }
+                    options.config_overrides.push(option);
+                }
+                "-h" | "--help" => options.is_help = true,
+                "-V" | "--version" => options.is_version = true,
+                _ => return Err(format!("unknown argument: {}", arg)),
+            }
+        }
+        Ok(options)
+    }
+
+    pub fn usage() -> &'static str {
+        "Usage: shalacritty [OPTIONS] [-e <PROGRAM> [ARGS]...]
+
+Options:
+  -e, --command <PROGRAM> [ARGS]...  Run the program instead of the shell
+      --working-directory <DIR>      Start the shell in the directory
+      --config-file <PATH>           Read the config from the file
+  -T, --title <TITLE>                Set the window title
+      --class <CLASS>                Set the window class on X11 and Wayland
+      --hold                         Keep the window open after the program exits
+  -o, --option <KEY=VALUE>           Override a config value, e.g. font.size=14
+  -h, --help                         Print help
+  -V, --version                      Print version"
+    }
+
+    // 指定ごとに "a.b=1" を [a] b = 1 にして、元の指定と組にする
+    // 設定として読めるかは重ねるときに 1 つずつ確かめる
+    pub fn config_override_tables(&self) -> Vec<(String, toml::Table)> {
+        self.config_overrides
+            .iter()
+            .filter_map(|option| Some((option.clone(), Self::config_override_table(option)?)))
+            .collect()
+    }
+
+    // TOML の値として読めなければ文字列として扱う
+    fn config_override_table(option: &str) -> Option<toml::Table> {
+        let (key, value) = option.split_once('=')?;
+        let mut value = toml::from_str::<toml::Table>(&format!("value = {}", value))
+            .ok()
+            .and_then(|mut table| table.remove("value"))
+            .unwrap_or_else(|| toml::Value::String(value.to_string()));
+
+        // 内側のキーから順にテーブルで包む
+        for key in key.trim().split('.').rev() {
+            value = toml::Value::Table(toml::Table::from_iter([(key.to_string(), value)]));
+        }
+        let toml::Value::Table(table) = value else {
+            unreachable!();
+        };
+        Some(table)
+    }
+
+    // 最初のウィンドウで起動するプログラムと作業ディレクトリを上書きする
+    pub fn apply_to_shell(&self, shell: &mut Shell) {
+        if let Some((program, args)) = self.command.split_first() {
+            shell.program = Some(program.clone());
+            shell.args = args.to_vec();
+        }
+        if let Some(working_directory) = &self.working_directory {
+            shell.working_directory = Some(working_directory.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::CommandLineOptions;
+
+    fn parse(args: &[&str]) -> Result<CommandLineOptions, String> {
+        CommandLineOptions::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn parse_options() {
+        let options = parse(&[
+            "--working-directory",
+            "/tmp",
+            "--title",
+            "logs",
+            "--hold",
+            "-o",
+            "font.size=14",
+            "-e",
+            "tail",
+            "-f",
+            "--title",
+        ])
+        .unwrap();
+        assert_eq!(options.working_directory, Some(PathBuf::from("/tmp")));
+        assert_eq!(options.title.as_deref(), Some("logs"));
+        assert!(options.hold);
+        assert_eq!(options.config_overrides, ["font.size=14"]);
+
+        // -e より後ろはプログラムの引数
+        assert_eq!(options.command, ["tail", "-f", "--title"]);
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(parse(&["--title"]).is_err());
+        assert!(parse(&["-e"]).is_err());
+        assert!(parse(&["-o", "font.size"]).is_err());
+        assert!(parse(&["--unknown"]).is_err());
+    }
+
+    #[test]
+    fn config_override_tables() {
+        let options = parse(&[
+            "-o",
+            "font.size=14",
+            "-o",
+            "font.normal.family=Fira Code",
+            "-o",
+            "shell.args=[\"-l\"]",
+        ])
+        .unwrap();
+        let tables = options.config_override_tables();
+        assert_eq!(tables.len(), 3);
+        assert_eq!(tables[0].0, "font.size=14");
+        assert_eq!(tables[0].1["font"]["size"].as_integer(), Some(14));
+        assert_eq!(
+            tables[1].1["font"]["normal"]["family"].as_str(),
+            Some("Fira Code")
+        );
+        assert_eq!(tables[2].1["shell"]["args"][0].as_str(), Some("-l"));
+    }
+}
@@ -0,0 +1,128 @@
+use super::{MemoryClipboard, SystemClipboard};
+use crate::config::Osc52;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ClipboardType {
+    // Ctrl+C などでコピーする通常のクリップボード
+    Clipboard,
+
+    // 選択しただけで保存されて中クリックで貼り付ける X11 のプライマリセレクション
+    Selection,
+}
+
+// クリップボードの実装
+// テストではメモリー上のものに差し替える
+pub trait ClipboardBackend {
+    fn store(&mut self, ty: ClipboardType, text: String);
+
+    fn load(&mut self, ty: ClipboardType) -> Option<String>;
+}
+
+pub struct ClipboardService {
+    backend: Box<dyn ClipboardBackend>,
+}
+
+impl ClipboardService {
+    // システムのクリップボードが使えなければアプリ内だけで共有する
+    pub fn new() -> Self {
+        match SystemClipboard::new() {
+            Some(system_clipboard) => Self::new_with_backend(Box::new(system_clipboard)),
+            None => Self::new_with_backend(Box::<MemoryClipboard>::default()),
+        }
+    }
+
+    pub fn new_with_backend(backend: Box<dyn ClipboardBackend>) -> Self {
+        Self { backend }
+    }
+
+    pub fn store(&mut self, ty: ClipboardType, text: String) {
+        self.backend.store(ty, text);
+    }
+
+    pub fn load(&mut self, ty: ClipboardType) -> Option<String> {
+        self.backend.load(ty)
+    }
+
+    // アプリから OSC 52 で書き込む。許可していなければ捨てる
+    pub fn store_osc52(&mut self, ty: ClipboardType, text: String, osc52: Osc52) {
+        if osc52.write {
+            self.store(ty, text);
+        }
+    }
+
+    // アプリから OSC 52 で読み出す。許可していなければ None
+    pub fn load_osc52(&mut self, ty: ClipboardType, osc52: Osc52) -> Option<String> {
+        if !osc52.read {
+            return None;
+        }
+        self.load(ty)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ClipboardService, ClipboardType};
+    use crate::{
+        clipboard::{ClipboardOperation, MemoryClipboard, ReplayClipboard},
+        config::Osc52,
+    };
+
+    // 種類ごとに別々に保存される
+    #[test]
+    fn store_and_load() {
+        let mut clipboard = ClipboardService::new_with_backend(Box::<MemoryClipboard>::default());
+        assert!(clipboard.load(ClipboardType::Clipboard).is_none());
+
+        clipboard.store(ClipboardType::Selection, "selected".to_string());
+        clipboard.store(ClipboardType::Clipboard, "copied".to_string());
+        assert_eq!(
+            clipboard.load(ClipboardType::Selection).as_deref(),
+            Some("selected")
+        );
+        assert_eq!(
+            clipboard.load(ClipboardType::Clipboard).as_deref(),
+            Some("copied")
+        );
+    }
+
+    // 許可していない操作はクリップボードまで届かない
+    #[test]
+    fn osc52_policy() {
+        let replay_clipboard = ReplayClipboard::default();
+        let mut clipboard = ClipboardService::new_with_backend(Box::new(replay_clipboard.clone()));
+        let osc52 = Osc52::default();
+        clipboard.store_osc52(ClipboardType::Clipboard, "copied".to_string(), osc52);
+        assert!(clipboard
+            .load_osc52(ClipboardType::Clipboard, osc52)
+            .is_none());
+
+        let deny_write = Osc52 {
+            read: true,
+            write: false,
+        };
+        clipboard.store_osc52(ClipboardType::Selection, "ignored".to_string(), deny_write);
+        assert_eq!(
+            clipboard
+                .load_osc52(ClipboardType::Clipboard, deny_write)
+                .as_deref(),
+            Some("copied")
+        );
+
+        assert_eq!(
+            replay_clipboard.operations(),
+            [
+                ClipboardOperation::Store(ClipboardType::Clipboard, "copied".to_string()),
+                ClipboardOperation::Load(ClipboardType::Clipboard),
+            ]
+        );
+
+        // 記録した操作を再生すると同じ内容になる
+        let mut memory_clipboard = MemoryClipboard::default();
+        replay_clipboard.replay(&mut memory_clipboard);
+        let mut clipboard = ClipboardService::new_with_backend(Box::new(memory_clipboard));
+        assert_eq!(
+            clipboard.load(ClipboardType::Clipboard).as_deref(),
+            Some("copied")
+        );
+    }
+}
@@ -0,0 +1,19 @@
+use std::collections::HashMap;
+
+use super::{ClipboardBackend, ClipboardType};
+
+// アプリ内だけで共有するクリップボード
+#[derive(Debug, Default)]
+pub struct MemoryClipboard {
+    content_table: HashMap<ClipboardType, String>,
+}
+
+impl ClipboardBackend for MemoryClipboard {
+    fn store(&mut self, ty: ClipboardType, text: String) {
+        self.content_table.insert(ty, text);
+    }
+
+    fn load(&mut self, ty: ClipboardType) -> Option<String> {
+        self.content_table.get(&ty).cloned()
+    }
+}
@@ -0,0 +1,11 @@
+mod clipboard_service;
+mod memory_clipboard;
+#[cfg(test)]
+mod replay_clipboard;
+mod system_clipboard;
+
+pub use clipboard_service::{ClipboardBackend, ClipboardService, ClipboardType};
+pub use memory_clipboard::MemoryClipboard;
+#[cfg(test)]
+pub use replay_clipboard::{ClipboardOperation, ReplayClipboard};
+pub use system_clipboard::SystemClipboard;
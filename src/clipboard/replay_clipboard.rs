@@ -0,0 +1,56 @@
+use std::sync::{Arc, Mutex};
+
+use super::{ClipboardBackend, ClipboardType, MemoryClipboard};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClipboardOperation {
+    Store(ClipboardType, String),
+    Load(ClipboardType),
+}
+
+// テスト用に、メモリー上に保存して届いた操作を順に記録するクリップボード
+// 複製しても記録を共有するので、サービスに渡したあとも手元で確かめられる
+#[derive(Debug, Clone, Default)]
+pub struct ReplayClipboard {
+    inner: Arc<Mutex<ReplayClipboardInner>>,
+}
+
+#[derive(Debug, Default)]
+struct ReplayClipboardInner {
+    clipboard: MemoryClipboard,
+    operations: Vec<ClipboardOperation>,
+}
+
+impl ReplayClipboard {
+    pub fn operations(&self) -> Vec<ClipboardOperation> {
+        self.inner.lock().unwrap().operations.clone()
+    }
+
+    // 記録した操作を同じ順に別のクリップボードで実行する
+    pub fn replay(&self, backend: &mut dyn ClipboardBackend) {
+        for operation in self.operations() {
+            match operation {
+                ClipboardOperation::Store(ty, text) => backend.store(ty, text),
+                ClipboardOperation::Load(ty) => {
+                    backend.load(ty);
+                }
+            }
+        }
+    }
+}
+
+impl ClipboardBackend for ReplayClipboard {
+    fn store(&mut self, ty: ClipboardType, text: String) {
+        let mut inner = self.inner.lock().unwrap();
+        inner
+            .operations
+            .push(ClipboardOperation::Store(ty, text.clone()));
+        inner.clipboard.store(ty, text);
+    }
+
+    fn load(&mut self, ty: ClipboardType) -> Option<String> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.operations.push(ClipboardOperation::Load(ty));
+        inner.clipboard.load(ty)
+    }
+}
@@ -0,0 +1,66 @@
+use copypasta::{ClipboardContext, ClipboardProvider};
+
+use super::{ClipboardBackend, ClipboardType, MemoryClipboard};
+
+// copypasta を使った OS のクリップボード
+// プライマリセレクションがない環境ではアプリ内だけで共有する
+pub struct SystemClipboard {
+    clipboard: Box<dyn ClipboardProvider>,
+    selection: Option<Box<dyn ClipboardProvider>>,
+    fallback: MemoryClipboard,
+}
+
+impl SystemClipboard {
+    pub fn new() -> Option<Self> {
+        let clipboard = ClipboardContext::new().ok()?;
+        Some(Self {
+            clipboard: Box::new(clipboard),
+            selection: Self::create_selection(),
+            fallback: MemoryClipboard::default(),
+        })
+    }
+
+    #[cfg(all(
+        unix,
+        not(any(target_os = "macos", target_os = "android", target_os = "ios"))
+    ))]
+    fn create_selection() -> Option<Box<dyn ClipboardProvider>> {
+        use copypasta::x11_clipboard::{Primary, X11ClipboardContext};
+
+        let selection = X11ClipboardContext::<Primary>::new().ok()?;
+        Some(Box::new(selection))
+    }
+
+    #[cfg(not(all(
+        unix,
+        not(any(target_os = "macos", target_os = "android", target_os = "ios"))
+    )))]
+    fn create_selection() -> Option<Box<dyn ClipboardProvider>> {
+        None
+    }
+
+    fn provider(&mut self, ty: ClipboardType) -> Option<&mut Box<dyn ClipboardProvider>> {
+        match ty {
+            ClipboardType::Clipboard => Some(&mut self.clipboard),
+            ClipboardType::Selection => self.selection.as_mut(),
+        }
+    }
+}
+
+impl ClipboardBackend for SystemClipboard {
+    fn store(&mut self, ty: ClipboardType, text: String) {
+        match self.provider(ty) {
+            Some(provider) => {
+                let _ = provider.set_contents(text);
+            }
+            None => self.fallback.store(ty, text),
+        }
+    }
+
+    fn load(&mut self, ty: ClipboardType) -> Option<String> {
+        match self.provider(ty) {
+            Some(provider) => provider.get_contents().ok(),
+            None => self.fallback.load(ty),
+        }
+    }
+}
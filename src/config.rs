@@ -1,5 +1,5 @@
 use std::{
-    io::Read,
+    collections::BTreeMap,
     path::{Path, PathBuf},
     str::FromStr,
     sync::{Arc, LockResult, Mutex, MutexGuard},
@@ -18,6 +18,53 @@ pub struct Config {
 
     #[serde(default)]
     pub background: Background,
+
+    #[serde(default)]
+    pub window: Window,
+
+    #[serde(default)]
+    pub font: Font,
+
+    #[serde(default)]
+    pub colors: Colors,
+
+    #[serde(default)]
+    pub selection: Selection,
+
+    #[serde(default)]
+    pub paste: Paste,
+
+    #[serde(default)]
+    pub osc52: Osc52,
+
+    #[serde(default)]
+    pub scrolling: Scrolling,
+
+    #[serde(default)]
+    pub shell: Shell,
+
+    // ペインごとに shell を上書きする。名前で選ぶ
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
+
+    // 既定の割り当てより優先する
+    #[serde(default)]
+    pub keybindings: Vec<KeyBinding>,
+}
+
+impl Config {
+    // 名前のプロファイルがなければ shell の設定をそのまま使う
+    pub fn shell_for(&self, profile: Option<&str>) -> Shell {
+        match profile.and_then(|name| self.profiles.get(name)) {
+            Some(profile) => self.shell.with_profile(profile),
+            None => {
+                if let Some(name) = profile {
+                    eprintln!("unknown profile: {}", name);
+                }
+                self.shell.clone()
+            }
+        }
+    }
 }
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
@@ -29,10 +76,637 @@ pub struct Background {
     pub path: Vec<String>,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Window {
+    // アプリがタイトルを設定していないときのタイトル
+    #[serde(default = "Window::default_title")]
+    pub title: String,
+
+    // {title} をアプリが設定したタイトル、{cwd} をシェルの作業ディレクトリに置き換える
+    // 例: "{title} — {cwd}"
+    #[serde(default = "Window::default_title_template")]
+    pub title_template: String,
+
+    // 無効ならアプリが設定したタイトルを使わない
+    #[serde(default = "Window::default_dynamic_title")]
+    pub dynamic_title: bool,
+}
+
+impl Window {
+    fn default_title() -> String {
+        "Shalacritty".to_string()
+    }
+
+    fn default_title_template() -> String {
+        "{title}".to_string()
+    }
+
+    fn default_dynamic_title() -> bool {
+        true
+    }
+
+    // ウィンドウに表示するタイトル
+    pub fn format_title(&self, title: Option<&str>, cwd: Option<&Path>) -> String {
+        let title = match title {
+            Some(title) if self.dynamic_title => title,
+            _ => self.title.as_str(),
+        };
+        let cwd = cwd.map(|cwd| cwd.display().to_string()).unwrap_or_default();
+        self.title_template
+            .replace("{title}", title)
+            .replace("{cwd}", &cwd)
+    }
+}
+
+impl Default for Window {
+    fn default() -> Self {
+        Self {
+            title: Self::default_title(),
+            title_template: Self::default_title_template(),
+            dynamic_title: Self::default_dynamic_title(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Font {
+    #[serde(default)]
+    pub normal: FontFace,
+
+    // 未指定なら normal のファミリーを太字で使う
+    #[serde(default)]
+    pub bold: FontFace,
+
+    // 未指定なら normal のファミリーを斜体で使う
+    #[serde(default)]
+    pub italic: FontFace,
+
+    #[serde(default)]
+    pub bold_italic: FontFace,
+
+    // 上記のフォントにない文字を探すファミリー。先頭から順に探す
+    #[serde(default)]
+    pub fallback: Vec<String>,
+
+    // 同じ属性のセルの並びを整形して合字や文脈で形の変わる文字を描く
+    // 整形してもグリフは等幅のセルからはずれない
+    #[serde(default)]
+    pub shaping: bool,
+
+    // ポイント単位
+    #[serde(default = "default_font_size")]
+    pub size: f32,
+
+    // セルの大きさに加えるピクセル数
+    #[serde(default)]
+    pub offset: Delta,
+
+    // セル内でグリフをずらすピクセル数
+    #[serde(default)]
+    pub glyph_offset: Delta,
+}
+
+impl Default for Font {
+    fn default() -> Self {
+        Self {
+            normal: FontFace::default(),
+            bold: FontFace::default(),
+            italic: FontFace::default(),
+            bold_italic: FontFace::default(),
+            fallback: Vec::new(),
+            shaping: false,
+            size: default_font_size(),
+            offset: Delta::default(),
+            glyph_offset: Delta::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FontFace {
+    #[serde(default)]
+    pub family: Option<String>,
+
+    // "Bold" や "Light Italic" などフォントが定義しているスタイル名
+    #[serde(default)]
+    pub style: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Delta {
+    #[serde(default)]
+    pub x: i32,
+
+    #[serde(default)]
+    pub y: i32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Colors {
+    #[serde(default)]
+    pub primary: PrimaryColors,
+
+    // 未指定なら前景色
+    #[serde(default)]
+    pub cursor: Option<Rgb>,
+
+    #[serde(default)]
+    pub normal: AnsiColors,
+
+    #[serde(
+        default = "AnsiColors::bright",
+        deserialize_with = "AnsiColors::deserialize_bright"
+    )]
+    pub bright: AnsiColors,
+
+    // 未指定なら normal を暗くして使う
+    #[serde(default)]
+    pub dim: Option<AnsiColors>,
+
+    #[serde(default)]
+    pub search: SearchColors,
+}
+
+impl Default for Colors {
+    fn default() -> Self {
+        Self {
+            primary: PrimaryColors::default(),
+            cursor: None,
+            normal: AnsiColors::default(),
+            bright: AnsiColors::bright(),
+            dim: None,
+            search: SearchColors::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PrimaryColors {
+    #[serde(default = "PrimaryColors::default_foreground")]
+    pub foreground: Rgb,
+
+    #[serde(default = "PrimaryColors::default_background")]
+    pub background: Rgb,
+
+    // 未指定なら前景色を暗くして使う
+    #[serde(default)]
+    pub dim_foreground: Option<Rgb>,
+
+    // 未指定なら前景色
+    #[serde(default)]
+    pub bright_foreground: Option<Rgb>,
+}
+
+impl PrimaryColors {
+    fn default_foreground() -> Rgb {
+        Rgb::new(0xd8, 0xd8, 0xd8)
+    }
+
+    fn default_background() -> Rgb {
+        Rgb::new(0x18, 0x18, 0x18)
+    }
+}
+
+impl Default for PrimaryColors {
+    fn default() -> Self {
+        Self {
+            foreground: Self::default_foreground(),
+            background: Self::default_background(),
+            dim_foreground: None,
+            bright_foreground: None,
+        }
+    }
+}
+
+// 検索に一致したセルの色。フォーカスしている一致はほかと区別して塗る
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SearchColors {
+    #[serde(default = "SearchColors::default_matches")]
+    pub matches: CellColors,
+
+    #[serde(default = "SearchColors::default_focused_match")]
+    pub focused_match: CellColors,
+}
+
+impl SearchColors {
+    fn default_matches() -> CellColors {
+        CellColors {
+            foreground: Rgb::new(0x18, 0x18, 0x18),
+            background: Rgb::new(0xd8, 0xd8, 0xd8),
+        }
+    }
+
+    fn default_focused_match() -> CellColors {
+        CellColors {
+            foreground: Rgb::new(0x18, 0x18, 0x18),
+            background: Rgb::new(0xf4, 0xbf, 0x75),
+        }
+    }
+}
+
+impl Default for SearchColors {
+    fn default() -> Self {
+        Self {
+            matches: Self::default_matches(),
+            focused_match: Self::default_focused_match(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CellColors {
+    pub foreground: Rgb,
+    pub background: Rgb,
+}
+
+// ANSI の 8 色
+// 省略した色は既定値で埋めるので、一部の色だけ変えられる
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AnsiColors {
+    pub black: Rgb,
+    pub red: Rgb,
+    pub green: Rgb,
+    pub yellow: Rgb,
+    pub blue: Rgb,
+    pub magenta: Rgb,
+    pub cyan: Rgb,
+    pub white: Rgb,
+}
+
+impl AnsiColors {
+    fn bright() -> Self {
+        Self {
+            black: Rgb::new(0x6b, 0x6b, 0x6b),
+            red: Rgb::new(0xc5, 0x55, 0x55),
+            green: Rgb::new(0xaa, 0xc4, 0x74),
+            yellow: Rgb::new(0xfe, 0xca, 0x88),
+            blue: Rgb::new(0x82, 0xb8, 0xc8),
+            magenta: Rgb::new(0xc2, 0x8c, 0xb8),
+            cyan: Rgb::new(0x93, 0xd3, 0xc3),
+            white: Rgb::new(0xf8, 0xf8, 0xf8),
+        }
+    }
+
+    // 明るい 8 色は省略した色を明るい既定値で埋める
+    fn deserialize_bright<'de, D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let table = toml::Table::deserialize(deserializer)?;
+        let mut bright = toml::Table::try_from(Self::bright()).map_err(serde::de::Error::custom)?;
+        merge_table(&mut bright, &table);
+        Self::deserialize(toml::Value::Table(bright)).map_err(serde::de::Error::custom)
+    }
+
+    // black から white の順
+    pub fn to_array(&self) -> [Rgb; 8] {
+        [
+            self.black,
+            self.red,
+            self.green,
+            self.yellow,
+            self.blue,
+            self.magenta,
+            self.cyan,
+            self.white,
+        ]
+    }
+}
+
+impl Default for AnsiColors {
+    fn default() -> Self {
+        Self {
+            black: Rgb::new(0x18, 0x18, 0x18),
+            red: Rgb::new(0xac, 0x42, 0x42),
+            green: Rgb::new(0x90, 0xa9, 0x59),
+            yellow: Rgb::new(0xf4, 0xbf, 0x75),
+            blue: Rgb::new(0x6a, 0x9f, 0xb5),
+            magenta: Rgb::new(0xaa, 0x75, 0x9f),
+            cyan: Rgb::new(0x75, 0xb5, 0xaa),
+            white: Rgb::new(0xd8, 0xd8, 0xd8),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Selection {
+    // ダブルクリックで単語を選択するときに区切りとみなす文字
+    #[serde(default = "Selection::default_semantic_escape_chars")]
+    pub semantic_escape_chars: String,
+}
+
+impl Selection {
+    fn default_semantic_escape_chars() -> String {
+        alacritty_terminal::term::SEMANTIC_ESCAPE_CHARS.to_string()
+    }
+}
+
+impl Default for Selection {
+    fn default() -> Self {
+        Self {
+            semantic_escape_chars: Self::default_semantic_escape_chars(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Paste {
+    // ブラケットペーストが無効なときに、複数行や制御文字を含む文字列を貼り付ける前に確認する
+    #[serde(default = "Paste::default_confirm")]
+    pub confirm: bool,
+
+    // 貼り付ける文字列の改行をそろえる
+    #[serde(default)]
+    pub newline: Newline,
+}
+
+impl Paste {
+    fn default_confirm() -> bool {
+        true
+    }
+}
+
+impl Default for Paste {
+    fn default() -> Self {
+        Self {
+            confirm: Self::default_confirm(),
+            newline: Newline::default(),
+        }
+    }
+}
+
+// アプリが OSC 52 でクリップボードを使うことを許可するか
+// 読み出しは入力した内容を盗み見られるので既定では許可しない
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Osc52 {
+    #[serde(default)]
+    pub read: bool,
+
+    #[serde(default = "Osc52::default_write")]
+    pub write: bool,
+}
+
+impl Osc52 {
+    fn default_write() -> bool {
+        true
+    }
+}
+
+impl Default for Osc52 {
+    fn default() -> Self {
+        Self {
+            read: false,
+            write: Self::default_write(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Scrolling {
+    // スクロールバックに残す行数。0 なら残さない
+    #[serde(default = "Scrolling::default_history")]
+    pub history: usize,
+
+    // ホイールを 1 段回したときにスクロールする行数
+    #[serde(default = "Scrolling::default_multiplier")]
+    pub multiplier: u8,
+}
+
+impl Scrolling {
+    fn default_history() -> usize {
+        10000
+    }
+
+    fn default_multiplier() -> u8 {
+        3
+    }
+}
+
+impl Default for Scrolling {
+    fn default() -> Self {
+        Self {
+            history: Self::default_history(),
+            multiplier: Self::default_multiplier(),
+        }
+    }
+}
+
+// 端末で起動するプログラム
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Shell {
+    // 未指定なら bash、Windows では cmd.exe
+    #[serde(default)]
+    pub program: Option<String>,
+
+    // ログインシェルにするなら ["-l"] など
+    #[serde(default)]
+    pub args: Vec<String>,
+
+    // 起動するプログラムに追加で渡す環境変数
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+
+    // 未指定ならこのプロセスと同じ
+    #[serde(default)]
+    pub working_directory: Option<PathBuf>,
+
+    // 環境変数 TERM の値
+    #[serde(default = "Shell::default_term")]
+    pub term: String,
+}
+
+impl Shell {
+    fn default_term() -> String {
+        "xterm-256color".to_string()
+    }
+
+    // プロファイルで指定した項目だけ上書きする。環境変数は足し合わせる
+    pub fn with_profile(&self, profile: &Profile) -> Self {
+        let mut env = self.env.clone();
+        env.extend(profile.env.clone());
+        Self {
+            program: profile.program.clone().or_else(|| self.program.clone()),
+            args: profile.args.clone().unwrap_or_else(|| self.args.clone()),
+            env,
+            working_directory: profile
+                .working_directory
+                .clone()
+                .or_else(|| self.working_directory.clone()),
+            term: profile.term.clone().unwrap_or_else(|| self.term.clone()),
+        }
+    }
+}
+
+impl Default for Shell {
+    fn default() -> Self {
+        Self {
+            program: None,
+            args: Vec::new(),
+            env: BTreeMap::new(),
+            working_directory: None,
+            term: Self::default_term(),
+        }
+    }
+}
+
+// shell の設定の一部を上書きする
+// 例: [profiles.fish] program = "fish"
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Profile {
+    #[serde(default)]
+    pub program: Option<String>,
+
+    #[serde(default)]
+    pub args: Option<Vec<String>>,
+
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+
+    #[serde(default)]
+    pub working_directory: Option<PathBuf>,
+
+    #[serde(default)]
+    pub term: Option<String>,
+}
+
+// キーと修飾キーの組み合わせに割り当てる操作
+// 例: { key = "C", mods = "Control|Shift", action = "Copy" }
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyBinding {
+    // "A" などの文字か "PageUp" などのキーの名前
+    pub key: String,
+
+    // "Control|Shift" のように | でつなぐ
+    #[serde(default)]
+    pub mods: String,
+
+    // "AltScreen|~AppCursor" のように | でつなぎ、~ をつけたモードでは使わない
+    #[serde(default)]
+    pub mode: String,
+
+    pub action: Action,
+}
+
+impl KeyBinding {
+    pub fn new(key: &str, mods: &str, action: Action) -> Self {
+        Self {
+            key: key.to_string(),
+            mods: mods.to_string(),
+            mode: String::new(),
+            action,
+        }
+    }
+}
+
+// 設定ファイルでは action = "Copy" や action = { SendString = "\u001b[A" } と書く
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Action {
+    Copy,
+    Paste,
+    SpawnWindow,
+    // プロファイルの名前を指定してウィンドウを開く
+    SpawnWindowWithProfile(String),
+    // ペインを左右、上下に分ける
+    SplitHorizontal,
+    SplitVertical,
+    // 次のペインにフォーカスを移す
+    FocusNext,
+    ClosePane,
+    IncreaseFontSize,
+    DecreaseFontSize,
+    ResetFontSize,
+    ScrollPageUp,
+    ScrollPageDown,
+    ScrollToTop,
+    ScrollToBottom,
+    SearchForward,
+    SearchBackward,
+    // 検索中だけ使う
+    ToggleSearchCaseSensitive,
+    ToggleSearchRegex,
+    ToggleFullscreen,
+    SendBytes(Vec<u8>),
+    SendString(String),
+
+    // 何もしない。既定の割り当てを無効にする
+    None,
+}
+
+// 端末では Enter と同じ CR にそろえるのが既定
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Newline {
+    #[default]
+    Cr,
+    Lf,
+    CrLf,
+    // 変換しない
+    Keep,
+}
+
+// 設定ファイルでは "#rrggbb" の形式で書く
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Rgb {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Rgb {
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b }
+    }
+}
+
+impl FromStr for Rgb {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let hex = s
+            .strip_prefix('#')
+            .or_else(|| s.strip_prefix("0x"))
+            .unwrap_or(s);
+        if hex.len() != 6 {
+            return Err(format!("invalid color: {}", s));
+        }
+
+        let value = u32::from_str_radix(hex, 16).map_err(|_| format!("invalid color: {}", s))?;
+        Ok(Self::new(
+            (value >> 16) as u8,
+            (value >> 8) as u8,
+            value as u8,
+        ))
+    }
+}
+
+impl Serialize for Rgb {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(&format!("#{:02x}{:02x}{:02x}", self.r, self.g, self.b))
+    }
+}
+
+impl<'de> Deserialize<'de> for Rgb {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let value = String::deserialize(deserializer)?;
+        Rgb::from_str(&value).map_err(serde::de::Error::custom)
+    }
+}
+
 fn default_image_alpha() -> f32 {
     1.0
 }
 
+fn default_font_size() -> f32 {
+    16.0
+}
+
 pub struct ConfigService {
     // ConfigService は各種オブジェクトに共有することを想定するので Send + Sync
     #[allow(dead_code)]
@@ -44,27 +718,50 @@ pub struct ConfigService {
 
 impl ConfigService {
     pub fn new() -> Self {
-        // コンフィグ置き場。なければ作る。
-        let mut config_path = create_config_directory();
-        let config = if config_path.exists() {
-            config_path.push("config.toml");
-            load_config(&config_path.to_path_buf())
+        Self::new_with(None, Vec::new())
+    }
+
+    // config_file を指定しなければ既定の場所の config.toml を読む
+    // overrides は読み込むたびに設定ファイルの上に順に重ねる
+    pub fn new_with(config_file: Option<&Path>, overrides: Vec<(String, toml::Table)>) -> Self {
+        let config_path = match config_file {
+            Some(config_file) => config_file.to_path_buf(),
+            None => {
+                // コンフィグ置き場。なければ作る。
+                let mut config_path = create_config_directory();
+                if config_path.exists() {
+                    config_path.push("config.toml");
+                } else {
+                    std::fs::DirBuilder::new().create(&config_path).unwrap();
+                }
+                config_path
+            }
+        };
+        // 読めなければ既定の設定で起動する
+        // 上書きは 1 つずつ確かめて重ねるので、空の設定は必ず読める
+        let config = if config_path.is_file() {
+            load_config(&config_path, &overrides).unwrap_or_else(|error| {
+                eprintln!("failed to load {}: {}", config_path.display(), error);
+                parse_config("", &overrides).unwrap()
+            })
         } else {
-            std::fs::DirBuilder::new().create(&config_path).unwrap();
-            Config::default()
+            parse_config("", &overrides).unwrap()
         };
 
         let config = Arc::new(Mutex::new(config));
         let mut watcher = notify::RecommendedWatcher::new(
             EventHandler {
                 config: config.clone(),
+                overrides,
             },
             notify::Config::default(),
         )
         .unwrap();
-        watcher
-            .watch(&config_path, notify::RecursiveMode::Recursive)
-            .unwrap();
+        if config_path.exists() {
+            watcher
+                .watch(&config_path, notify::RecursiveMode::Recursive)
+                .unwrap();
+        }
 
         Self {
             watcher: Arc::new(watcher),
@@ -93,6 +790,9 @@ impl Drop for ConfigService {
 
 struct EventHandler {
     config: Arc<Mutex<Config>>,
+
+    // コマンドラインで指定された設定とその元の指定
+    overrides: Vec<(String, toml::Table)>,
 }
 
 impl notify::EventHandler for EventHandler {
@@ -111,8 +811,7 @@ impl notify::EventHandler for EventHandler {
 
                 // 定義ファイルが作成されたので読み込む
                 for path in &e.paths {
-                    println!("{:?}: {:?}", e.kind, path);
-                    let _file = std::fs::File::open(path).unwrap();
+                    self.reload(path);
                 }
             }
             notify::EventKind::Modify(kind) => {
@@ -122,7 +821,7 @@ impl notify::EventHandler for EventHandler {
 
                 // 定義ファイルが更新されたので読み込む
                 for path in &e.paths {
-                    *self.config.lock().unwrap() = load_config(path);
+                    self.reload(path);
                 }
             }
             // notify::EventKind::Remove(_) => todo!(),
@@ -132,20 +831,58 @@ impl notify::EventHandler for EventHandler {
     }
 }
 
-fn load_config(path: &Path) -> Config {
-    let mut file = std::fs::File::open(path).unwrap();
-    let mut str: String = String::new();
-    file.read_to_string(&mut str).ok().unwrap();
-    let mut config: Config = toml::from_str(&str).unwrap();
+impl EventHandler {
+    // 読めない設定に書き換えられたら、直されるまで今の設定を使い続ける
+    fn reload(&self, path: &Path) {
+        match load_config(path, &self.overrides) {
+            Ok(config) => *self.config.lock().unwrap() = config,
+            Err(error) => eprintln!("failed to load {}: {}", path.display(), error),
+        }
+    }
+}
+
+fn load_config(
+    path: &Path,
+    overrides: &[(String, toml::Table)],
+) -> Result<Config, Box<dyn std::error::Error>> {
+    let str = std::fs::read_to_string(path)?;
+    let mut config = parse_config(&str, overrides)?;
 
     // 画像パスは設定ファイルからの相対パスにする
     let mut image_path = path.to_path_buf();
     image_path.pop();
     image_path.push(config.image);
-    config.image = image_path.to_str().unwrap().to_string();
-    println!("{}", config.image);
+    config.image = image_path.to_string_lossy().into_owned();
+
+    Ok(config)
+}
+
+// 上書きは 1 つずつ重ねて、設定として読めないものだけ捨てる
+fn parse_config(str: &str, overrides: &[(String, toml::Table)]) -> Result<Config, toml::de::Error> {
+    let mut table: toml::Table = toml::from_str(str)?;
+    for (option, override_table) in overrides {
+        let mut merged_table = table.clone();
+        merge_table(&mut merged_table, override_table);
+        match Config::deserialize(toml::Value::Table(merged_table.clone())) {
+            Ok(_) => table = merged_table,
+            Err(error) => eprintln!("invalid option {}: {}", option, error),
+        }
+    }
+    Config::deserialize(toml::Value::Table(table))
+}
 
-    config
+// テーブルは再帰的に重ね、それ以外の値は置き換える
+fn merge_table(table: &mut toml::Table, overrides: &toml::Table) {
+    for (key, value) in overrides {
+        match (table.get_mut(key), value) {
+            (Some(toml::Value::Table(table)), toml::Value::Table(overrides)) => {
+                merge_table(table, overrides)
+            }
+            _ => {
+                table.insert(key.clone(), value.clone());
+            }
+        }
+    }
 }
 
 fn create_config_directory() -> PathBuf {
@@ -164,3 +901,58 @@ fn create_config_directory() -> PathBuf {
 
     config_directory_path
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_config, AnsiColors, Rgb};
+
+    // 一部の色だけ書いた表は残りを既定値で埋める
+    #[test]
+    fn partial_ansi_colors() {
+        let config = parse_config(
+            r##"
+            [colors.normal]
+            red = "#ff0000"
+
+            [colors.bright]
+            blue = "#0000ff"
+            "##,
+            &[],
+        )
+        .unwrap();
+        assert_eq!(config.colors.normal.red, Rgb::new(0xff, 0, 0));
+        assert_eq!(config.colors.normal.black, AnsiColors::default().black);
+        assert_eq!(config.colors.bright.blue, Rgb::new(0, 0, 0xff));
+        assert_eq!(config.colors.bright.black, AnsiColors::bright().black);
+    }
+
+    // 読めない上書きだけ捨てて、ほかの上書きは残す
+    #[test]
+    fn invalid_override() {
+        let override_table = |option: &str, toml: &str| {
+            (
+                option.to_string(),
+                toml::from_str::<toml::Table>(toml).unwrap(),
+            )
+        };
+        let config = parse_config(
+            "",
+            &[
+                override_table("font.size=big", "font = { size = \"big\" }"),
+                override_table("font.size=14", "font = { size = 14 }"),
+                override_table("scrolling=1", "scrolling = 1"),
+                override_table("window.title=logs", "window = { title = \"logs\" }"),
+            ],
+        )
+        .unwrap();
+        assert_eq!(config.font.size, 14.0);
+        assert_eq!(config.window.title, "logs");
+    }
+
+    // 読めない設定はエラーにして、呼び出し側が今の設定を使い続けられるようにする
+    #[test]
+    fn invalid_config() {
+        assert!(parse_config("[font", &[]).is_err());
+        assert!(parse_config("font = { size = \"big\" }", &[]).is_err());
+    }
+}
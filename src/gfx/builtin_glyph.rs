@@ -0,0 +1,579 @@
+use crossfont::{BitmapBuffer, RasterizedGlyph};
+
+use super::CellMetrics;
+
+// フォントを使わずにセルの大きさぴったりに描く文字
+// 罫線、ブロック要素、点字、Powerline の区切り
+pub fn is_builtin(code: char) -> bool {
+    matches!(
+        code,
+        '\u{2500}'..='\u{259F}' | '\u{2800}'..='\u{28FF}' | '\u{E0B0}'..='\u{E0BF}'
+    )
+}
+
+// フォントのグリフは隣のセルとすき間ができたりずれたりするので自前で描く
+// セルと同じ大きさの画像を作るので隣り合う線は必ずつながる
+pub fn rasterize(code: char, cell_metrics: &CellMetrics) -> Option<RasterizedGlyph> {
+    if !is_builtin(code) {
+        return None;
+    }
+
+    let mut canvas = Canvas::new(
+        cell_metrics.cell_width() as i32,
+        cell_metrics.cell_height() as i32,
+    );
+    let light = (cell_metrics.underline_thickness().round() as i32).max(1);
+    match code {
+        '\u{2500}'..='\u{257F}' => draw_box(&mut canvas, code, light),
+        '\u{2580}'..='\u{259F}' => draw_block(&mut canvas, code),
+        '\u{2800}'..='\u{28FF}' => draw_braille(&mut canvas, code),
+        _ => draw_powerline(&mut canvas, code, light as f32),
+    }
+
+    let (left, top) = cell_metrics.cell_aligned_placement();
+    Some(canvas.into_glyph(code, left, top))
+}
+
+// 1 バイトの濃さを持つセル大の画像
+struct Canvas {
+    width: i32,
+    height: i32,
+    buffer: Vec<u8>,
+}
+
+impl Canvas {
+    fn new(width: i32, height: i32) -> Self {
+        Self {
+            width,
+            height,
+            buffer: vec![0; (width * height) as usize],
+        }
+    }
+
+    // 矩形を塗る。はみ出した分は捨てる
+    fn fill_rect(&mut self, x0: i32, y0: i32, x1: i32, y1: i32, alpha: u8) {
+        for y in y0.max(0)..y1.min(self.height) {
+            for x in x0.max(0)..x1.min(self.width) {
+                let pixel = &mut self.buffer[(x + y * self.width) as usize];
+                *pixel = (*pixel).max(alpha);
+            }
+        }
+    }
+
+    // 転置した座標で矩形を塗る。縦横の処理を共通にするのに使う
+    fn fill_rect_transposed(&mut self, transposed: bool, a0: i32, b0: i32, a1: i32, b1: i32) {
+        if transposed {
+            self.fill_rect(b0, a0, b1, a1, 255);
+        } else {
+            self.fill_rect(a0, b0, a1, b1, 255);
+        }
+    }
+
+    // 曲線や斜めの図形は 4x4 のサンプルで縁をなめらかにする
+    fn fill_with(&mut self, inside: impl Fn(f32, f32) -> bool) {
+        const SAMPLES: i32 = 4;
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let mut count = 0;
+                for sy in 0..SAMPLES {
+                    for sx in 0..SAMPLES {
+                        let px = x as f32 + (sx as f32 + 0.5) / SAMPLES as f32;
+                        let py = y as f32 + (sy as f32 + 0.5) / SAMPLES as f32;
+                        if inside(px, py) {
+                            count += 1;
+                        }
+                    }
+                }
+
+                let alpha = (255 * count / (SAMPLES * SAMPLES)) as u8;
+                let pixel = &mut self.buffer[(x + y * self.width) as usize];
+                *pixel = (*pixel).max(alpha);
+            }
+        }
+    }
+
+    fn into_glyph(self, code: char, left: i32, top: i32) -> RasterizedGlyph {
+        let buffer = self.buffer.iter().flat_map(|alpha| [*alpha; 3]).collect();
+        RasterizedGlyph {
+            character: code,
+            width: self.width,
+            height: self.height,
+            top,
+            left,
+            advance: (self.width, 0),
+            buffer: BitmapBuffer::Rgb(buffer),
+        }
+    }
+}
+
+// 罫線の腕の種類。細線は 1
+const NONE: u8 = 0;
+const HEAVY: u8 = 2;
+const DOUBLE: u8 = 3;
+
+// U+2500 からの罫線の腕。上、右、下、左の順
+// 点線、円弧、斜線は別に描くので 0 にしておく
+#[rustfmt::skip]
+const BOX_ARMS: [[u8; 4]; 128] = [
+    // ─ ━ │ ┃
+    [0, 1, 0, 1], [0, 2, 0, 2], [1, 0, 1, 0], [2, 0, 2, 0],
+    // ┄ ┅ ┆ ┇ ┈ ┉ ┊ ┋
+    [0; 4], [0; 4], [0; 4], [0; 4], [0; 4], [0; 4], [0; 4], [0; 4],
+    // ┌ ┍ ┎ ┏
+    [0, 1, 1, 0], [0, 2, 1, 0], [0, 1, 2, 0], [0, 2, 2, 0],
+    // ┐ ┑ ┒ ┓
+    [0, 0, 1, 1], [0, 0, 1, 2], [0, 0, 2, 1], [0, 0, 2, 2],
+    // └ ┕ ┖ ┗
+    [1, 1, 0, 0], [1, 2, 0, 0], [2, 1, 0, 0], [2, 2, 0, 0],
+    // ┘ ┙ ┚ ┛
+    [1, 0, 0, 1], [1, 0, 0, 2], [2, 0, 0, 1], [2, 0, 0, 2],
+    // ├ ┝ ┞ ┟
+    [1, 1, 1, 0], [1, 2, 1, 0], [2, 1, 1, 0], [1, 1, 2, 0],
+    // ┠ ┡ ┢ ┣
+    [2, 1, 2, 0], [2, 2, 1, 0], [1, 2, 2, 0], [2, 2, 2, 0],
+    // ┤ ┥ ┦ ┧
+    [1, 0, 1, 1], [1, 0, 1, 2], [2, 0, 1, 1], [1, 0, 2, 1],
+    // ┨ ┩ ┪ ┫
+    [2, 0, 2, 1], [2, 0, 1, 2], [1, 0, 2, 2], [2, 0, 2, 2],
+    // ┬ ┭ ┮ ┯
+    [0, 1, 1, 1], [0, 1, 1, 2], [0, 2, 1, 1], [0, 2, 1, 2],
+    // ┰ ┱ ┲ ┳
+    [0, 1, 2, 1], [0, 1, 2, 2], [0, 2, 2, 1], [0, 2, 2, 2],
+    // ┴ ┵ ┶ ┷
+    [1, 1, 0, 1], [1, 1, 0, 2], [1, 2, 0, 1], [1, 2, 0, 2],
+    // ┸ ┹ ┺ ┻
+    [2, 1, 0, 1], [2, 1, 0, 2], [2, 2, 0, 1], [2, 2, 0, 2],
+    // ┼ ┽ ┾ ┿
+    [1, 1, 1, 1], [1, 1, 1, 2], [1, 2, 1, 1], [1, 2, 1, 2],
+    // ╀ ╁ ╂ ╃
+    [2, 1, 1, 1], [1, 1, 2, 1], [2, 1, 2, 1], [2, 1, 1, 2],
+    // ╄ ╅ ╆ ╇
+    [2, 2, 1, 1], [1, 1, 2, 2], [1, 2, 2, 1], [2, 2, 1, 2],
+    // ╈ ╉ ╊ ╋
+    [1, 2, 2, 2], [2, 1, 2, 2], [2, 2, 2, 1], [2, 2, 2, 2],
+    // ╌ ╍ ╎ ╏
+    [0; 4], [0; 4], [0; 4], [0; 4],
+    // ═ ║ ╒ ╓
+    [0, 3, 0, 3], [3, 0, 3, 0], [0, 3, 1, 0], [0, 1, 3, 0],
+    // ╔ ╕ ╖ ╗
+    [0, 3, 3, 0], [0, 0, 1, 3], [0, 0, 3, 1], [0, 0, 3, 3],
+    // ╘ ╙ ╚ ╛
+    [1, 3, 0, 0], [3, 1, 0, 0], [3, 3, 0, 0], [1, 0, 0, 3],
+    // ╜ ╝ ╞ ╟
+    [3, 0, 0, 1], [3, 0, 0, 3], [1, 3, 1, 0], [3, 1, 3, 0],
+    // ╠ ╡ ╢ ╣
+    [3, 3, 3, 0], [1, 0, 1, 3], [3, 0, 3, 1], [3, 0, 3, 3],
+    // ╤ ╥ ╦ ╧
+    [0, 3, 1, 3], [0, 1, 3, 1], [0, 3, 3, 3], [1, 3, 0, 3],
+    // ╨ ╩ ╪ ╫
+    [3, 1, 0, 1], [3, 3, 0, 3], [1, 3, 1, 3], [3, 1, 3, 1],
+    // ╬ ╭ ╮ ╯
+    [3, 3, 3, 3], [0; 4], [0; 4], [0; 4],
+    // ╰ ╱ ╲ ╳
+    [0; 4], [0; 4], [0; 4], [0; 4],
+    // ╴ ╵ ╶ ╷
+    [0, 0, 0, 1], [1, 0, 0, 0], [0, 1, 0, 0], [0, 0, 1, 0],
+    // ╸ ╹ ╺ ╻
+    [0, 0, 0, 2], [2, 0, 0, 0], [0, 2, 0, 0], [0, 0, 2, 0],
+    // ╼ ╽ ╾ ╿
+    [0, 2, 0, 1], [1, 0, 2, 0], [0, 1, 0, 2], [2, 0, 1, 0],
+];
+
+fn draw_box(canvas: &mut Canvas, code: char, light: i32) {
+    let heavy = 2 * light;
+    match code {
+        // 点線は 1 セルを等分して描く
+        '\u{2504}'..='\u{250B}' | '\u{254C}'..='\u{254F}' => {
+            let (count, offset) = match code {
+                '\u{2504}'..='\u{2507}' => (3, 0x2504),
+                '\u{2508}'..='\u{250B}' => (4, 0x2508),
+                _ => (2, 0x254C),
+            };
+            // 細線と太線が交互に並んでいる
+            let index = code as u32 - offset;
+            let thickness = [light, heavy][index as usize % 2];
+            draw_dashes(canvas, index >= 2, count, thickness);
+        }
+        '\u{256D}'..='\u{2570}' => draw_arc(canvas, code, light),
+        '\u{2571}'..='\u{2573}' => draw_diagonal(canvas, code, light),
+        _ => {
+            let [up, right, down, left] = BOX_ARMS[code as usize - 0x2500];
+            draw_arms(canvas, false, [left, right], [up, down], light);
+            draw_arms(canvas, true, [up, down], [left, right], light);
+        }
+    }
+}
+
+// 一方向の 2 本の腕を描く
+// 横の腕なら along は左右、across は上下の腕。縦の腕は座標を転置して同じ処理で描く
+fn draw_arms(canvas: &mut Canvas, transposed: bool, along: [u8; 2], across: [u8; 2], light: i32) {
+    let (length, breadth) = if transposed {
+        (canvas.height, canvas.width)
+    } else {
+        (canvas.width, canvas.height)
+    };
+    let thickness = |weight: u8| if weight == HEAVY { 2 * light } else { light };
+
+    // 交わる線の位置。二重線なら 2 本の外側から外側まで
+    let cross_double = across.contains(&DOUBLE);
+    let cross_thickness = across
+        .iter()
+        .filter(|weight| **weight != NONE)
+        .map(|weight| thickness(*weight))
+        .max();
+    let cross_begin = (length - 3 * light) / 2;
+
+    for (side, weight) in along.into_iter().enumerate() {
+        if weight == NONE {
+            continue;
+        }
+
+        // 腕の線ごとに (幅方向の位置, 太さ, 交点側の端) を決める
+        let mut strokes = Vec::new();
+        if weight == DOUBLE {
+            let begin = (breadth - 3 * light) / 2;
+            for (index, offset) in [0, 2 * light].into_iter().enumerate() {
+                // 二重線どうしの角は内側の線を内側で、外側の線を外側で止める
+                let (end, start) = match cross_thickness {
+                    Some(_) if cross_double && across[index] == DOUBLE => {
+                        (cross_begin + light, cross_begin + 2 * light)
+                    }
+                    Some(cross) if !cross_double => {
+                        let cross_begin = (length - cross) / 2;
+                        (cross_begin + cross, cross_begin)
+                    }
+                    _ => (cross_begin + 3 * light, cross_begin),
+                };
+                strokes.push((begin + offset, light, end, start));
+            }
+        } else {
+            // 一本線は交わる線を覆うところまで伸ばして角を埋める
+            let own = thickness(weight);
+            let (end, start) = match cross_thickness {
+                Some(_) if cross_double => (cross_begin + 3 * light, cross_begin),
+                Some(cross) => {
+                    let cross_begin = (length - cross) / 2;
+                    (cross_begin + cross, cross_begin)
+                }
+                None => {
+                    let own_begin = (length - own) / 2;
+                    (own_begin + own, own_begin)
+                }
+            };
+            strokes.push(((breadth - own) / 2, own, end, start));
+        }
+
+        for (position, stroke_thickness, end, start) in strokes {
+            let (a0, a1) = if side == 0 { (0, end) } else { (start, length) };
+            canvas.fill_rect_transposed(transposed, a0, position, a1, position + stroke_thickness);
+        }
+    }
+}
+
+// 点線。セルの境目で途切れ方がそろうように区間の両端に半分ずつすき間を空ける
+fn draw_dashes(canvas: &mut Canvas, vertical: bool, count: i32, thickness: i32) {
+    let (length, breadth) = if vertical {
+        (canvas.height, canvas.width)
+    } else {
+        (canvas.width, canvas.height)
+    };
+    let segment = length as f32 / count as f32;
+    let gap = (segment / 4.0).max(1.0);
+    let position = (breadth - thickness) / 2;
+    for index in 0..count {
+        let begin = (index as f32 * segment + gap / 2.0).round() as i32;
+        let end = ((index + 1) as f32 * segment - gap / 2.0).round() as i32;
+        canvas.fill_rect_transposed(vertical, begin, position, end, position + thickness);
+    }
+}
+
+// 角の丸い罫線。直線の罫線と同じ位置から出て四分円でつなぐ
+fn draw_arc(canvas: &mut Canvas, code: char, light: i32) {
+    let (width, height) = (canvas.width, canvas.height);
+    let begin_x = (width - light) / 2;
+    let begin_y = (height - light) / 2;
+    let center_x = begin_x as f32 + light as f32 / 2.0;
+    let center_y = begin_y as f32 + light as f32 / 2.0;
+
+    // 向きは右か左か、下か上か
+    let (sign_x, sign_y) = match code {
+        '\u{256D}' => (1.0, 1.0),
+        '\u{256E}' => (-1.0, 1.0),
+        '\u{256F}' => (-1.0, -1.0),
+        _ => (1.0, -1.0),
+    };
+    let radius = center_x
+        .min(width as f32 - center_x)
+        .min(center_y)
+        .min(height as f32 - center_y);
+    let arc_x = center_x + sign_x * radius;
+    let arc_y = center_y + sign_y * radius;
+    let half = light as f32 / 2.0;
+    canvas.fill_with(|x, y| {
+        let in_quadrant = (x - arc_x) * sign_x <= 0.0 && (y - arc_y) * sign_y <= 0.0;
+        let distance = ((x - arc_x).powi(2) + (y - arc_y).powi(2)).sqrt();
+        in_quadrant && (distance - radius).abs() <= half
+    });
+
+    // 円弧の端からセルの端までの直線
+    let arc_x = arc_x.round() as i32;
+    let arc_y = arc_y.round() as i32;
+    let (x0, x1) = if sign_x > 0.0 {
+        (arc_x, width)
+    } else {
+        (0, arc_x)
+    };
+    canvas.fill_rect(x0, begin_y, x1, begin_y + light, 255);
+    let (y0, y1) = if sign_y > 0.0 {
+        (arc_y, height)
+    } else {
+        (0, arc_y)
+    };
+    canvas.fill_rect(begin_x, y0, begin_x + light, y1, 255);
+}
+
+// 斜線はセルの角から角へ引くので斜めに隣のセルとつながる
+fn draw_diagonal(canvas: &mut Canvas, code: char, light: i32) {
+    let (width, height) = (canvas.width as f32, canvas.height as f32);
+    let half = light as f32 / 2.0;
+    let rising = matches!(code, '\u{2571}' | '\u{2573}');
+    let falling = matches!(code, '\u{2572}' | '\u{2573}');
+    canvas.fill_with(|x, y| {
+        (rising && line_distance(x, y, (0.0, height), (width, 0.0)) <= half)
+            || (falling && line_distance(x, y, (0.0, 0.0), (width, height)) <= half)
+    });
+}
+
+fn draw_block(canvas: &mut Canvas, code: char) {
+    let (width, height) = (canvas.width, canvas.height);
+    let eighth_x = |count: i32| (width as f32 * count as f32 / 8.0).round() as i32;
+    let eighth_y = |count: i32| (height as f32 * count as f32 / 8.0).round() as i32;
+    let (half_x, half_y) = (eighth_x(4), eighth_y(4));
+    match code {
+        // ▀
+        '\u{2580}' => canvas.fill_rect(0, 0, width, half_y, 255),
+        // ▁ から █ まで下から 1/8 ずつ
+        '\u{2581}'..='\u{2588}' => {
+            let count = code as i32 - 0x2580;
+            canvas.fill_rect(0, height - eighth_y(count), width, height, 255);
+        }
+        // ▉ から ▏ まで左から 1/8 ずつ減る
+        '\u{2589}'..='\u{258F}' => {
+            let count = 0x2590 - code as i32;
+            canvas.fill_rect(0, 0, eighth_x(count), height, 255);
+        }
+        // ▐
+        '\u{2590}' => canvas.fill_rect(half_x, 0, width, height, 255),
+        // ░ ▒ ▓ は一様な濃さで塗るので隣のセルと模様がずれない
+        '\u{2591}' => canvas.fill_rect(0, 0, width, height, 64),
+        '\u{2592}' => canvas.fill_rect(0, 0, width, height, 128),
+        '\u{2593}' => canvas.fill_rect(0, 0, width, height, 192),
+        // ▔
+        '\u{2594}' => canvas.fill_rect(0, 0, width, eighth_y(1), 255),
+        // ▕
+        '\u{2595}' => canvas.fill_rect(width - eighth_x(1), 0, width, height, 255),
+        // ▖ から ▟ までの四分割。左上、右上、左下、右下
+        _ => {
+            let quadrants: [bool; 4] = match code {
+                '\u{2596}' => [false, false, true, false],
+                '\u{2597}' => [false, false, false, true],
+                '\u{2598}' => [true, false, false, false],
+                '\u{2599}' => [true, false, true, true],
+                '\u{259A}' => [true, false, false, true],
+                '\u{259B}' => [true, true, true, false],
+                '\u{259C}' => [true, true, false, true],
+                '\u{259D}' => [false, true, false, false],
+                '\u{259E}' => [false, true, true, false],
+                _ => [false, true, true, true],
+            };
+            let rects = [
+                (0, 0, half_x, half_y),
+                (half_x, 0, width, half_y),
+                (0, half_y, half_x, height),
+                (half_x, half_y, width, height),
+            ];
+            for ((x0, y0, x1, y1), fill) in rects.into_iter().zip(quadrants) {
+                if fill {
+                    canvas.fill_rect(x0, y0, x1, y1, 255);
+                }
+            }
+        }
+    }
+}
+
+// 点字は 2 列 4 行の点
+fn draw_braille(canvas: &mut Canvas, code: char) {
+    let (width, height) = (canvas.width, canvas.height);
+    let dots = code as u32 - 0x2800;
+
+    // ビットの順番は左列の上 3 つ、右列の上 3 つ、左右の最下段
+    const POSITIONS: [(i32, i32); 8] = [
+        (0, 0),
+        (0, 1),
+        (0, 2),
+        (1, 0),
+        (1, 1),
+        (1, 2),
+        (0, 3),
+        (1, 3),
+    ];
+    let size = ((width / 2).min(height / 4) / 2).max(1);
+    for (bit, (column, row)) in POSITIONS.into_iter().enumerate() {
+        if dots & (1 << bit) == 0 {
+            continue;
+        }
+
+        // 区画の中央に置く
+        let x = column * width / 2 + (width / 2 - size) / 2;
+        let y = row * height / 4 + (height / 4 - size) / 2;
+        canvas.fill_rect(x, y, x + size, y + size, 255);
+    }
+}
+
+fn draw_powerline(canvas: &mut Canvas, code: char, light: f32) {
+    let (width, height) = (canvas.width as f32, canvas.height as f32);
+    let half = light / 2.0;
+    let middle = height / 2.0;
+    let diagonal = width.hypot(height);
+
+    // 左右反転の組は x を反転して同じ図形を描く
+    let mirrored = matches!(code, '\u{E0B2}' | '\u{E0B3}' | '\u{E0B6}' | '\u{E0B7}');
+    let flip = |x: f32| if mirrored { width - x } else { x };
+    match code {
+        // 塗りつぶした三角
+        '\u{E0B0}' | '\u{E0B2}' => {
+            canvas.fill_with(|x, y| flip(x) * middle <= width * (middle - (y - middle).abs()))
+        }
+        // 細い山形
+        '\u{E0B1}' | '\u{E0B3}' => canvas.fill_with(|x, y| {
+            let x = flip(x);
+            segment_distance(x, y, (0.0, 0.0), (width, middle)) <= half
+                || segment_distance(x, y, (width, middle), (0.0, height)) <= half
+        }),
+        // 塗りつぶした半円
+        '\u{E0B4}' | '\u{E0B6}' => canvas
+            .fill_with(|x, y| (flip(x) / width).powi(2) + ((y - middle) / middle).powi(2) <= 1.0),
+        // 細い半円
+        '\u{E0B5}' | '\u{E0B7}' => canvas.fill_with(|x, y| {
+            let distance = ((flip(x) / width).powi(2) + ((y - middle) / middle).powi(2)).sqrt();
+            distance <= 1.0 && (1.0 - distance) * width.min(middle) <= light
+        }),
+        // 左下、右下、左上、右上の直角三角
+        '\u{E0B8}' => canvas.fill_with(|x, y| y * width >= x * height),
+        '\u{E0BA}' => canvas.fill_with(|x, y| y * width >= (width - x) * height),
+        '\u{E0BC}' => canvas.fill_with(|x, y| y * width <= (width - x) * height),
+        '\u{E0BE}' => canvas.fill_with(|x, y| y * width <= x * height),
+        // 左上から右下への斜線
+        '\u{E0B9}' | '\u{E0BF}' => {
+            canvas.fill_with(|x, y| (y * width - x * height).abs() / diagonal <= half)
+        }
+        // 左下から右上への斜線
+        _ => canvas.fill_with(|x, y| (y * width - (width - x) * height).abs() / diagonal <= half),
+    }
+}
+
+// 2 点を通る直線までの距離
+fn line_distance(x: f32, y: f32, a: (f32, f32), b: (f32, f32)) -> f32 {
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    ((x - a.0) * dy - (y - a.1) * dx).abs() / dx.hypot(dy)
+}
+
+// 線分までの距離
+fn segment_distance(x: f32, y: f32, a: (f32, f32), b: (f32, f32)) -> f32 {
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let t = (((x - a.0) * dx + (y - a.1) * dy) / (dx * dx + dy * dy)).clamp(0.0, 1.0);
+    (x - (a.0 + t * dx)).hypot(y - (a.1 + t * dy))
+}
+
+#[cfg(test)]
+mod tests {
+    use crossfont::{BitmapBuffer, RasterizedGlyph};
+
+    use crate::{config::Delta, gfx::CellMetrics};
+
+    use super::rasterize;
+
+    fn cell_metrics() -> CellMetrics {
+        let metrics = crossfont::Metrics {
+            average_advance: 8.0,
+            line_height: 16.0,
+            descent: -4.0,
+            underline_position: -2.0,
+            underline_thickness: 1.0,
+            strikeout_position: 4.0,
+            strikeout_thickness: 1.0,
+        };
+        CellMetrics::new(&metrics, Delta::default(), Delta::default())
+    }
+
+    fn pixel(glyph: &RasterizedGlyph, x: i32, y: i32) -> u8 {
+        let BitmapBuffer::Rgb(buffer) = &glyph.buffer else {
+            unreachable!();
+        };
+        buffer[(3 * (x + y * glyph.width)) as usize]
+    }
+
+    // セルと同じ大きさでセルの左上に置かれる
+    #[test]
+    fn cell_sized() {
+        let cell_metrics = cell_metrics();
+        let glyph = rasterize('─', &cell_metrics).unwrap();
+        assert_eq!((glyph.width, glyph.height), (8, 16));
+        assert_eq!(cell_metrics.glyph_position(glyph.left, glyph.top), (0, 0));
+        assert!(rasterize('a', &cell_metrics).is_none());
+    }
+
+    // 線はセルの端から端まで引かれるので隣のセルとつながる
+    #[test]
+    fn lines_reach_edges() {
+        let cell_metrics = cell_metrics();
+        let horizontal = rasterize('─', &cell_metrics).unwrap();
+        assert!((0..8).all(|x| pixel(&horizontal, x, 7) == 255));
+        let vertical = rasterize('│', &cell_metrics).unwrap();
+        assert!((0..16).all(|y| pixel(&vertical, 3, y) == 255));
+
+        // 角は交点より先に伸びない
+        let corner = rasterize('┌', &cell_metrics).unwrap();
+        assert_eq!(pixel(&corner, 0, 7), 0);
+        assert_eq!(pixel(&corner, 3, 0), 0);
+        assert_eq!(pixel(&corner, 7, 7), 255);
+        assert_eq!(pixel(&corner, 3, 15), 255);
+        assert_eq!(pixel(&corner, 3, 7), 255);
+    }
+
+    // 二重線の角は内側と外側の線がそれぞれつながる
+    #[test]
+    fn double_corner() {
+        let glyph = rasterize('╔', &cell_metrics()).unwrap();
+        // 外側の線は (2, 6) で曲がり、内側の線は (4, 8) で曲がる
+        assert_eq!(pixel(&glyph, 2, 6), 255);
+        assert_eq!(pixel(&glyph, 7, 6), 255);
+        assert_eq!(pixel(&glyph, 2, 15), 255);
+        assert_eq!(pixel(&glyph, 4, 8), 255);
+        assert_eq!(pixel(&glyph, 3, 8), 0);
+        assert_eq!(pixel(&glyph, 4, 7), 0);
+    }
+
+    #[test]
+    fn blocks_and_braille() {
+        let cell_metrics = cell_metrics();
+        let upper_half = rasterize('▀', &cell_metrics).unwrap();
+        assert_eq!(pixel(&upper_half, 0, 7), 255);
+        assert_eq!(pixel(&upper_half, 0, 8), 0);
+
+        let braille = rasterize('⠁', &cell_metrics).unwrap();
+        assert_eq!(pixel(&braille, 1, 1), 255);
+        assert_eq!(pixel(&braille, 5, 1), 0);
+        assert_eq!(pixel(&braille, 1, 13), 0);
+    }
+
+    // Powerline の三角はセルの高さいっぱいに描く
+    #[test]
+    fn powerline_triangle() {
+        let glyph = rasterize('\u{E0B0}', &cell_metrics()).unwrap();
+        assert!((0..16).all(|y| pixel(&glyph, 0, y) > 0));
+        assert_eq!(pixel(&glyph, 7, 0), 0);
+        assert_eq!(pixel(&glyph, 3, 8), 255);
+        assert!(pixel(&glyph, 7, 8) > 0);
+    }
+}
@@ -0,0 +1,196 @@
+use alacritty_terminal::{event::WindowSize, index::Side};
+
+use crate::config::Delta;
+
+// フォントのメトリクスから求めたセルの大きさ
+// 描画、カーソル、pty に伝えるサイズはすべてこれを基準にする
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CellMetrics {
+    cell_width: u32,
+    cell_height: u32,
+
+    // セルの上端からベースラインまでのピクセル数
+    baseline: i32,
+
+    // ベースラインからの相対位置。下向きが負
+    underline_position: f32,
+    underline_thickness: f32,
+    strikeout_position: f32,
+    strikeout_thickness: f32,
+
+    // セル内でグリフをずらすピクセル数。y は下向きが正
+    glyph_offset: Delta,
+}
+
+impl CellMetrics {
+    pub fn new(metrics: &crossfont::Metrics, offset: Delta, glyph_offset: Delta) -> Self {
+        let cell_width = (metrics.average_advance + offset.x as f64).floor().max(1.0) as u32;
+        let cell_height = (metrics.line_height + offset.y as f64).floor().max(1.0) as u32;
+
+        // descent は負の値なのでセルの下端からその分だけ持ち上げた位置がベースライン
+        let baseline = (cell_height as f32 + metrics.descent).round() as i32;
+
+        Self {
+            cell_width,
+            cell_height,
+            baseline,
+            underline_position: metrics.underline_position,
+            underline_thickness: metrics.underline_thickness.max(1.0),
+            strikeout_position: metrics.strikeout_position,
+            strikeout_thickness: metrics.strikeout_thickness.max(1.0),
+            glyph_offset,
+        }
+    }
+
+    pub fn cell_width(&self) -> u32 {
+        self.cell_width
+    }
+
+    pub fn cell_height(&self) -> u32 {
+        self.cell_height
+    }
+
+    pub fn baseline(&self) -> i32 {
+        self.baseline
+    }
+
+    pub fn underline_thickness(&self) -> f32 {
+        self.underline_thickness
+    }
+
+    pub fn strikeout_thickness(&self) -> f32 {
+        self.strikeout_thickness
+    }
+
+    // セルの上端から下線の上端までのピクセル数
+    // フォントによってはセルからはみ出すのでセル内に収める
+    pub fn underline_top(&self) -> f32 {
+        let top = self.baseline as f32 - self.underline_position - self.underline_thickness / 2.0;
+        top.min(self.cell_height as f32 - self.underline_thickness)
+            .max(0.0)
+    }
+
+    // セルの上端から取り消し線の上端までのピクセル数
+    pub fn strikeout_top(&self) -> f32 {
+        let top = self.baseline as f32 - self.strikeout_position - self.strikeout_thickness / 2.0;
+        top.max(0.0)
+    }
+
+    // グリフの位置に変換したときにセルの左上ぴったりになる left と top
+    pub fn cell_aligned_placement(&self) -> (i32, i32) {
+        (-self.glyph_offset.x, self.baseline + self.glyph_offset.y)
+    }
+
+    // ウィンドウに収まる列数。最低でも 1 列は確保する
+    pub fn columns(&self, width: u32) -> usize {
+        (width / self.cell_width).max(1) as usize
+    }
+
+    // ウィンドウに収まる行数。最低でも 1 行は確保する
+    pub fn lines(&self, height: u32) -> usize {
+        (height / self.cell_height).max(1) as usize
+    }
+
+    // ピクセル座標にあるセルの列と行
+    // 領域の外はいちばん近いセルにする
+    pub fn cell_at(&self, x: f64, y: f64, width: u32, height: u32) -> (usize, usize) {
+        let column = (x.max(0.0) as u32 / self.cell_width) as usize;
+        let line = (y.max(0.0) as u32 / self.cell_height) as usize;
+        (
+            column.min(self.columns(width) - 1),
+            line.min(self.lines(height) - 1),
+        )
+    }
+
+    // ピクセル座標がセルの左右どちらの半分にあるか
+    // 最後の列より右は右側とみなす
+    pub fn cell_side(&self, x: f64, width: u32) -> Side {
+        let right = (self.columns(width) as u32 * self.cell_width) as f64;
+        let x_in_cell = x.max(0.0) % self.cell_width as f64;
+        if x < right && x_in_cell < self.cell_width as f64 / 2.0 {
+            Side::Left
+        } else {
+            Side::Right
+        }
+    }
+
+    // pty に伝えるサイズ
+    pub fn window_size(&self, width: u32, height: u32) -> WindowSize {
+        WindowSize {
+            num_lines: self.lines(height) as u16,
+            num_cols: self.columns(width) as u16,
+            cell_width: self.cell_width as u16,
+            cell_height: self.cell_height as u16,
+        }
+    }
+
+    // セルの左上を原点としたグリフ左上のピクセル座標
+    pub fn glyph_position(&self, left: i32, top: i32) -> (i32, i32) {
+        (
+            left + self.glyph_offset.x,
+            self.baseline - top + self.glyph_offset.y,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::Delta;
+
+    use super::CellMetrics;
+
+    fn metrics() -> crossfont::Metrics {
+        crossfont::Metrics {
+            average_advance: 9.6,
+            line_height: 19.2,
+            descent: -4.0,
+            underline_position: -2.0,
+            underline_thickness: 1.0,
+            strikeout_position: 5.0,
+            strikeout_thickness: 1.0,
+        }
+    }
+
+    #[test]
+    fn cell_size() {
+        let cell_metrics = CellMetrics::new(&metrics(), Delta::default(), Delta::default());
+        assert_eq!(cell_metrics.cell_width(), 9);
+        assert_eq!(cell_metrics.cell_height(), 19);
+        assert_eq!(cell_metrics.baseline(), 15);
+    }
+
+    #[test]
+    fn cell_size_with_offset() {
+        let cell_metrics = CellMetrics::new(&metrics(), Delta { x: 1, y: 2 }, Delta::default());
+        assert_eq!(cell_metrics.cell_width(), 10);
+        assert_eq!(cell_metrics.cell_height(), 21);
+    }
+
+    // グリッドとして使う行数と列数が pty に伝えるサイズと一致する
+    #[test]
+    fn window_size() {
+        let cell_metrics = CellMetrics::new(&metrics(), Delta::default(), Delta::default());
+        let window_size = cell_metrics.window_size(640, 480);
+        assert_eq!(window_size.num_cols as usize, cell_metrics.columns(640));
+        assert_eq!(window_size.num_lines as usize, cell_metrics.lines(480));
+        assert_eq!(window_size.num_cols, 71);
+        assert_eq!(window_size.num_lines, 25);
+        assert_eq!(window_size.cell_width, 9);
+        assert_eq!(window_size.cell_height, 19);
+    }
+
+    #[test]
+    fn decoration_position() {
+        let cell_metrics = CellMetrics::new(&metrics(), Delta::default(), Delta::default());
+        assert_eq!(cell_metrics.underline_top(), 16.5);
+        assert_eq!(cell_metrics.strikeout_top(), 9.5);
+    }
+
+    #[test]
+    fn glyph_position() {
+        let cell_metrics = CellMetrics::new(&metrics(), Delta::default(), Delta { x: 1, y: 2 });
+        assert_eq!(cell_metrics.glyph_position(1, 10), (2, 7));
+        let (left, top) = cell_metrics.cell_aligned_placement();
+        assert_eq!(cell_metrics.glyph_position(left, top), (0, 0));
+    }
+}
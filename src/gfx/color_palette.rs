@@ -0,0 +1,264 @@
+use alacritty_terminal::{
+    term::color::{Colors, COUNT},
+    vte::ansi::{Color, NamedColor, Rgb},
+};
+
+use crate::config;
+
+// 暗い色は通常の色をこの割合で暗くする
+const DIM_FACTOR: f32 = 0.66;
+
+// xterm 互換の 256 色と前景色や背景色などの名前付きの色
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColorPalette {
+    colors: [Rgb; COUNT],
+
+    // 検索に一致したセルの前景色と背景色
+    search_match: (Rgb, Rgb),
+    search_focused_match: (Rgb, Rgb),
+
+    // 生成に使った配色の設定。変更検知に使う
+    source: config::Colors,
+}
+
+impl ColorPalette {
+    pub fn new(config: &config::Colors) -> Self {
+        let mut colors = [Rgb { r: 0, g: 0, b: 0 }; COUNT];
+
+        // 0-15: 設定ファイルの ANSI カラー
+        let normal = config.normal.to_array();
+        let bright = config.bright.to_array();
+        for (index, rgb) in normal.iter().chain(bright.iter()).enumerate() {
+            colors[index] = convert(*rgb);
+        }
+
+        // 16-231: 6x6x6 のカラーキューブ
+        for r in 0..6 {
+            for g in 0..6 {
+                for b in 0..6 {
+                    let index = 16 + 36 * r + 6 * g + b;
+                    colors[index] = Rgb {
+                        r: cube_level(r),
+                        g: cube_level(g),
+                        b: cube_level(b),
+                    };
+                }
+            }
+        }
+
+        // 232-255: グレースケール
+        for (index, rgb) in colors[232..256].iter_mut().enumerate() {
+            let level = (8 + 10 * index) as u8;
+            *rgb = Rgb {
+                r: level,
+                g: level,
+                b: level,
+            };
+        }
+
+        // 名前付きの色
+        let foreground = convert(config.primary.foreground);
+        colors[NamedColor::Foreground as usize] = foreground;
+        colors[NamedColor::Background as usize] = convert(config.primary.background);
+        colors[NamedColor::Cursor as usize] = config.cursor.map(convert).unwrap_or(foreground);
+        colors[NamedColor::BrightForeground as usize] = config
+            .primary
+            .bright_foreground
+            .map(convert)
+            .unwrap_or(foreground);
+        colors[NamedColor::DimForeground as usize] = config
+            .primary
+            .dim_foreground
+            .map(convert)
+            .unwrap_or_else(|| dim(foreground));
+
+        let dim_colors = match &config.dim {
+            Some(dim_colors) => dim_colors.to_array().map(convert),
+            None => normal.map(|rgb| dim(convert(rgb))),
+        };
+        for (index, rgb) in dim_colors.into_iter().enumerate() {
+            colors[NamedColor::DimBlack as usize + index] = rgb;
+        }
+
+        let cell_colors =
+            |colors: config::CellColors| (convert(colors.foreground), convert(colors.background));
+        Self {
+            colors,
+            search_match: cell_colors(config.search.matches),
+            search_focused_match: cell_colors(config.search.focused_match),
+            source: config.clone(),
+        }
+    }
+
+    pub fn source(&self) -> &config::Colors {
+        &self.source
+    }
+
+    // OSC 4/10/11 でアプリから上書きされた色があればそちらを優先する
+    pub fn get_with_override(&self, index: usize, term_colors: &Colors) -> Rgb {
+        term_colors[index].unwrap_or(self.colors[index])
+    }
+
+    // 検索に一致したセルの前景色と背景色
+    pub fn search_match(&self, is_focused: bool) -> (Rgb, Rgb) {
+        if is_focused {
+            self.search_focused_match
+        } else {
+            self.search_match
+        }
+    }
+
+    pub fn resolve(&self, color: Color, term_colors: &Colors) -> Rgb {
+        match color {
+            Color::Spec(rgb) => rgb,
+            Color::Named(named_color) => self.get_with_override(named_color as usize, term_colors),
+            Color::Indexed(index) => self.get_with_override(index as usize, term_colors),
+        }
+    }
+
+    // SGR 2 の暗い色
+    // ANSI カラーは暗い色のパレットを使い、それ以外は明るさを落とす
+    pub fn resolve_dim(&self, color: Color, term_colors: &Colors) -> Rgb {
+        match color {
+            Color::Named(named_color) => {
+                self.get_with_override(named_color.to_dim() as usize, term_colors)
+            }
+            Color::Indexed(index) if index < 8 => {
+                self.get_with_override(NamedColor::DimBlack as usize + index as usize, term_colors)
+            }
+            _ => dim(self.resolve(color, term_colors)),
+        }
+    }
+}
+
+impl Default for ColorPalette {
+    fn default() -> Self {
+        Self::new(&config::Colors::default())
+    }
+}
+
+// [0, 1] の RGBA に変換
+pub fn to_rgba(rgb: Rgb) -> [f32; 4] {
+    [
+        rgb.r as f32 / 255.0,
+        rgb.g as f32 / 255.0,
+        rgb.b as f32 / 255.0,
+        1.0,
+    ]
+}
+
+fn convert(rgb: config::Rgb) -> Rgb {
+    Rgb {
+        r: rgb.r,
+        g: rgb.g,
+        b: rgb.b,
+    }
+}
+
+fn cube_level(level: usize) -> u8 {
+    if level == 0 {
+        0
+    } else {
+        (55 + 40 * level) as u8
+    }
+}
+
+fn dim(rgb: Rgb) -> Rgb {
+    Rgb {
+        r: (rgb.r as f32 * DIM_FACTOR) as u8,
+        g: (rgb.g as f32 * DIM_FACTOR) as u8,
+        b: (rgb.b as f32 * DIM_FACTOR) as u8,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alacritty_terminal::{
+        term::color::Colors,
+        vte::ansi::{Color, NamedColor, Rgb},
+    };
+
+    use crate::config;
+
+    use super::ColorPalette;
+
+    fn rgb(r: u8, g: u8, b: u8) -> Rgb {
+        Rgb { r, g, b }
+    }
+
+    #[test]
+    fn ansi_colors() {
+        let config = config::Colors::default();
+        let palette = ColorPalette::new(&config);
+        let get = |index| palette.get_with_override(index, &Colors::default());
+        assert_eq!(get(1), rgb(0xac, 0x42, 0x42));
+        assert_eq!(get(9), rgb(0xc5, 0x55, 0x55));
+    }
+
+    #[test]
+    fn color_cube() {
+        let palette = ColorPalette::default();
+        let get = |index| palette.get_with_override(index, &Colors::default());
+        assert_eq!(get(16), rgb(0, 0, 0));
+        assert_eq!(get(196), rgb(255, 0, 0));
+        assert_eq!(get(231), rgb(255, 255, 255));
+        assert_eq!(get(110), rgb(135, 175, 215));
+    }
+
+    #[test]
+    fn grayscale() {
+        let palette = ColorPalette::default();
+        let get = |index| palette.get_with_override(index, &Colors::default());
+        assert_eq!(get(232), rgb(8, 8, 8));
+        assert_eq!(get(255), rgb(238, 238, 238));
+    }
+
+    #[test]
+    fn named_colors() {
+        let palette = ColorPalette::default();
+        let term_colors = Colors::default();
+        let foreground = palette.resolve(Color::Named(NamedColor::Foreground), &term_colors);
+        assert_eq!(foreground, rgb(0xd8, 0xd8, 0xd8));
+
+        let cursor = palette.resolve(Color::Named(NamedColor::Cursor), &term_colors);
+        assert_eq!(cursor, foreground);
+
+        let dim_red = palette.resolve(Color::Named(NamedColor::DimRed), &term_colors);
+        assert_eq!(dim_red, rgb(113, 43, 43));
+    }
+
+    #[test]
+    fn dim_colors() {
+        let palette = ColorPalette::default();
+        let term_colors = Colors::default();
+        let dim_red = palette.resolve(Color::Named(NamedColor::DimRed), &term_colors);
+        assert_eq!(
+            palette.resolve_dim(Color::Named(NamedColor::Red), &term_colors),
+            dim_red
+        );
+        assert_eq!(
+            palette.resolve_dim(Color::Indexed(1), &term_colors),
+            dim_red
+        );
+        assert_eq!(
+            palette.resolve_dim(Color::Spec(rgb(100, 200, 50)), &term_colors),
+            rgb(66, 132, 33)
+        );
+    }
+
+    // アプリから上書きされた色が優先される
+    #[test]
+    fn override_by_terminal() {
+        let palette = ColorPalette::default();
+        let mut term_colors = Colors::default();
+        term_colors[NamedColor::Background] = Some(rgb(1, 2, 3));
+        term_colors[5] = Some(rgb(4, 5, 6));
+
+        let background = palette.resolve(Color::Named(NamedColor::Background), &term_colors);
+        assert_eq!(background, rgb(1, 2, 3));
+        assert_eq!(
+            palette.resolve(Color::Indexed(5), &term_colors),
+            rgb(4, 5, 6)
+        );
+    }
+}
@@ -1,15 +1,28 @@
+use std::collections::HashMap;
+
 use alacritty_terminal::{
     grid::Indexed,
     index::{Column, Line, Point},
-    term::{cell::Cell, RenderableContent, RenderableCursor},
-    vte::ansi::{Color, NamedColor},
+    term::{
+        cell::{Cell, Flags},
+        color::Colors,
+        RenderableContent, RenderableCursor,
+    },
+    vte::ansi::{Color, CursorShape, NamedColor, Rgb},
 };
 
+use crossfont::RasterizedGlyph;
 use nalgebra::{Matrix3, Vector2};
 
 use crate::util::{DiffCalculator, IDiffCalculator};
 
-use super::{GlyphManager, GlyphWriter};
+use super::{
+    builtin_glyph,
+    color_palette::{self, ColorPalette},
+    glyph_manager::{FontStyle, GlyphKey},
+    text_shaper::ShapedCluster,
+    CellMetrics, GlyphManager, GlyphWriter, Preedit, SearchOverlay,
+};
 
 #[derive(PartialEq, Clone, Copy)]
 pub struct CharacterInfo {
@@ -18,19 +31,75 @@ pub struct CharacterInfo {
     pub fore_ground_color: [f32; 4],
     pub uv0: nalgebra::Vector2<f32>,
     pub uv1: nalgebra::Vector2<f32>,
+    // アトラスのページ
+    pub page: u32,
+    // カラーのアトラスから色をそのまま使う
+    pub is_color: bool,
+    pub index: usize,
+}
+
+#[derive(PartialEq, Clone, Copy)]
+pub struct CellBackgroundInfo {
+    pub transform: nalgebra::Matrix3x2<f32>,
+    pub color: [f32; 4],
+    pub index: usize,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum UnderlineStyle {
+    None,
+    Single,
+    Double,
+    Undercurl,
+    Dotted,
+    Dashed,
+}
+
+impl UnderlineStyle {
+    fn from_flags(flags: Flags) -> Self {
+        if flags.contains(Flags::DOUBLE_UNDERLINE) {
+            Self::Double
+        } else if flags.contains(Flags::UNDERCURL) {
+            Self::Undercurl
+        } else if flags.contains(Flags::DOTTED_UNDERLINE) {
+            Self::Dotted
+        } else if flags.contains(Flags::DASHED_UNDERLINE) {
+            Self::Dashed
+        } else if flags.contains(Flags::UNDERLINE) {
+            Self::Single
+        } else {
+            Self::None
+        }
+    }
+}
+
+// 下線と取り消し線
+#[derive(PartialEq, Clone, Copy)]
+pub struct CellDecorationInfo {
+    pub transform: nalgebra::Matrix3x2<f32>,
+    pub underline: UnderlineStyle,
+    pub underline_color: [f32; 4],
+    pub strikeout: bool,
+    pub strikeout_color: [f32; 4],
     pub index: usize,
 }
 
 #[derive(Debug)]
 pub struct GlyphTexturePatch {
+    page: u32,
     offset_x: u32,
     offset_y: u32,
     width: u32,
     height: u32,
+    is_color: bool,
     pixels: Vec<u8>,
 }
 
 impl GlyphTexturePatch {
+    pub fn page(&self) -> u32 {
+        self.page
+    }
+
     pub fn offset_x(&self) -> u32 {
         self.offset_x
     }
@@ -47,16 +116,48 @@ impl GlyphTexturePatch {
         self.height
     }
 
+    // RGBA のピクセルか
+    pub fn is_color(&self) -> bool {
+        self.is_color
+    }
+
     pub fn pixels(&self) -> &[u8] {
         &self.pixels
     }
 }
 
+// ウィンドウの中でペインを描く場所
+// ウィンドウのペインはセルを 1 列に並べて、ペインごとに続きの位置から置く
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PaneViewport {
+    // ウィンドウのピクセル数
+    pub window_size: (u32, u32),
+
+    // ペインの左上のピクセル座標
+    pub origin: (u32, u32),
+
+    // ペインのピクセル数
+    pub size: (u32, u32),
+
+    // ウィンドウのセルの並びでこのペインのセルが始まる位置
+    pub first_cell_index: usize,
+
+    // ウィンドウのすべてのペインのセルの数
+    pub window_cell_count: usize,
+
+    // カーソルはフォーカスしているペインにだけ描く
+    pub is_focused: bool,
+}
+
 #[derive(Default)]
 pub struct Diff {
     glyph_texture_patches: Vec<GlyphTexturePatch>,
     character_info_array: Vec<CharacterInfo>,
+    cell_background_info_array: Vec<CellBackgroundInfo>,
+    cell_decoration_info_array: Vec<CellDecorationInfo>,
     cursor: Option<RenderableCursor>,
+    cell_metrics: Option<CellMetrics>,
+    viewport: Option<PaneViewport>,
     item_count: i32,
 }
 
@@ -69,10 +170,28 @@ impl Diff {
         &self.character_info_array
     }
 
+    pub fn cell_background_info_array(&self) -> &[CellBackgroundInfo] {
+        &self.cell_background_info_array
+    }
+
+    pub fn cell_decoration_info_array(&self) -> &[CellDecorationInfo] {
+        &self.cell_decoration_info_array
+    }
+
     pub fn cursor(&self) -> Option<&RenderableCursor> {
         self.cursor.as_ref()
     }
 
+    // 配置に使ったセルの大きさ
+    pub fn cell_metrics(&self) -> Option<&CellMetrics> {
+        self.cell_metrics.as_ref()
+    }
+
+    // 配置に使ったペインの領域
+    pub fn viewport(&self) -> Option<&PaneViewport> {
+        self.viewport.as_ref()
+    }
+
     pub fn item_count(&self) -> i32 {
         self.item_count
     }
@@ -80,212 +199,612 @@ impl Diff {
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 struct CharacterInfoCache {
-    pub code: char,
-    pub color: Color,
+    // 結合文字も含めたグリフ
+    pub glyph_key: GlyphKey,
+    // 2 セル分の幅で描く
+    pub is_wide: bool,
+    // パレットの変更を検出できるように解決済みの色を持つ
+    pub color: Rgb,
+    // 既定の背景色のセルは塗らないので None
+    pub background: Option<Rgb>,
+    pub underline: UnderlineStyle,
+    pub underline_color: Rgb,
+    pub strikeout: bool,
     pub point: Point<Line, Column>,
 }
 
+impl CharacterInfoCache {
+    fn new(
+        cell: &Indexed<&Cell>,
+        shaped: Option<ShapedCluster>,
+        is_selected: bool,
+        search_highlight: Option<bool>,
+        display_offset: usize,
+        color_palette: &ColorPalette,
+        term_colors: &Colors,
+    ) -> Self {
+        let flags = cell.flags;
+
+        // 暗い色は前景色だけに適用する
+        let mut color = if flags.contains(Flags::DIM) {
+            color_palette.resolve_dim(cell.fg, term_colors)
+        } else {
+            color_palette.resolve(cell.fg, term_colors)
+        };
+        let mut background =
+            ContentPlotter::resolve_background(cell.bg, color_palette, term_colors);
+
+        // 反転したら既定の背景色のセルも前景色で塗る
+        // 選択中のセルはさらに反転して強調する
+        if flags.contains(Flags::INVERSE) != is_selected {
+            let inverse_color = background.unwrap_or_else(|| {
+                color_palette.resolve(Color::Named(NamedColor::Background), term_colors)
+            });
+            background = Some(color);
+            color = inverse_color;
+        }
+
+        // 検索に一致したセルは属性によらず検索の色で塗る
+        if let Some(is_focused) = search_highlight {
+            let (search_color, search_background) = color_palette.search_match(is_focused);
+            color = search_color;
+            background = Some(search_background);
+        }
+
+        let underline_color = match cell.underline_color() {
+            Some(underline_color) => color_palette.resolve(underline_color, term_colors),
+            None => color,
+        };
+
+        // 隠す文字はグリフも装飾も描かずに背景だけ残す
+        let is_hidden = flags.contains(Flags::HIDDEN);
+
+        // 全角文字は先頭のセルから 2 セルにまたがって描くので後ろのセルにはグリフを置かない
+        let is_spacer = flags.intersects(Flags::WIDE_CHAR_SPACER | Flags::LEADING_WIDE_CHAR_SPACER);
+        // 合字で前のセルに含まれた文字も何も描かない
+        let glyph_key = if is_hidden || is_spacer || shaped.is_some_and(|s| s.is_empty()) {
+            GlyphKey::from(' ')
+        } else {
+            let glyph_key = GlyphKey::new(cell.c, FontStyle::from_flags(flags))
+                .with_combining(cell.zerowidth().unwrap_or_default());
+            match shaped {
+                Some(shaped) => glyph_key.with_shaped(shaped),
+                None => glyph_key,
+            }
+        };
+
+        Self {
+            glyph_key,
+            is_wide: flags.contains(Flags::WIDE_CHAR),
+            color,
+            background,
+            underline: if is_hidden {
+                UnderlineStyle::None
+            } else {
+                UnderlineStyle::from_flags(flags)
+            },
+            underline_color,
+            strikeout: !is_hidden && flags.contains(Flags::STRIKEOUT),
+            // スクロールバックをさかのぼっているときは負の行から並ぶので画面の行にする
+            point: Point::new(cell.point.line + display_offset, cell.point.column),
+        }
+    }
+
+    fn glyph_key(&self) -> GlyphKey {
+        self.glyph_key
+    }
+}
+
+// ペインごとの前回の描画
+struct PaneState {
+    viewport: PaneViewport,
+
+    // 差分検出
+    diff_calculator: DiffCalculator<CharacterInfoCache>,
+
+    // 描いたセルの数
+    cell_count: usize,
+
+    // ほかのペインを描くときにアトラスから追い出さないように、見えているグリフを覚えておく
+    glyph_keys: Vec<GlyphKey>,
+}
+
 pub struct ContentPlotter {
     // TODO: グリフ画像を生成する処理は外部からさせるようにしたい
     glyph_writer: GlyphWriter,
 
-    // 差分検出
-    diff_calculator: DiffCalculator<CharacterInfoCache>,
+    // ペインのセルが始まる位置 -> 前回の描画
+    pane_state_table: HashMap<usize, PaneState>,
 }
 
 impl ContentPlotter {
     pub fn new() -> Self {
         let glyph_writer = GlyphWriter::new();
-        let diff_calculator = DiffCalculator::new();
 
         Self {
             glyph_writer,
-            diff_calculator,
+            pane_state_table: HashMap::default(),
         }
     }
 
+    // フォントが変わったらグリフもセルの配置も使えないので作り直す
+    pub fn rebuild(&mut self) {
+        self.glyph_writer = GlyphWriter::new();
+        self.pane_state_table.clear();
+    }
+
     pub fn calculate_diff(
         &mut self,
         renderable_content: RenderableContent,
         glyph_manager: &mut GlyphManager,
-        size: (u32, u32),
+        color_palette: &ColorPalette,
+        preedit: Option<&Preedit>,
+        search: Option<&SearchOverlay>,
+        viewport: &PaneViewport,
     ) -> Diff {
+        // ペインからはみ出したセルはほかのペインに重なるので描かない
+        let display_offset = renderable_content.display_offset;
+        let pane_size = glyph_manager
+            .cell_metrics()
+            .window_size(viewport.size.0, viewport.size.1);
+
         // グリフは全部作り直してる。差分検出したい
-        let cells = renderable_content
+        let mut cells = renderable_content
             .display_iter
+            .filter(|c| {
+                c.point.column.0 < pane_size.num_cols as usize
+                    && c.point.line.0 + (display_offset as i32) < pane_size.num_lines as i32
+            })
             .collect::<Vec<Indexed<&Cell>>>();
 
+        // IME で変換中の文字列をカーソルの位置から重ねる
+        let mut cursor = renderable_content.cursor;
+        let columns = cells.iter().map(|c| c.point.column.0 + 1).max();
+        let preedit_cells = match (preedit, columns) {
+            (Some(preedit), Some(columns)) => preedit.cells(cursor.point, columns),
+            _ => Vec::new(),
+        };
+        for preedit_cell in &preedit_cells {
+            if let Some(cell) = cells.iter_mut().find(|c| c.point == preedit_cell.point) {
+                *cell = Indexed {
+                    point: preedit_cell.point,
+                    cell: &preedit_cell.cell,
+                };
+            }
+        }
+        // 行からはみ出した分は描かないので、カーソルも最後の列で止める
+        if let (Some(preedit), Some(columns)) = (preedit, columns) {
+            let column = cursor.point.column.0 + preedit.cursor_offset();
+            cursor.point.column = Column(column.min(columns - 1));
+        }
+
+        // 検索の入力欄は最下行に重ねて、カーソルも入力欄に移す
+        let bottom_line = cells.iter().map(|c| c.point.line).max();
+        let search_cells = match (search, bottom_line, columns) {
+            (Some(search), Some(line), Some(columns)) => {
+                cursor.point = Point::new(line, Column(search.cursor_column(columns)));
+                cursor.shape = CursorShape::Beam;
+                search.cells(line, columns)
+            }
+            _ => Vec::new(),
+        };
+        for search_cell in &search_cells {
+            if let Some(cell) = cells.iter_mut().find(|c| c.point == search_cell.point) {
+                *cell = Indexed {
+                    point: search_cell.point,
+                    cell: &search_cell.cell,
+                };
+            }
+        }
+
+        // 整形する設定なら同じ属性のセルの並びごとにグリフを決める
+        let shaped_clusters = Self::shape_runs(&cells, glyph_manager);
+
         // 差分検出
-        let items = cells.iter().map(|c| CharacterInfoCache {
-            code: c.c,
-            color: c.fg,
-            point: c.point,
-        });
-        let diff = self.diff_calculator.calculate(items);
-
-        // 差分をグリフ化
+        let term_colors = renderable_content.colors;
+        let selection = renderable_content.selection;
+        let emoji_wide_cells = Self::emoji_wide_cells(&cells);
+        let items = cells
+            .iter()
+            .zip(shaped_clusters.iter())
+            .enumerate()
+            .map(|(index, (c, shaped))| {
+                let is_selected =
+                    selection.is_some_and(|s| s.contains_cell(c, cursor.point, cursor.shape));
+                let search_highlight = match search {
+                    Some(search) if Some(c.point.line) != bottom_line => search.highlight(c.point),
+                    _ => None,
+                };
+                let mut info = CharacterInfoCache::new(
+                    c,
+                    *shaped,
+                    is_selected,
+                    search_highlight,
+                    display_offset,
+                    color_palette,
+                    term_colors,
+                );
+                info.is_wide |= emoji_wide_cells[index];
+                info
+            })
+            .collect::<Vec<CharacterInfoCache>>();
+
+        // 場所が変わったペインは全部描きなおす
+        // 使うセルの並びが重なったペインも書き換えられるので、次に描くときに全部描きなおす
+        let first_cell_index = viewport.first_cell_index;
+        let is_moved = !matches!(
+            self.pane_state_table.get(&first_cell_index),
+            Some(pane_state) if pane_state.viewport == *viewport
+        );
+        if is_moved {
+            let cell_count = pane_size.num_lines as usize * pane_size.num_cols as usize;
+            self.pane_state_table.retain(|index, pane_state| {
+                let is_overlapped = *index < first_cell_index + cell_count
+                    && first_cell_index < *index + pane_state.cell_count;
+                *index < viewport.window_cell_count && !is_overlapped
+            });
+            self.pane_state_table.insert(
+                first_cell_index,
+                PaneState {
+                    viewport: *viewport,
+                    diff_calculator: DiffCalculator::new(),
+                    cell_count: 0,
+                    glyph_keys: Vec::new(),
+                },
+            );
+        }
+        let pane_state = self.pane_state_table.get_mut(&first_cell_index).unwrap();
+        let diff = pane_state.diff_calculator.calculate(items.iter().copied());
+        pane_state.cell_count = items.len();
+        pane_state.glyph_keys = items.iter().map(|c| c.glyph_key()).collect();
+
+        // 画面のグリフをアトラスに置く
+        // 変化のなかったセルやほかのペインのグリフも渡して、今のフレームで使うものとして追い出さないようにする
+        let glyph_keys = self
+            .pane_state_table
+            .values()
+            .flat_map(|pane_state| pane_state.glyph_keys.iter().copied())
+            .collect::<Vec<GlyphKey>>();
         let glyph_patches = self
             .glyph_writer
-            .execute(diff.items().iter().map(|c| c.code), glyph_manager);
+            .execute(glyph_keys.into_iter(), glyph_manager);
+
+        // ピクセル座標を [-1, 1] に変換する行列
+        // フレームバッファーのサイズで変わる
+        // ペインの中の座標はペインの左上に移してから変換する
+        let (origin_x, origin_y) = viewport.origin;
+        let screen_matrix = Self::screen_matrix(viewport.window_size)
+            * Matrix3::new_translation(&Vector2::new(origin_x as f32, origin_y as f32));
 
         // 表示要素を描画に必要な情報に変換
+        let cell_metrics = *glyph_manager.cell_metrics();
         let items = (0..diff.items().len())
             .map(|index| {
                 let item = &diff.items()[index];
-                let item_index = diff.indicies()[index];
-
-                let code = item.code;
-                let glyph = glyph_manager.get_rasterized_glyph(code);
-
-                // ピクセル座標で 1x1 の四角形をフォントのサイズにスケール
-                let local_pixel_scale_matrix = Matrix3::new_nonuniform_scaling(&Vector2::new(
-                    glyph.width as f32,
-                    glyph.height as f32,
-                ));
-
-                // ピクセル座標で表示位置をずらす
-                let local_pixel_translate_matrix = Matrix3::new_translation(&Vector2::new(
-                    glyph.left as f32,
-                    (32 - glyph.top) as f32,
-                ));
-
-                // ピクセル座標を [0, 1] 空間に変換する行列
-                // フレームバッファーのサイズで変わる
-                // 文字間を開けて見栄えを整えるために文字サイズを 0.6 倍している
-                let normalized_matrix = Matrix3::new_nonuniform_scaling(&Vector2::new(
-                    0.6f32 / size.0 as f32,
-                    0.6f32 / size.1 as f32,
-                ));
-
-                // [0, 1] => [-1, 1]
-                let view_matrix =
-                    Matrix3::new_translation(&Vector2::new(-1.0, -1.0)) * Matrix3::new_scaling(2.0);
-
-                // 画面上に配置
-                let offset_matrix = Matrix3::new_translation(
-                    &(Vector2::new(
-                        item.point.column.0 as f32 / (size.0 as f32 / 16.0),
-                        item.point.line.0 as f32 / (size.1 as f32 / 16.0),
-                    )),
-                );
-
-                let transform_matrix = view_matrix
-                    * offset_matrix
-                    * normalized_matrix
-                    * local_pixel_translate_matrix
-                    * local_pixel_scale_matrix;
-
-                let character = self.glyph_writer.get_clip_rect(code);
-                let fore_ground_color = match item.color {
-                    Color::Named(c) => Self::convert_named_color(c),
-                    Color::Spec(rgb) => [
-                        rgb.r as f32 / 255.0,
-                        rgb.g as f32 / 255.0,
-                        rgb.b as f32 / 255.0,
-                        1.0f32,
-                    ],
-                    Color::Indexed(i) => Self::convert_index_color(i),
+                let item_index = first_cell_index + diff.indicies()[index];
+
+                let code = item.glyph_key.code;
+                let glyph = glyph_manager.get_rasterized_glyph(item.glyph_key());
+
+                // カラー絵文字はビットマップの大きさがフォントと合わないのでセルに収める
+                // 全角でも次のセルが空いた絵文字でもなければ 1 セルに収める
+                let character = self.glyph_writer.get_clip_rect(item.glyph_key());
+                let local_pixel_matrix = if character.is_color {
+                    Self::fit_to_cells_matrix(glyph, item.is_wide, &cell_metrics)
+                } else {
+                    // ピクセル座標で 1x1 の四角形をフォントのサイズにスケール
+                    let local_pixel_scale_matrix = Matrix3::new_nonuniform_scaling(&Vector2::new(
+                        glyph.width as f32,
+                        glyph.height as f32,
+                    ));
+
+                    // ピクセル座標で表示位置をずらす
+                    let (glyph_x, glyph_y) = cell_metrics.glyph_position(glyph.left, glyph.top);
+                    let local_pixel_translate_matrix =
+                        Matrix3::new_translation(&Vector2::new(glyph_x as f32, glyph_y as f32));
+                    local_pixel_translate_matrix * local_pixel_scale_matrix
                 };
+
+                // ピクセル座標でセルの位置に配置
+                let offset_matrix = Self::cell_offset_matrix(item.point, &cell_metrics);
+
+                let transform_matrix = screen_matrix * offset_matrix * local_pixel_matrix;
+
+                let fore_ground_color = color_palette::to_rgba(item.color);
                 CharacterInfo {
                     code,
                     transform: transform_matrix.transpose().remove_column(2),
                     fore_ground_color,
                     uv0: nalgebra::Vector2::new(character.uv_begin[0], character.uv_begin[1]),
                     uv1: nalgebra::Vector2::new(character.uv_end[0], character.uv_end[1]),
+                    page: character.page,
+                    is_color: character.is_color,
                     index: item_index,
                 }
             })
             .collect::<Vec<CharacterInfo>>();
 
+        // セルの背景
+        // セルの大きさぴったりの矩形なので同じ色が続いても継ぎ目なくつながる
+        let cell_scale_matrix = Matrix3::new_nonuniform_scaling(&Vector2::new(
+            cell_metrics.cell_width() as f32,
+            cell_metrics.cell_height() as f32,
+        ));
+        let cell_background_info_array = diff
+            .items()
+            .iter()
+            .zip(diff.indicies())
+            .map(|(item, index)| {
+                let offset_matrix = Self::cell_offset_matrix(item.point, &cell_metrics);
+                let transform_matrix = screen_matrix * offset_matrix * cell_scale_matrix;
+                CellBackgroundInfo {
+                    transform: transform_matrix.transpose().remove_column(2),
+                    color: match item.background {
+                        Some(background) => color_palette::to_rgba(background),
+                        None => [0.0; 4],
+                    },
+                    index: first_cell_index + *index,
+                }
+            })
+            .collect::<Vec<CellBackgroundInfo>>();
+
+        // 下線と取り消し線
+        // 背景と同じくセルの大きさの矩形に描く
+        let cell_decoration_info_array = diff
+            .items()
+            .iter()
+            .zip(diff.indicies())
+            .map(|(item, index)| {
+                let offset_matrix = Self::cell_offset_matrix(item.point, &cell_metrics);
+                let transform_matrix = screen_matrix * offset_matrix * cell_scale_matrix;
+                CellDecorationInfo {
+                    transform: transform_matrix.transpose().remove_column(2),
+                    underline: item.underline,
+                    underline_color: color_palette::to_rgba(item.underline_color),
+                    strikeout: item.strikeout,
+                    strikeout_color: color_palette::to_rgba(item.color),
+                    index: first_cell_index + *index,
+                }
+            })
+            .collect::<Vec<CellDecorationInfo>>();
+
         // グリフ
         let glyph_texture_patches = glyph_patches
             .iter()
             .map(|glyph_patch| {
                 //
                 GlyphTexturePatch {
+                    page: glyph_patch.page(),
                     offset_x: glyph_patch.offset_x(),
                     offset_y: glyph_patch.offset_y(),
                     width: glyph_patch.width(),
                     height: glyph_patch.height(),
+                    is_color: glyph_patch.is_color(),
                     pixels: glyph_patch.pixels().to_vec(),
                 }
             })
             .collect::<Vec<GlyphTexturePatch>>();
 
-        let item_count = cells.len() as i32;
+        // カーソルも画面の行にして、画面外にあれば隠す
+        let screen_lines = cells
+            .iter()
+            .map(|c| c.point.line.0 + display_offset as i32 + 1)
+            .max()
+            .unwrap_or(0);
+        cursor.point.line += display_offset;
+        if cursor.point.line.0 >= screen_lines {
+            cursor.shape = CursorShape::Hidden;
+        }
+
+        // ほかのペインのセルも描くのでウィンドウのセルの数だけ描画する
+        let item_count = viewport.window_cell_count as i32;
         Diff {
             glyph_texture_patches,
             character_info_array: items,
-            cursor: Some(renderable_content.cursor),
+            cell_background_info_array,
+            cell_decoration_info_array,
+            cursor: viewport.is_focused.then_some(cursor),
+            cell_metrics: Some(cell_metrics),
+            viewport: Some(*viewport),
             item_count,
         }
     }
 
-    fn convert_index_color(i: u8) -> [f32; 4] {
-        match i {
-            0 => Self::convert_named_color(NamedColor::White),
-            1 => Self::convert_named_color(NamedColor::Magenta),
-            2 => Self::convert_named_color(NamedColor::Black),
-            3 => Self::convert_named_color(NamedColor::BrightBlack),
-            4 => Self::convert_named_color(NamedColor::White),
-            5 => Self::convert_named_color(NamedColor::BrightMagenta),
-            6 => Self::convert_named_color(NamedColor::BrightWhite),
-            7 => Self::convert_named_color(NamedColor::White),
-            8 => Self::convert_named_color(NamedColor::BrightBlack),
-            10 => Self::convert_named_color(NamedColor::BrightBlue),
-            11 => Self::convert_named_color(NamedColor::Green),
-            12 => Self::convert_named_color(NamedColor::Blue),
-            13 => Self::convert_named_color(NamedColor::Cyan),
-            14 => Self::convert_named_color(NamedColor::White),
-            15 => Self::convert_named_color(NamedColor::Green),
-            // わからん
-            48 => Self::convert_named_color(NamedColor::White),
-            81 => Self::convert_named_color(NamedColor::White),
-            149 => Self::convert_named_color(NamedColor::White),
-            208 => Self::convert_named_color(NamedColor::White),
-            243 => Self::convert_named_color(NamedColor::White),
-            _ => {
-                println!("unknown index color: {}", i);
-                [0.0; 4]
+    // 絵文字の表示を U+FE0F で指定された文字は、同じ行の次のセルが空いていれば全角文字と同じく 2 セルに広げる
+    // 空いていなければ次の文字に重ならないように 1 セルに収める
+    fn emoji_wide_cells(cells: &[Indexed<&Cell>]) -> Vec<bool> {
+        let spacer = Flags::WIDE_CHAR_SPACER | Flags::LEADING_WIDE_CHAR_SPACER;
+        (0..cells.len())
+            .map(|index| {
+                let cell = &cells[index];
+                let is_emoji_presentation = cell
+                    .zerowidth()
+                    .is_some_and(|combining| combining.contains(&'\u{FE0F}'));
+                let is_next_blank = cells.get(index + 1).is_some_and(|next| {
+                    next.point.line == cell.point.line
+                        && next.c == ' '
+                        && !next.flags.intersects(spacer)
+                });
+                is_emoji_presentation && is_next_blank
+            })
+            .collect()
+    }
+
+    // 同じ行で属性の同じセルの並びをまとめて整形する
+    // 整形しない設定ならすべて None
+    fn shape_runs(
+        cells: &[Indexed<&Cell>],
+        glyph_manager: &GlyphManager,
+    ) -> Vec<Option<ShapedCluster>> {
+        let mut shaped_clusters = vec![None; cells.len()];
+
+        // 全角文字の後ろのセルは文字を持たないので並びを区切らずに飛ばす
+        let spacer = Flags::WIDE_CHAR_SPACER | Flags::LEADING_WIDE_CHAR_SPACER;
+        let attributes = |cell: &Indexed<&Cell>| {
+            (
+                cell.point.line,
+                cell.fg,
+                cell.bg,
+                cell.flags - spacer - Flags::WIDE_CHAR,
+            )
+        };
+
+        let texts = cells
+            .iter()
+            .map(|cell| {
+                std::iter::once(cell.c)
+                    .chain(cell.zerowidth().unwrap_or_default().iter().copied())
+                    .collect::<String>()
+            })
+            .collect::<Vec<String>>();
+
+        let mut begin = 0;
+        while begin < cells.len() {
+            let end = (begin + 1..cells.len())
+                .find(|index| attributes(&cells[*index]) != attributes(&cells[begin]))
+                .unwrap_or(cells.len());
+
+            // 隠す文字は描かないので整形しない
+            let flags = cells[begin].flags;
+            if !flags.contains(Flags::HIDDEN) {
+                let indices = (begin..end)
+                    .filter(|index| !cells[*index].flags.intersects(spacer))
+                    .collect::<Vec<usize>>();
+                let run_texts = indices
+                    .iter()
+                    .map(|index| texts[*index].as_str())
+                    .collect::<Vec<&str>>();
+                let Some(clusters) = glyph_manager.shape(FontStyle::from_flags(flags), &run_texts)
+                else {
+                    return shaped_clusters;
+                };
+
+                // 空白はグリフがないので、罫線などはセルぴったりに描くのでそのまま描く
+                for (index, cluster) in indices.into_iter().zip(clusters) {
+                    let code = cells[index].c;
+                    if code != ' ' && !builtin_glyph::is_builtin(code) {
+                        shaped_clusters[index] = cluster;
+                    }
+                }
             }
+
+            begin = end;
         }
+
+        shaped_clusters
     }
 
-    fn convert_named_color(color: NamedColor) -> [f32; 4] {
-        match color {
-            NamedColor::Black => [0.0, 0.0, 0.0, 0.0],
-            NamedColor::Red => [1.0, 0.0, 0.0, 0.0],
-            NamedColor::Green => [0.0, 1.0, 0.0, 0.0],
-            NamedColor::Yellow => [1.0, 1.0, 0.0, 0.0],
-            NamedColor::Blue => [0.0, 0.0, 0.8, 0.0],
-            NamedColor::White => [1.0, 1.0, 1.0, 0.0],
-            NamedColor::Magenta => [1.0, 0.0, 1.0, 0.0],
-            NamedColor::Cyan => [87.0 / 255.0, 154.0 / 255.0, 205.0 / 255.0, 0.0],
-            NamedColor::BrightBlack => [0.2, 0.2, 0.2, 0.0],
-            // NamedColor::BrightRed => todo!(),
-            // NamedColor::BrightGreen => todo!(),
-            // NamedColor::BrightYellow => todo!(),
-            NamedColor::BrightBlue => [0.0, 0.0, 1.0, 0.0],
-            NamedColor::BrightMagenta => [1.0, 0.0, 1.0, 0.0],
-            NamedColor::BrightCyan => [0.0, 1.0, 1.0, 0.0],
-            NamedColor::BrightWhite => [0.8, 0.8, 0.8, 0.0],
-            NamedColor::Foreground => [1.0, 1.0, 1.0, 0.0],
-            NamedColor::Background => [1.0, 1.0, 1.0, 0.0],
-            // NamedColor::Cursor => todo!(),
-            // NamedColor::DimBlack => todo!(),
-            // NamedColor::DimRed => todo!(),
-            // NamedColor::DimGreen => todo!(),
-            // NamedColor::DimYellow => todo!(),
-            // NamedColor::DimBlue => todo!(),
-            // NamedColor::DimMagenta => todo!(),
-            // NamedColor::DimCyan => todo!(),
-            // NamedColor::DimWhite => todo!(),
-            // NamedColor::BrightForeground => todo!(),
-            // NamedColor::DimForeground => todo!(),
-            _ => {
-                println!("unknown color: {:?}", color);
-                [0.0, 0.0, 0.0, 0.0]
-            }
+    fn resolve_background(
+        color: Color,
+        color_palette: &ColorPalette,
+        term_colors: &Colors,
+    ) -> Option<Rgb> {
+        // 既定の背景色は塗らずにウィンドウの背景を見せる
+        // アプリが OSC 11 で背景色を変えていたらその色で塗る
+        let is_default = color == Color::Named(NamedColor::Background)
+            && term_colors[NamedColor::Background].is_none();
+        if is_default {
+            return None;
         }
+
+        Some(color_palette.resolve(color, term_colors))
+    }
+
+    // ピクセル座標を [-1, 1] に変換する行列
+    fn screen_matrix(size: (u32, u32)) -> Matrix3<f32> {
+        // ピクセル座標を [0, 1] 空間に変換する行列
+        let normalized_matrix = Matrix3::new_nonuniform_scaling(&Vector2::new(
+            1.0f32 / size.0 as f32,
+            1.0f32 / size.1 as f32,
+        ));
+
+        // [0, 1] => [-1, 1]
+        let view_matrix =
+            Matrix3::new_translation(&Vector2::new(-1.0, -1.0)) * Matrix3::new_scaling(2.0);
+
+        view_matrix * normalized_matrix
+    }
+
+    // 縦横比を保ったままセルに収めて中央に置く行列
+    fn fit_to_cells_matrix(
+        glyph: &RasterizedGlyph,
+        is_wide: bool,
+        cell_metrics: &CellMetrics,
+    ) -> Matrix3<f32> {
+        let cell_count = if is_wide { 2.0 } else { 1.0 };
+        let box_width = cell_count * cell_metrics.cell_width() as f32;
+        let box_height = cell_metrics.cell_height() as f32;
+        let (width, height) = (glyph.width.max(1) as f32, glyph.height.max(1) as f32);
+        let scale = (box_width / width).min(box_height / height);
+
+        let (scaled_width, scaled_height) = (width * scale, height * scale);
+        Matrix3::new_translation(&Vector2::new(
+            (box_width - scaled_width) / 2.0,
+            (box_height - scaled_height) / 2.0,
+        )) * Matrix3::new_nonuniform_scaling(&Vector2::new(scaled_width, scaled_height))
+    }
+
+    // セルの左上に移動する行列
+    fn cell_offset_matrix(point: Point<Line, Column>, cell_metrics: &CellMetrics) -> Matrix3<f32> {
+        Matrix3::new_translation(&Vector2::new(
+            point.column.0 as f32 * cell_metrics.cell_width() as f32,
+            point.line.0 as f32 * cell_metrics.cell_height() as f32,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alacritty_terminal::{
+        grid::Indexed,
+        index::{Column, Line, Point},
+        term::cell::Cell,
+    };
+
+    use super::ContentPlotter;
+
+    fn cell(c: char, combining: Option<char>) -> Cell {
+        let mut cell = Cell {
+            c,
+            ..Default::default()
+        };
+        if let Some(combining) = combining {
+            cell.push_zerowidth(combining);
+        }
+        cell
+    }
+
+    fn indexed(column: usize, cell: &Cell) -> Indexed<&Cell> {
+        Indexed {
+            point: Point::new(Line(0), Column(column)),
+            cell,
+        }
+    }
+
+    // U+2764 U+FE0F は次のセルが空いていれば 2 セルに広げる
+    #[test]
+    fn emoji_presentation_with_free_cell() {
+        let (heart, blank) = (cell('\u{2764}', Some('\u{FE0F}')), cell(' ', None));
+        let cells = [indexed(0, &heart), indexed(1, &blank)];
+        assert_eq!(ContentPlotter::emoji_wide_cells(&cells), [true, false]);
+    }
+
+    // 次のセルに文字があったり行末だったりすれば 1 セルに収める
+    #[test]
+    fn emoji_presentation_without_free_cell() {
+        let (heart, a) = (cell('\u{2764}', Some('\u{FE0F}')), cell('a', None));
+        let cells = [indexed(0, &heart), indexed(1, &a)];
+        assert_eq!(ContentPlotter::emoji_wide_cells(&cells), [false, false]);
+
+        let cells = [indexed(0, &a), indexed(1, &heart)];
+        assert_eq!(ContentPlotter::emoji_wide_cells(&cells), [false, false]);
+    }
+
+    // U+FE0F のない文字は次のセルが空いていても広げない
+    #[test]
+    fn text_presentation() {
+        let (heart, blank) = (cell('\u{2764}', None), cell(' ', None));
+        let cells = [indexed(0, &heart), indexed(1, &blank)];
+        assert_eq!(ContentPlotter::emoji_wide_cells(&cells), [false, false]);
     }
 }
@@ -62,8 +62,13 @@ impl<'a> BackgroundRenderer<'a> {
         }
     }
 
-    pub fn resize(&mut self, _id: WindowId, queue: &wgpu::Queue, width: u32, height: u32) {
-        for instance in self.instance_table.values_mut() {
+    // 背景はウィンドウのデバイスに作っているので、ほかのウィンドウの背景は触らない
+    pub fn resize(&mut self, id: WindowId, queue: &wgpu::Queue, width: u32, height: u32) {
+        let Some(Some(background_id)) = self.window_background_table.get(&id) else {
+            return;
+        };
+
+        if let Some(instance) = self.instance_table.get_mut(background_id) {
             let width = width as f32;
             let height = height as f32;
             let image_width = instance.width as f32;
@@ -123,13 +128,22 @@ impl<'a> BackgroundRenderer<'a> {
             bytemuck::bytes_of(&MaterialData { alpha_enhance }),
         );
 
+        // 置き換えた背景はもう描かない
         let id = BackgroundId { id: Uuid::new_v4() };
-        self.window_background_table.insert(window_id, Some(id));
+        if let Some(Some(old_id)) = self.window_background_table.insert(window_id, Some(id)) {
+            self.instance_table.remove(&old_id);
+        }
         self.instance_table.insert(id, instance);
 
         id
     }
 
+    pub fn unregister(&mut self, id: WindowId) {
+        if let Some(Some(background_id)) = self.window_background_table.remove(&id) {
+            self.instance_table.remove(&background_id);
+        }
+    }
+
     pub fn render(&'a self, id: WindowId, mut render_pass: wgpu::RenderPass<'a>) {
         let Some(background_id_opt) = self.window_background_table.get(&id) else {
             return;
@@ -0,0 +1,200 @@
+use std::{borrow::Cow, collections::HashMap, marker::PhantomData};
+
+use bytemuck::{Pod, Zeroable};
+use wgpu::util::DeviceExt;
+use winit::window::WindowId;
+
+use crate::gfx::{content_plotter::Diff, detail::MAX_CELL_COUNT};
+
+#[repr(C)]
+#[derive(Debug, Pod, Copy, Clone, Zeroable)]
+struct CellData {
+    transform0: [f32; 4],
+    transform1: [f32; 4],
+    color: [f32; 4],
+}
+
+struct Instance {
+    render_pipeline: wgpu::RenderPipeline,
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    cell_storage_block: wgpu::Buffer,
+    cell_count: u32,
+}
+
+// セルごとの背景色を描画する
+pub struct CellBackgroundRenderer<'a> {
+    instance_table: HashMap<WindowId, Instance>,
+
+    _phantom_data: PhantomData<&'a ()>,
+}
+
+impl<'a> CellBackgroundRenderer<'a> {
+    pub fn new() -> Self {
+        Self {
+            instance_table: HashMap::default(),
+            _phantom_data: Default::default(),
+        }
+    }
+
+    pub fn register(&mut self, id: WindowId, device: &wgpu::Device, format: wgpu::TextureFormat) {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let vertex_buffers = [wgpu::VertexBufferLayout {
+            array_stride: (std::mem::size_of::<f32>() * 2) as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &[wgpu::VertexAttribute {
+                format: wgpu::VertexFormat::Float32x2,
+                offset: 0,
+                shader_location: 0,
+            }],
+        }];
+
+        let vertex_shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: None,
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("cell_rect.vs.wgsl"))),
+        });
+
+        let pixel_shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: None,
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("cell_rect.fs.wgsl"))),
+        });
+
+        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: None,
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &vertex_shader_module,
+                entry_point: "main",
+                buffers: &vertex_buffers,
+            },
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &pixel_shader_module,
+                entry_point: "main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState {
+                        color: wgpu::BlendComponent {
+                            src_factor: wgpu::BlendFactor::SrcAlpha,
+                            dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                            operation: wgpu::BlendOperation::Add,
+                        },
+                        alpha: wgpu::BlendComponent::OVER,
+                    }),
+                    write_mask: wgpu::ColorWrites::all(),
+                })],
+            }),
+            multiview: None,
+        });
+
+        // 頂点バッファー
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(&[0.0f32, 1.0, 0.0, 0.0, 1.0, 0.0, 1.0, 1.0]),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+
+        // インデックスバッファー
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(&[0u16, 1, 2, 0, 2, 3]),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+
+        // セルごとの情報。文字と同じだけ確保しておく
+        let cell_storage_block = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: (std::mem::size_of::<CellData>() * MAX_CELL_COUNT) as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        // リソースたちのバインド設定
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: cell_storage_block.as_entire_binding(),
+            }],
+        });
+
+        self.instance_table.insert(
+            id,
+            Instance {
+                render_pipeline,
+                vertex_buffer,
+                index_buffer,
+                bind_group,
+                cell_storage_block,
+                cell_count: 0,
+            },
+        );
+    }
+
+    pub fn unregister(&mut self, id: WindowId) {
+        self.instance_table.remove(&id);
+    }
+
+    pub fn update(&mut self, queue: &wgpu::Queue, id: WindowId, diff: &Diff) {
+        let Some(instance) = self.instance_table.get_mut(&id) else {
+            return;
+        };
+
+        // 背景を塗らないセルは透明で書き込まれているので文字と同じ数だけ描画する
+        instance.cell_count = diff.item_count().min(MAX_CELL_COUNT as i32) as u32;
+
+        for info in diff.cell_background_info_array() {
+            if info.index >= MAX_CELL_COUNT {
+                continue;
+            }
+
+            let t = info.transform;
+            let data = CellData {
+                transform0: [t[0], t[1], t[2], 0.0],
+                transform1: [t[3], t[4], t[5], 0.0],
+                color: info.color,
+            };
+            let offset = info.index * std::mem::size_of::<CellData>();
+            queue.write_buffer(
+                &instance.cell_storage_block,
+                offset as u64,
+                bytemuck::bytes_of(&data),
+            );
+        }
+    }
+
+    pub fn render(&'a self, id: WindowId, mut render_pass: wgpu::RenderPass<'a>) {
+        let Some(instance) = self.instance_table.get(&id) else {
+            return;
+        };
+
+        render_pass.set_pipeline(&instance.render_pipeline);
+        render_pass.set_vertex_buffer(0, instance.vertex_buffer.slice(..));
+        render_pass.set_index_buffer(instance.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+        render_pass.set_bind_group(0, &instance.bind_group, &[]);
+        render_pass.draw_indexed(0..6, 0, 0..instance.cell_count);
+    }
+}
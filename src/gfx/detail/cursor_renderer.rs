@@ -1,5 +1,6 @@
-use std::borrow::Cow;
+use std::{borrow::Cow, collections::HashMap};
 
+use alacritty_terminal::vte::ansi::CursorShape;
 use wgpu::util::DeviceExt;
 use winit::window::WindowId;
 
@@ -16,26 +17,26 @@ struct Instance {
     index_buffer: wgpu::Buffer,
     bind_group: wgpu::BindGroup,
     constant_buffer: wgpu::Buffer,
+    size: (u32, u32),
+    is_visible: bool,
 }
 
 pub struct CursorRenderer<'a> {
-    instance: Option<Instance>,
-    size: (u32, u32),
+    instance_table: HashMap<WindowId, Instance>,
     _marker: std::marker::PhantomData<&'a ()>,
 }
 
 impl<'a> CursorRenderer<'a> {
     pub fn new() -> Self {
         Self {
-            instance: None,
-            size: (128, 128),
+            instance_table: HashMap::default(),
             _marker: std::marker::PhantomData,
         }
     }
 
     pub fn register(
         &mut self,
-        _id: WindowId,
+        id: WindowId,
         device: &wgpu::Device,
         _queue: &wgpu::Queue,
         format: wgpu::TextureFormat,
@@ -142,37 +143,54 @@ impl<'a> CursorRenderer<'a> {
             index_buffer,
             bind_group,
             constant_buffer,
+            size: (128, 128),
+            is_visible: true,
         };
-        self.instance = Some(instance);
+        self.instance_table.insert(id, instance);
     }
 
-    pub fn update(&mut self, _id: WindowId, diff: &Diff, queue: &wgpu::Queue) {
-        let Some(cursor) = diff.cursor() else {
+    pub fn unregister(&mut self, id: WindowId) {
+        self.instance_table.remove(&id);
+    }
+
+    pub fn update(&mut self, id: WindowId, diff: &Diff, queue: &wgpu::Queue) {
+        let (Some(cursor), Some(cell_metrics)) = (diff.cursor(), diff.cell_metrics()) else {
             return;
         };
 
-        let Some(instance) = &self.instance else {
+        let Some(instance) = self.instance_table.get_mut(&id) else {
             return;
         };
 
-        // [0, 1] に正規化
-        let (x, y) = {
-            (
-                cursor.point.column.0 as f32 / (self.size.0 as f32 / 16.0),
-                cursor.point.line.0 as f32 / (self.size.1 as f32 / 16.0),
-            )
-        };
+        // アプリが隠したときと、スクロールバックをさかのぼって画面外にあるときは描かない
+        instance.is_visible = cursor.shape != CursorShape::Hidden;
+        if !instance.is_visible {
+            return;
+        }
+
+        // セルの左上のピクセル座標
+        // ペインに分けていればペインの左上からの位置になる
+        let (origin_x, origin_y) = diff.viewport().map_or((0, 0), |viewport| viewport.origin);
+        let (x, y) = (
+            origin_x as f32 + cursor.point.column.0 as f32 * cell_metrics.cell_width() as f32,
+            origin_y as f32 + cursor.point.line.0 as f32 * cell_metrics.cell_height() as f32,
+        );
+
+        // [0, 1] に正規化して [-1, 1] に変換
+        let (width, height) = (instance.size.0 as f32, instance.size.1 as f32);
+        let (screen_x, screen_y) = (2.0 * x / width - 1.0, 2.0 * y / height - 1.0);
 
-        // [0, 1] -> [-1, 1]
-        let (screen_x, screen_y) = (2.0 * x - 1.0, 2.0 * y - 1.0);
+        // セルの高さいっぱいの縦棒
+        let cursor_width = (cell_metrics.cell_width() as f32 / 8.0).round().max(1.0);
+        let cursor_height = cell_metrics.cell_height() as f32;
 
         let data = [
-            1.0f32 / 256.0, // それっぽいサイズを目あわせ
+            2.0 * cursor_width / width,
             0.0,
             screen_x,
             0.0,
             0.0,
-            1.0 / 24.0, // それっぽいサイズを目あわせ
+            2.0 * cursor_height / height,
             screen_y,
             0.0,
         ];
@@ -183,14 +201,20 @@ impl<'a> CursorRenderer<'a> {
         );
     }
 
-    pub fn resize(&mut self, width: u32, height: u32) {
-        self.size = (width, height);
+    pub fn resize(&mut self, id: WindowId, width: u32, height: u32) {
+        let Some(instance) = self.instance_table.get_mut(&id) else {
+            return;
+        };
+        instance.size = (width, height);
     }
 
-    pub fn render(&'a self, _id: WindowId, mut render_pass: wgpu::RenderPass<'a>) {
-        let Some(instance) = &self.instance else {
+    pub fn render(&'a self, id: WindowId, mut render_pass: wgpu::RenderPass<'a>) {
+        let Some(instance) = self.instance_table.get(&id) else {
             return;
         };
+        if !instance.is_visible {
+            return;
+        }
 
         render_pass.set_pipeline(&instance.render_pipeline);
         render_pass.set_vertex_buffer(0, instance.vertex_buffer.slice(..));
@@ -1,9 +1,19 @@
 mod background_renderer;
+mod cell_background_renderer;
+mod cell_decoration_renderer;
 mod cursor_renderer;
 mod scan_buffer_renderer;
+mod scroll_indicator_renderer;
 mod text_renderer;
 
+// セルごとの情報を置くストレージバッファーの要素数
+// 4096x4096 のウィンドウを小さなフォントで埋めてもあふれないだけ確保し、超えた分は描かない
+const MAX_CELL_COUNT: usize = 128 * 1024;
+
 pub use background_renderer::BackgroundRenderer;
+pub use cell_background_renderer::CellBackgroundRenderer;
+pub use cell_decoration_renderer::CellDecorationRenderer;
 pub use cursor_renderer::CursorRenderer;
 pub use scan_buffer_renderer::ScanBufferRenderer;
+pub use scroll_indicator_renderer::ScrollIndicatorRenderer;
 pub use text_renderer::TextRenderer;
@@ -162,6 +162,10 @@ impl<'a> ScanBufferRenderer<'a> {
         );
     }
 
+    pub fn unregister(&mut self, id: WindowId) {
+        self.instance_table.remove(&id);
+    }
+
     pub fn resize(&self, id: WindowId, queue: &wgpu::Queue, width: u32, height: u32) {
         let (scale_x, scale_y) = (width as f32 / 4096.0, height as f32 / 4096.0);
         let instance = self.instance_table.get(&id).unwrap();
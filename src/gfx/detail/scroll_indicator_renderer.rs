@@ -0,0 +1,213 @@
+use std::{borrow::Cow, collections::HashMap};
+
+use wgpu::util::DeviceExt;
+use winit::window::WindowId;
+
+use crate::gfx::{PaneViewport, ScrollIndicator};
+
+struct View {
+    #[allow(dead_code)]
+    transform: [f32; 64],
+}
+
+struct Instance {
+    render_pipeline: wgpu::RenderPipeline,
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    constant_buffer: wgpu::Buffer,
+    size: (u32, u32),
+    is_visible: bool,
+}
+
+pub struct ScrollIndicatorRenderer<'a> {
+    instance_table: HashMap<WindowId, Instance>,
+    _marker: std::marker::PhantomData<&'a ()>,
+}
+
+impl<'a> ScrollIndicatorRenderer<'a> {
+    pub fn new() -> Self {
+        Self {
+            instance_table: HashMap::default(),
+            _marker: std::marker::PhantomData,
+        }
+    }
+
+    pub fn register(&mut self, id: WindowId, device: &wgpu::Device, format: wgpu::TextureFormat) {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let vertex_shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: None,
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("rect.vs.wgsl"))),
+        });
+
+        let pixel_shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: None,
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("rect.fs.wgsl"))),
+        });
+
+        // 頂点アトリビュート
+        let vertex_buffers = [wgpu::VertexBufferLayout {
+            array_stride: (std::mem::size_of::<f32>() * 2) as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &[wgpu::VertexAttribute {
+                format: wgpu::VertexFormat::Float32x2,
+                offset: 0,
+                shader_location: 0,
+            }],
+        }];
+
+        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: None,
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &vertex_shader_module,
+                entry_point: "main",
+                buffers: &vertex_buffers,
+            },
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &pixel_shader_module,
+                entry_point: "main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::all(),
+                })],
+            }),
+            multiview: None,
+        });
+
+        // 頂点バッファー
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(&[0.0f32, 1.0, 0.0, 0.0, 1.0, 0.0, 1.0, 1.0]),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+
+        // インデックスバッファー
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(&[0u16, 1, 2, 0, 2, 3]),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+
+        // 定数バッファー
+        let constant_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            size: std::mem::size_of::<View>() as u64,
+            mapped_at_creation: false,
+        });
+
+        // リソースたちのバインド設定
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: constant_buffer.as_entire_binding(),
+            }],
+        });
+
+        let instance = Instance {
+            render_pipeline,
+            vertex_buffer,
+            index_buffer,
+            bind_group,
+            constant_buffer,
+            size: (128, 128),
+            is_visible: false,
+        };
+        self.instance_table.insert(id, instance);
+    }
+
+    pub fn unregister(&mut self, id: WindowId) {
+        self.instance_table.remove(&id);
+    }
+
+    pub fn update(
+        &mut self,
+        id: WindowId,
+        scroll_indicator: &ScrollIndicator,
+        viewport: Option<&PaneViewport>,
+        queue: &wgpu::Queue,
+    ) {
+        let Some(instance) = self.instance_table.get_mut(&id) else {
+            return;
+        };
+
+        // ペインに分けていればペインの右端に描く
+        let (origin, size) = match viewport {
+            Some(viewport) => (viewport.origin, viewport.size),
+            None => ((0, 0), instance.size),
+        };
+        let Some((x, y, rect_width, rect_height)) = scroll_indicator.rect(size) else {
+            instance.is_visible = false;
+            return;
+        };
+        instance.is_visible = true;
+        let (x, y) = (origin.0 as f32 + x, origin.1 as f32 + y);
+
+        // [0, 1] に正規化して [-1, 1] に変換
+        let (width, height) = (instance.size.0 as f32, instance.size.1 as f32);
+        let (screen_x, screen_y) = (2.0 * x / width - 1.0, 2.0 * y / height - 1.0);
+        let data = [
+            2.0 * rect_width / width,
+            0.0,
+            screen_x,
+            0.0,
+            0.0,
+            2.0 * rect_height / height,
+            screen_y,
+            0.0,
+        ];
+        queue.write_buffer(
+            &instance.constant_buffer,
+            0, /*offset*/
+            bytemuck::cast_slice(&data),
+        );
+    }
+
+    pub fn resize(&mut self, id: WindowId, width: u32, height: u32) {
+        let Some(instance) = self.instance_table.get_mut(&id) else {
+            return;
+        };
+        instance.size = (width, height);
+    }
+
+    pub fn render(&'a self, id: WindowId, mut render_pass: wgpu::RenderPass<'a>) {
+        let Some(instance) = self.instance_table.get(&id) else {
+            return;
+        };
+        if !instance.is_visible {
+            return;
+        }
+
+        render_pass.set_pipeline(&instance.render_pipeline);
+        render_pass.set_vertex_buffer(0, instance.vertex_buffer.slice(..));
+        render_pass.set_index_buffer(instance.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+        render_pass.set_bind_group(0, &instance.bind_group, &[]);
+        render_pass.draw_indexed(0..6, 0, 0..1);
+    }
+}
@@ -4,7 +4,7 @@ use bytemuck::{Pod, Zeroable};
 use wgpu::util::DeviceExt;
 use winit::window::WindowId;
 
-use crate::gfx::content_plotter::Diff;
+use crate::gfx::{content_plotter::Diff, detail::MAX_CELL_COUNT, glyph_writer::ATLAS_PAGE_SIZE};
 
 #[repr(C)]
 #[derive(Debug, Pod, Copy, Clone, Zeroable)]
@@ -14,6 +14,10 @@ struct CharacterData {
     fore_ground_color: [f32; 4],
     uv_bl: [f32; 2],
     uv_tr: [f32; 2],
+
+    // x: カラーのアトラスを使うなら 1
+    // y: アトラスのページ
+    glyph_params: [u32; 4],
 }
 
 pub struct TextRenderer<'a> {
@@ -23,9 +27,10 @@ pub struct TextRenderer<'a> {
     bind_group_table: HashMap<WindowId, wgpu::BindGroup>,
     character_storage_block_table: HashMap<WindowId, wgpu::Buffer>,
     sampler_table: HashMap<WindowId, wgpu::Sampler>,
-    glyph_texture: Option<wgpu::Texture>,
-
-    character_count: u32,
+    bind_group_layout_table: HashMap<WindowId, wgpu::BindGroupLayout>,
+    glyph_texture_table: HashMap<WindowId, wgpu::Texture>,
+    color_glyph_texture_table: HashMap<WindowId, wgpu::Texture>,
+    character_count_table: HashMap<WindowId, u32>,
 
     _phantom_data: PhantomData<&'a ()>,
 }
@@ -39,8 +44,10 @@ impl<'a> TextRenderer<'a> {
             bind_group_table: HashMap::default(),
             character_storage_block_table: HashMap::default(),
             sampler_table: HashMap::default(),
-            glyph_texture: None,
-            character_count: 0,
+            bind_group_layout_table: HashMap::default(),
+            glyph_texture_table: HashMap::default(),
+            color_glyph_texture_table: HashMap::default(),
+            character_count_table: HashMap::default(),
             _phantom_data: Default::default(),
         }
     }
@@ -69,7 +76,7 @@ impl<'a> TextRenderer<'a> {
                     visibility: wgpu::ShaderStages::FRAGMENT,
                     ty: wgpu::BindingType::Texture {
                         sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                        view_dimension: wgpu::TextureViewDimension::D2,
+                        view_dimension: wgpu::TextureViewDimension::D2Array,
                         multisampled: false,
                     },
                     count: None,
@@ -80,6 +87,16 @@ impl<'a> TextRenderer<'a> {
                     ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
                     count: None,
                 },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2Array,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
             ],
         });
 
@@ -155,7 +172,7 @@ impl<'a> TextRenderer<'a> {
         // 文字ごとの情報
         let character_storage_block = device.create_buffer(&wgpu::BufferDescriptor {
             label: None,
-            size: std::mem::size_of::<CharacterData>() as u64 * 32 * 1024,
+            size: (std::mem::size_of::<CharacterData>() * MAX_CELL_COUNT) as u64,
             usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
             mapped_at_creation: false,
         });
@@ -176,52 +193,22 @@ impl<'a> TextRenderer<'a> {
             border_color: None,
         });
 
-        // 文字テクスチャ。一番複雑なところなので最終的には外部で管理する。
-        let texture = device.create_texture(&wgpu::TextureDescriptor {
-            label: None,
-            size: wgpu::Extent3d {
-                width: 4096,
-                height: 4096,
-                depth_or_array_layers: 1,
-            },
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::R8Unorm,
-            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
-            view_formats: &[wgpu::TextureFormat::R8Unorm],
-        });
+        // 文字テクスチャ。アトラスのページを配列のレイヤーに持つ
+        // ページが増えたら作り直すので最初は 1 枚だけ確保する
+        let texture = create_atlas_texture(device, wgpu::TextureFormat::R8Unorm, 1);
+
+        // カラー絵文字のテクスチャ
+        let color_texture = create_atlas_texture(device, wgpu::TextureFormat::Rgba8Unorm, 1);
 
         // リソースたちのバインド設定
-        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: None,
-            layout: &bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: character_storage_block.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::TextureView(&texture.create_view(
-                        &wgpu::TextureViewDescriptor {
-                            label: None,
-                            format: Some(wgpu::TextureFormat::R8Unorm),
-                            dimension: Some(wgpu::TextureViewDimension::D2),
-                            aspect: wgpu::TextureAspect::All,
-                            base_mip_level: 0,
-                            mip_level_count: None,
-                            base_array_layer: 0,
-                            array_layer_count: None,
-                        },
-                    )),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 2,
-                    resource: wgpu::BindingResource::Sampler(&sampler),
-                },
-            ],
-        });
+        let bind_group = create_bind_group(
+            device,
+            &bind_group_layout,
+            &character_storage_block,
+            &texture,
+            &color_texture,
+            &sampler,
+        );
 
         self.pipelie_table.insert(id, render_pipeline);
         self.vertex_buffer_table.insert(id, vertrex_buffer);
@@ -230,18 +217,45 @@ impl<'a> TextRenderer<'a> {
             .insert(id, character_storage_block);
         self.bind_group_table.insert(id, bind_group);
         self.sampler_table.insert(id, sampler);
-        self.glyph_texture = Some(texture);
+        self.bind_group_layout_table.insert(id, bind_group_layout);
+        self.glyph_texture_table.insert(id, texture);
+        self.color_glyph_texture_table.insert(id, color_texture);
+        self.character_count_table.insert(id, 0);
     }
 
-    pub fn update(&mut self, queue: &wgpu::Queue, id: WindowId, diff: &Diff) {
+    pub fn unregister(&mut self, id: WindowId) {
+        self.pipelie_table.remove(&id);
+        self.vertex_buffer_table.remove(&id);
+        self.index_buffer_table.remove(&id);
+        self.bind_group_table.remove(&id);
+        self.character_storage_block_table.remove(&id);
+        self.sampler_table.remove(&id);
+        self.bind_group_layout_table.remove(&id);
+        self.glyph_texture_table.remove(&id);
+        self.color_glyph_texture_table.remove(&id);
+        self.character_count_table.remove(&id);
+    }
+
+    pub fn update(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        id: WindowId,
+        diff: &Diff,
+    ) {
+        // アトラスのページが増えていたらテクスチャーを広げる
+        self.grow_atlas_textures(device, queue, id, diff);
+
         let buffer = self.character_storage_block_table.get(&id).unwrap();
 
         // 文字数
-        self.character_count = diff.item_count() as u32;
+        self.character_count_table
+            .insert(id, diff.item_count().min(MAX_CELL_COUNT as i32) as u32);
 
         let data = diff
             .character_info_array()
             .iter()
+            .filter(|info| info.index < MAX_CELL_COUNT)
             .map(|info| {
                 let t = info.transform;
                 (
@@ -252,6 +266,7 @@ impl<'a> TextRenderer<'a> {
                         fore_ground_color: info.fore_ground_color,
                         uv_bl: [info.uv0[0], info.uv0[1]],
                         uv_tr: [info.uv1[0], info.uv1[1]],
+                        glyph_params: [info.is_color as u32, info.page, 0, 0],
                     },
                 )
             })
@@ -263,8 +278,13 @@ impl<'a> TextRenderer<'a> {
             queue.write_buffer(buffer, offset as u64, binary);
         }
 
-        let texture = self.glyph_texture.as_ref().unwrap();
         for texture_patch in diff.glyph_texture_patches() {
+            // カラー絵文字は RGBA のアトラスに書き込む
+            let (texture, bytes_per_pixel) = if texture_patch.is_color() {
+                (self.color_glyph_texture_table.get(&id).unwrap(), 4)
+            } else {
+                (self.glyph_texture_table.get(&id).unwrap(), 1)
+            };
             if texture_patch.width() == 0
                 || texture.height() == 0
                 || texture_patch.pixels().is_empty()
@@ -278,7 +298,7 @@ impl<'a> TextRenderer<'a> {
                 origin: wgpu::Origin3d {
                     x: texture_patch.offset_x(),
                     y: texture_patch.offset_y(),
-                    z: 0,
+                    z: texture_patch.page(),
                 },
                 aspect: wgpu::TextureAspect::All,
             };
@@ -287,7 +307,7 @@ impl<'a> TextRenderer<'a> {
                 texture_patch.pixels(),
                 wgpu::ImageDataLayout {
                     offset: 0,
-                    bytes_per_row: Some(texture_patch.width()),
+                    bytes_per_row: Some(bytes_per_pixel * texture_patch.width()),
                     rows_per_image: None,
                 },
                 wgpu::Extent3d {
@@ -299,6 +319,82 @@ impl<'a> TextRenderer<'a> {
         }
     }
 
+    fn grow_atlas_textures(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        id: WindowId,
+        diff: &Diff,
+    ) {
+        let required_layer_count = |is_color: bool| {
+            diff.glyph_texture_patches()
+                .iter()
+                .filter(|patch| patch.is_color() == is_color)
+                .map(|patch| patch.page() + 1)
+                .max()
+                .unwrap_or(0)
+        };
+
+        let mut is_grown = false;
+        for (texture_table, is_color) in [
+            (&mut self.glyph_texture_table, false),
+            (&mut self.color_glyph_texture_table, true),
+        ] {
+            let Some(old_texture) = texture_table.get(&id) else {
+                continue;
+            };
+
+            let layer_count = required_layer_count(is_color);
+            if layer_count <= old_texture.depth_or_array_layers() {
+                continue;
+            }
+
+            // 新しいテクスチャーに今までのページをコピーする
+            let new_texture = create_atlas_texture(device, old_texture.format(), layer_count);
+            let mut command_encoder =
+                device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+            command_encoder.copy_texture_to_texture(
+                old_texture.as_image_copy(),
+                new_texture.as_image_copy(),
+                old_texture.size(),
+            );
+            queue.submit(Some(command_encoder.finish()));
+            texture_table.insert(id, new_texture);
+            is_grown = true;
+        }
+
+        if !is_grown {
+            return;
+        }
+
+        // テクスチャーを差し替えたのでバインド設定も作り直す
+        let (
+            Some(bind_group_layout),
+            Some(character_storage_block),
+            Some(sampler),
+            Some(glyph_texture),
+            Some(color_glyph_texture),
+        ) = (
+            self.bind_group_layout_table.get(&id),
+            self.character_storage_block_table.get(&id),
+            self.sampler_table.get(&id),
+            self.glyph_texture_table.get(&id),
+            self.color_glyph_texture_table.get(&id),
+        )
+        else {
+            return;
+        };
+        let bind_group = create_bind_group(
+            device,
+            bind_group_layout,
+            character_storage_block,
+            glyph_texture,
+            color_glyph_texture,
+            sampler,
+        );
+        self.bind_group_table.insert(id, bind_group);
+    }
+
     pub fn render(&'a self, id: WindowId, mut render_pass: wgpu::RenderPass<'a>) {
         let Some(pipeline) = self.pipelie_table.get(&id) else {
             return;
@@ -316,11 +412,79 @@ impl<'a> TextRenderer<'a> {
             return;
         };
 
+        let Some(character_count) = self.character_count_table.get(&id) else {
+            return;
+        };
+
         render_pass.set_pipeline(pipeline);
         render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
         render_pass.set_index_buffer(index_buffer.slice(..), wgpu::IndexFormat::Uint16);
         render_pass.set_bind_group(0, bind_group, &[]);
         render_pass.draw_indexed(0..6, 0, 0..1);
-        render_pass.draw_indexed(0..6, 0, 0..self.character_count);
+        render_pass.draw_indexed(0..6, 0, 0..*character_count);
     }
 }
+
+fn create_atlas_texture(
+    device: &wgpu::Device,
+    format: wgpu::TextureFormat,
+    layer_count: u32,
+) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: None,
+        size: wgpu::Extent3d {
+            width: ATLAS_PAGE_SIZE,
+            height: ATLAS_PAGE_SIZE,
+            depth_or_array_layers: layer_count,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING
+            | wgpu::TextureUsages::COPY_DST
+            | wgpu::TextureUsages::COPY_SRC,
+        view_formats: &[format],
+    })
+}
+
+fn create_bind_group(
+    device: &wgpu::Device,
+    bind_group_layout: &wgpu::BindGroupLayout,
+    character_storage_block: &wgpu::Buffer,
+    glyph_texture: &wgpu::Texture,
+    color_glyph_texture: &wgpu::Texture,
+    sampler: &wgpu::Sampler,
+) -> wgpu::BindGroup {
+    // レイヤーが 1 枚でも配列として扱う
+    let view_descriptor = wgpu::TextureViewDescriptor {
+        dimension: Some(wgpu::TextureViewDimension::D2Array),
+        ..Default::default()
+    };
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: None,
+        layout: bind_group_layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: character_storage_block.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::TextureView(
+                    &glyph_texture.create_view(&view_descriptor),
+                ),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::Sampler(sampler),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: wgpu::BindingResource::TextureView(
+                    &color_glyph_texture.create_view(&view_descriptor),
+                ),
+            },
+        ],
+    })
+}
@@ -0,0 +1,336 @@
+use std::{collections::HashMap, hash::Hash};
+
+// グリフの間に空けるピクセル数
+// 線形補間で隣のグリフがにじまないようにする
+pub const PADDING: u32 = 1;
+
+// アトラス上のグリフの位置
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AtlasRegion {
+    pub page: u32,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+pub struct Insertion<K> {
+    pub region: AtlasRegion,
+
+    // 場所を空けるために追い出したグリフ
+    pub evicted: Vec<K>,
+}
+
+// 高さのそろったグリフを横に並べる棚
+struct Shelf {
+    y: u32,
+    height: u32,
+
+    // 空いている区間 (x, 幅)
+    free_spans: Vec<(u32, u32)>,
+}
+
+impl Shelf {
+    fn new(y: u32, height: u32, width: u32) -> Self {
+        Self {
+            y,
+            height,
+            free_spans: vec![(0, width)],
+        }
+    }
+
+    fn is_empty(&self, page_size: u32) -> bool {
+        self.free_spans == [(0, page_size)]
+    }
+
+    // 高すぎる棚に低いグリフを置くと無駄が多いので空の棚以外は高さの近いものだけ使う
+    fn fits(&self, height: u32, page_size: u32) -> bool {
+        height <= self.height && (self.height <= height * 3 / 2 + 2 || self.is_empty(page_size))
+    }
+
+    fn allocate(&mut self, width: u32) -> Option<u32> {
+        let index = self.free_spans.iter().position(|(_, w)| width <= *w)?;
+        let (x, span_width) = self.free_spans[index];
+        if span_width == width {
+            self.free_spans.remove(index);
+        } else {
+            self.free_spans[index] = (x + width, span_width - width);
+        }
+        Some(x)
+    }
+
+    fn free(&mut self, x: u32, width: u32) {
+        self.free_spans.push((x, width));
+        self.free_spans.sort_unstable();
+
+        // 隣り合った区間はつなげる
+        let mut merged: Vec<(u32, u32)> = Vec::with_capacity(self.free_spans.len());
+        for (x, width) in self.free_spans.drain(..) {
+            match merged.last_mut() {
+                Some((last_x, last_width)) if *last_x + *last_width == x => *last_width += width,
+                _ => merged.push((x, width)),
+            }
+        }
+        self.free_spans = merged;
+    }
+}
+
+#[derive(Default)]
+struct Page {
+    shelves: Vec<Shelf>,
+    used_height: u32,
+}
+
+struct Entry {
+    region: AtlasRegion,
+    last_used: u64,
+}
+
+// グリフを実際の大きさで棚詰めするアトラス
+// 足りなくなったらページを増やし、上限に達したら長く使っていないグリフから追い出す
+// GPU のリソースは持たないのでテクスチャーへの反映は呼び出し側で行う
+pub struct GlyphAtlas<K> {
+    page_size: u32,
+    max_page_count: u32,
+    pages: Vec<Page>,
+    entries: HashMap<K, Entry>,
+
+    // 今のフレームで使ったグリフは追い出さない
+    frame: u64,
+}
+
+impl<K: Copy + Eq + Hash> GlyphAtlas<K> {
+    pub fn new(page_size: u32, max_page_count: u32) -> Self {
+        Self {
+            page_size,
+            max_page_count,
+            pages: Vec::new(),
+            entries: HashMap::default(),
+            frame: 0,
+        }
+    }
+
+    pub fn page_size(&self) -> u32 {
+        self.page_size
+    }
+
+    pub fn page_count(&self) -> u32 {
+        self.pages.len() as u32
+    }
+
+    pub fn begin_frame(&mut self) {
+        self.frame += 1;
+    }
+
+    // 使ったことを記録して位置を返す
+    pub fn get(&mut self, key: &K) -> Option<AtlasRegion> {
+        let entry = self.entries.get_mut(key)?;
+        entry.last_used = self.frame;
+        Some(entry.region)
+    }
+
+    // 使ったことを記録せずに位置を返す
+    pub fn peek(&self, key: &K) -> Option<AtlasRegion> {
+        self.entries.get(key).map(|entry| entry.region)
+    }
+
+    pub fn insert(&mut self, key: K, width: u32, height: u32) -> Option<Insertion<K>> {
+        if let Some(region) = self.get(&key) {
+            return Some(Insertion {
+                region,
+                evicted: Vec::new(),
+            });
+        }
+
+        // ページに収まらないグリフは置けない
+        let (padded_width, padded_height) = (width + PADDING, height + PADDING);
+        if self.page_size < padded_width || self.page_size < padded_height {
+            return None;
+        }
+
+        let mut evicted = Vec::new();
+        loop {
+            if let Some((page, x, y)) = self.allocate(padded_width, padded_height) {
+                let region = AtlasRegion {
+                    page,
+                    x,
+                    y,
+                    width,
+                    height,
+                };
+                self.entries.insert(
+                    key,
+                    Entry {
+                        region,
+                        last_used: self.frame,
+                    },
+                );
+                return Some(Insertion { region, evicted });
+            }
+
+            if self.page_count() < self.max_page_count {
+                self.pages.push(Page::default());
+                continue;
+            }
+
+            // 追い出せるグリフがなければあきらめる
+            evicted.push(self.evict_least_recently_used()?);
+        }
+    }
+
+    fn allocate(&mut self, width: u32, height: u32) -> Option<(u32, u32, u32)> {
+        let page_size = self.page_size;
+
+        // 既存の棚に空きがあればそこに置く
+        for (page_index, page) in self.pages.iter_mut().enumerate() {
+            for shelf in page.shelves.iter_mut() {
+                if !shelf.fits(height, page_size) {
+                    continue;
+                }
+
+                if let Some(x) = shelf.allocate(width) {
+                    return Some((page_index as u32, x, shelf.y));
+                }
+            }
+        }
+
+        // 棚を新しく追加する
+        for (page_index, page) in self.pages.iter_mut().enumerate() {
+            if page_size < page.used_height + height {
+                continue;
+            }
+
+            let mut shelf = Shelf::new(page.used_height, height, page_size);
+            let x = shelf.allocate(width)?;
+            let y = shelf.y;
+            page.used_height += height;
+            page.shelves.push(shelf);
+            return Some((page_index as u32, x, y));
+        }
+
+        None
+    }
+
+    fn evict_least_recently_used(&mut self) -> Option<K> {
+        let (key, region) = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| entry.last_used < self.frame)
+            .min_by_key(|(_, entry)| entry.last_used)
+            .map(|(key, entry)| (*key, entry.region))?;
+        self.entries.remove(&key);
+
+        let page = &mut self.pages[region.page as usize];
+        let shelf = page
+            .shelves
+            .iter_mut()
+            .find(|shelf| shelf.y == region.y)
+            .unwrap();
+        shelf.free(region.x, region.width + PADDING);
+        Some(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AtlasRegion, GlyphAtlas};
+
+    // 同じ高さのグリフは同じ棚に横に並ぶ
+    #[test]
+    fn pack_into_shelf() {
+        let mut atlas = GlyphAtlas::new(64, 1);
+        let a = atlas.insert('a', 10, 20).unwrap().region;
+        let b = atlas.insert('b', 12, 20).unwrap().region;
+        let c = atlas.insert('c', 10, 30).unwrap().region;
+        assert_eq!(
+            a,
+            AtlasRegion {
+                page: 0,
+                x: 0,
+                y: 0,
+                width: 10,
+                height: 20
+            }
+        );
+        assert_eq!((b.x, b.y), (11, 0));
+        assert_eq!((c.x, c.y), (0, 21));
+    }
+
+    // 同じキーは同じ場所を返す
+    #[test]
+    fn insert_twice() {
+        let mut atlas = GlyphAtlas::new(64, 1);
+        let first = atlas.insert('a', 10, 20).unwrap().region;
+        let second = atlas.insert('a', 10, 20).unwrap().region;
+        assert_eq!(first, second);
+        assert_eq!(atlas.peek(&'a'), Some(first));
+    }
+
+    // ページが埋まったら次のページを追加する
+    #[test]
+    fn add_page() {
+        let mut atlas = GlyphAtlas::new(32, 2);
+        let a = atlas.insert('a', 30, 30).unwrap().region;
+        let b = atlas.insert('b', 30, 30).unwrap().region;
+        assert_eq!(a.page, 0);
+        assert_eq!(b.page, 1);
+        assert_eq!(atlas.page_count(), 2);
+    }
+
+    // ページに収まらないグリフは置けない
+    #[test]
+    fn too_large() {
+        let mut atlas = GlyphAtlas::<char>::new(32, 1);
+        assert!(atlas.insert('a', 40, 10).is_none());
+        assert_eq!(atlas.page_count(), 0);
+    }
+
+    // 一番長く使っていないグリフから追い出して場所を再利用する
+    #[test]
+    fn evict_least_recently_used() {
+        let mut atlas = GlyphAtlas::new(32, 1);
+        atlas.insert('a', 15, 15).unwrap();
+        atlas.begin_frame();
+        atlas.insert('b', 15, 15).unwrap();
+        atlas.begin_frame();
+        atlas.get(&'a');
+        atlas.insert('c', 15, 15).unwrap();
+        atlas.begin_frame();
+        atlas.insert('d', 15, 15).unwrap();
+        atlas.begin_frame();
+
+        let insertion = atlas.insert('e', 15, 15).unwrap();
+        assert_eq!(insertion.evicted, vec!['b']);
+        assert_eq!((insertion.region.x, insertion.region.y), (16, 0));
+        assert!(atlas.peek(&'b').is_none());
+        assert!(atlas.peek(&'a').is_some());
+    }
+
+    // 今のフレームで使ったグリフは追い出さない
+    #[test]
+    fn keep_glyphs_in_current_frame() {
+        let mut atlas = GlyphAtlas::new(16, 1);
+        atlas.insert('a', 15, 15).unwrap();
+        assert!(atlas.insert('b', 15, 15).is_none());
+        assert!(atlas.peek(&'a').is_some());
+
+        atlas.begin_frame();
+        let insertion = atlas.insert('b', 15, 15).unwrap();
+        assert_eq!(insertion.evicted, vec!['a']);
+    }
+
+    // 空いた区間はつながって大きなグリフも置ける
+    #[test]
+    fn merge_free_spans() {
+        let mut atlas = GlyphAtlas::new(32, 1);
+        atlas.insert('a', 15, 15).unwrap();
+        atlas.insert('b', 15, 15).unwrap();
+        atlas.insert('c', 31, 15).unwrap();
+        atlas.begin_frame();
+        atlas.get(&'c');
+
+        let insertion = atlas.insert('d', 31, 15).unwrap();
+        assert_eq!(insertion.evicted.len(), 2);
+        assert_eq!((insertion.region.x, insertion.region.y), (0, 0));
+    }
+}
@@ -1,45 +1,272 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
-use crossfont::{BitmapBuffer, FontDesc, Rasterize, RasterizedGlyph, Slant, Style, Weight};
+use alacritty_terminal::term::cell::Flags;
+use crossfont::{
+    BitmapBuffer, FontDesc, FontKey, Rasterize, RasterizedGlyph, Rasterizer, Size, Slant, Style,
+    Weight,
+};
+
+use crate::config::{Font, FontFace};
+
+use super::{
+    builtin_glyph,
+    text_shaper::{ShapedCluster, TextShaper},
+    CellMetrics,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FontStyle {
+    Regular,
+    Bold,
+    Italic,
+    BoldItalic,
+}
+
+impl FontStyle {
+    pub fn from_flags(flags: Flags) -> Self {
+        match (flags.contains(Flags::BOLD), flags.contains(Flags::ITALIC)) {
+            (false, false) => Self::Regular,
+            (true, false) => Self::Bold,
+            (false, true) => Self::Italic,
+            (true, true) => Self::BoldItalic,
+        }
+    }
+
+    fn slant_weight(self) -> (Slant, Weight) {
+        match self {
+            Self::Regular => (Slant::Normal, Weight::Normal),
+            Self::Bold => (Slant::Normal, Weight::Bold),
+            Self::Italic => (Slant::Italic, Weight::Normal),
+            Self::BoldItalic => (Slant::Italic, Weight::Bold),
+        }
+    }
+}
+
+// システムのフォールバックを覚えておく文字の範囲の大きさ
+// 近い文字は同じフォントにあることが多いので、範囲ごとに最後に見つかったフォントから探す
+const SYSTEM_FALLBACK_RANGE_SIZE: u32 = 128;
+
+// 1 つのセルに重ねられる結合文字の数
+// これを超えた分は描画しない
+pub const MAX_COMBINING_CHARS: usize = 4;
+
+// ラスタライズしたグリフを引くためのキー
+// 結合文字は基底文字とまとめて 1 つのグリフにする
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct GlyphKey {
+    pub code: char,
+    pub style: FontStyle,
+
+    // 結合文字。空きは '\0'
+    combining: [char; MAX_COMBINING_CHARS],
+
+    // 整形済みならそのグリフで描く
+    shaped: Option<ShapedCluster>,
+}
+
+impl GlyphKey {
+    pub fn new(code: char, style: FontStyle) -> Self {
+        Self {
+            code,
+            style,
+            combining: ['\0'; MAX_COMBINING_CHARS],
+            shaped: None,
+        }
+    }
+
+    pub fn with_shaped(mut self, shaped: ShapedCluster) -> Self {
+        self.shaped = Some(shaped);
+        self
+    }
+
+    pub fn with_combining(mut self, combining: &[char]) -> Self {
+        for (dst, src) in self.combining.iter_mut().zip(combining) {
+            *dst = *src;
+        }
+        self
+    }
+
+    pub fn combining(&self) -> impl Iterator<Item = char> + '_ {
+        self.combining.iter().copied().take_while(|c| *c != '\0')
+    }
+
+    pub fn has_combining(&self) -> bool {
+        self.combining[0] != '\0'
+    }
+}
+
+impl From<char> for GlyphKey {
+    fn from(code: char) -> Self {
+        Self::new(code, FontStyle::Regular)
+    }
+}
+
+// どのフォントのどのグリフか
+// フォールバックで同じフォントに行き着いたグリフはアトラスを共有する
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct GlyphId {
+    // 空白と豆腐はフォントに依らないので None
+    font_key: Option<FontKey>,
+    code: char,
+    combining: [char; MAX_COMBINING_CHARS],
+
+    // 整形したグリフは整形に使ったフォントのグリフ番号で区別する
+    shaped: Option<(FontStyle, ShapedCluster)>,
+}
+
+impl GlyphId {
+    // どのフォントにもなかった文字
+    const TOFU: Self = Self {
+        font_key: None,
+        code: '\0',
+        combining: ['\0'; MAX_COMBINING_CHARS],
+        shaped: None,
+    };
+}
+
+// スタイルごとのフォントの連なり
+// 先頭が設定したフォントで、見つからない文字は後ろのフォントから探す
+struct FontKeys {
+    regular: Vec<FontKey>,
+    bold: Vec<FontKey>,
+    italic: Vec<FontKey>,
+    bold_italic: Vec<FontKey>,
+}
+
+impl FontKeys {
+    fn get(&self, style: FontStyle) -> &[FontKey] {
+        match style {
+            FontStyle::Regular => &self.regular,
+            FontStyle::Bold => &self.bold,
+            FontStyle::Italic => &self.italic,
+            FontStyle::BoldItalic => &self.bold_italic,
+        }
+    }
+}
 
 pub struct GlyphManager {
     rasterizer: crossfont::Rasterizer,
-    font_key: crossfont::FontKey,
-    rasterized_glyph_table: HashMap<char, RasterizedGlyph>,
+    font_keys: FontKeys,
+
+    // 設定したフォントのどれにもなかった文字のためにシステムから探したフォント
+    // スタイルと文字の範囲ごとに使うときになってから読み込む
+    system_fallback_table: HashMap<(FontStyle, u32), FontKey>,
+    font_size: crossfont::Size,
+    rasterized_glyph_table: HashMap<GlyphId, RasterizedGlyph>,
+    glyph_id_table: HashMap<GlyphKey, GlyphId>,
+
+    // 整形しない設定なら None
+    text_shaper: Option<TextShaper>,
+
+    // フォントから求めたセルの大きさ
+    cell_metrics: CellMetrics,
+
+    // 生成に使ったフォント設定。変更検知に使う
+    font: Font,
 }
 
 impl GlyphManager {
+    #[allow(dead_code)]
     pub fn new() -> Self {
+        Self::new_with_font(&Font::default())
+    }
+
+    pub fn new_with_font(font: &Font) -> Self {
         let mut rasterizer = crossfont::Rasterizer::new().unwrap();
-        let font_key = rasterizer
-            .load_font(
-                &FontDesc::new(
-                    #[cfg(not(any(target_os = "macos", windows)))]
-                    "monospace",
-                    #[cfg(target_os = "macos")]
-                    "Menlo",
-                    #[cfg(target_os = "windows")]
-                    "Consolas",
-                    Style::Description {
-                        slant: Slant::Normal,
-                        weight: Weight::Normal,
-                    },
-                ),
-                crossfont::Size::new(32.0),
+        let font_size = Size::new(font.size);
+        let (regular, regular_request) = load_face(
+            &mut rasterizer,
+            FaceRequest::new(&font.normal, Slant::Normal, Weight::Normal),
+            font_size,
+        )
+        .unwrap();
+
+        // 太字や斜体はファミリーが未指定なら normal と同じファミリーを使う
+        // 読み込めなかったら通常のフォントで代用する
+        let mut load_variant = |face: &FontFace, slant: Slant, weight: Weight| {
+            let face = FontFace {
+                family: face.family.clone().or_else(|| font.normal.family.clone()),
+                style: face.style.clone(),
+            };
+            load_face(
+                &mut rasterizer,
+                FaceRequest::new(&face, slant, weight),
+                font_size,
             )
-            .unwrap();
+            .unwrap_or_else(|_| (regular, regular_request.clone()))
+        };
+        let (bold, bold_request) = load_variant(&font.bold, Slant::Normal, Weight::Bold);
+        let (italic, italic_request) = load_variant(&font.italic, Slant::Italic, Weight::Normal);
+        let (bold_italic, bold_italic_request) =
+            load_variant(&font.bold_italic, Slant::Italic, Weight::Bold);
+
+        // 設定したフォールバックを後ろにつなげる
+        // それでも見つからない文字はシステムのフォールバックから探す
+        let mut with_fallback = |primary: FontKey, style: FontStyle| {
+            let (slant, weight) = style.slant_weight();
+            let style = Style::Description { slant, weight };
+            let fallbacks = font.fallback.iter().filter_map(|family| {
+                let desc = FontDesc::new(family, style.clone());
+                rasterizer.load_font(&desc, font_size).ok()
+            });
+            std::iter::once(primary)
+                .chain(fallbacks)
+                .collect::<Vec<_>>()
+        };
+        let font_keys = FontKeys {
+            regular: with_fallback(regular, FontStyle::Regular),
+            bold: with_fallback(bold, FontStyle::Bold),
+            italic: with_fallback(italic, FontStyle::Italic),
+            bold_italic: with_fallback(bold_italic, FontStyle::BoldItalic),
+        };
+
+        let metrics = rasterizer.metrics(regular, font_size).unwrap();
+        let cell_metrics = CellMetrics::new(&metrics, font.offset, font.glyph_offset);
+
+        let mut rasterized_glyph_table = HashMap::default();
+        rasterized_glyph_table.insert(GlyphId::TOFU, tofu(&cell_metrics));
         Self {
             rasterizer,
-            font_key,
-            rasterized_glyph_table: HashMap::default(),
+            font_keys,
+            system_fallback_table: HashMap::default(),
+            font_size,
+            rasterized_glyph_table,
+            glyph_id_table: HashMap::default(),
+            text_shaper: font.shaping.then(|| {
+                TextShaper::new(
+                    &regular_request,
+                    &bold_request,
+                    &italic_request,
+                    &bold_italic_request,
+                    font_size,
+                )
+            }),
+            cell_metrics,
+            font: font.clone(),
         }
     }
 
+    pub fn font(&self) -> &Font {
+        &self.font
+    }
+
+    pub fn cell_metrics(&self) -> &CellMetrics {
+        &self.cell_metrics
+    }
+
+    // 同じスタイルのセルの並びを整形する
+    // 整形しない設定なら None
+    pub fn shape(&self, style: FontStyle, texts: &[&str]) -> Option<Vec<Option<ShapedCluster>>> {
+        self.text_shaper
+            .as_ref()
+            .map(|text_shaper| text_shaper.shape(style, texts))
+    }
+
     #[allow(dead_code)]
     pub fn extract_alphabet(&mut self) {
         // アルファベットをあらかじめ抽出しておく
         for char_code in 'A'..='z' {
-            self.extract(char_code);
+            self.extract(char_code.into());
         }
     }
 
@@ -48,12 +275,86 @@ impl GlyphManager {
         self.extract_alphabet();
     }
 
-    pub fn extract(&mut self, code: char) -> bool {
+    // どのフォントにもなければ豆腐になるので必ずグリフが決まる
+    pub fn extract(&mut self, key: GlyphKey) -> GlyphId {
         // すでに抽出済み
-        if self.rasterized_glyph_table.contains_key(&code) {
-            return true;
+        if let Some(glyph_id) = self.glyph_id_table.get(&key) {
+            return *glyph_id;
         }
 
+        // 整形したグリフが描けなければ文字から描く
+        if let Some(shaped) = key.shaped {
+            if let Some(rasterized_glyph) = self.rasterize_shaped(key.style, &shaped) {
+                let glyph_id = GlyphId {
+                    font_key: None,
+                    code: '\0',
+                    combining: ['\0'; MAX_COMBINING_CHARS],
+                    shaped: Some((key.style, shaped)),
+                };
+                self.rasterized_glyph_table
+                    .entry(glyph_id)
+                    .or_insert(rasterized_glyph);
+                self.glyph_id_table.insert(key, glyph_id);
+                return glyph_id;
+            }
+        }
+
+        let rasterized_glyph = if key.has_combining() {
+            self.rasterize_cluster(key)
+        } else {
+            self.rasterize(key.code, key.style)
+        };
+
+        let glyph_id = match rasterized_glyph {
+            Some((font_key, rasterized_glyph)) => {
+                let glyph_id = GlyphId {
+                    font_key,
+                    code: key.code,
+                    combining: key.combining,
+                    shaped: None,
+                };
+                self.rasterized_glyph_table
+                    .entry(glyph_id)
+                    .or_insert(rasterized_glyph);
+                glyph_id
+            }
+            None => GlyphId::TOFU,
+        };
+
+        self.glyph_id_table.insert(key, glyph_id);
+        glyph_id
+    }
+
+    // アトラスから追い出したグリフを忘れる
+    // 豆腐は作り直せないので残す
+    pub fn evict(&mut self, glyph_ids: &HashSet<GlyphId>) {
+        self.rasterized_glyph_table
+            .retain(|glyph_id, _| *glyph_id == GlyphId::TOFU || !glyph_ids.contains(glyph_id));
+        self.glyph_id_table
+            .retain(|_, glyph_id| !glyph_ids.contains(glyph_id));
+    }
+
+    pub fn get_rasterized_glyph(&self, key: GlyphKey) -> &RasterizedGlyph {
+        let glyph_id = self
+            .glyph_id_table
+            .get(&key)
+            .copied()
+            .unwrap_or(GlyphId::TOFU);
+        self.get_rasterized_glyph_by_id(glyph_id)
+    }
+
+    pub fn get_rasterized_glyph_by_id(&self, glyph_id: GlyphId) -> &RasterizedGlyph {
+        match self.rasterized_glyph_table.get(&glyph_id) {
+            Some(glyph) => glyph,
+            None => &self.rasterized_glyph_table[&GlyphId::TOFU],
+        }
+    }
+
+    fn rasterize(
+        &mut self,
+        code: char,
+        style: FontStyle,
+    ) -> Option<(Option<FontKey>, RasterizedGlyph)> {
         // 空白だけ特別扱い
         if code == ' ' {
             let mut buffer = Vec::default();
@@ -67,46 +368,299 @@ impl GlyphManager {
                 advance: (0, 0),
                 buffer: BitmapBuffer::Rgb(buffer),
             };
-            self.rasterized_glyph_table.insert(' ', space);
-            return true;
+            return Some((None, space));
         }
 
-        // ラスタライズに失敗した
-        let Ok(rasterized_glyph) = self.rasterizer.get_glyph(crossfont::GlyphKey {
-            character: code,
-            font_key: self.font_key,
-            size: crossfont::Size::new(32.0),
-        }) else {
-            return false;
-        };
+        // 罫線などはフォントを使わずにセルの大きさぴったりに描く
+        if let Some(glyph) = builtin_glyph::rasterize(code, &self.cell_metrics) {
+            return Some((None, glyph));
+        }
 
-        self.rasterized_glyph_table.insert(code, rasterized_glyph);
-        true
+        // 先頭のフォントから順に探して最初に見つかったグリフを使う
+        let font_keys = self.font_keys.get(style);
+        let glyph = font_keys.iter().find_map(|font_key| {
+            let glyph = get_glyph(&mut self.rasterizer, *font_key, code, self.font_size)?;
+            Some((Some(*font_key), glyph))
+        });
+        match glyph {
+            Some(glyph) => Some(glyph),
+            None => self.rasterize_with_system_fallback(code, style),
+        }
     }
 
-    pub fn get_rasterized_glyph(&self, code: char) -> &RasterizedGlyph {
-        let Some(glyph) = self.rasterized_glyph_table.get(&code) else {
-            return self.rasterized_glyph_table.get(&' ').unwrap();
+    // 同じ範囲の文字で見つけたフォントになければ、その文字を持つフォントをシステムから探して覚えなおす
+    fn rasterize_with_system_fallback(
+        &mut self,
+        code: char,
+        style: FontStyle,
+    ) -> Option<(Option<FontKey>, RasterizedGlyph)> {
+        let range = (style, code as u32 / SYSTEM_FALLBACK_RANGE_SIZE);
+        if let Some(font_key) = self.system_fallback_table.get(&range).copied() {
+            if let Some(glyph) = get_glyph(&mut self.rasterizer, font_key, code, self.font_size) {
+                return Some((Some(font_key), glyph));
+            }
+        }
+
+        let (slant, weight) = style.slant_weight();
+        let family = system_fallback_family(code, slant, weight)?;
+        let desc = FontDesc::new(family, Style::Description { slant, weight });
+        let font_key = self.rasterizer.load_font(&desc, self.font_size).ok()?;
+        let glyph = get_glyph(&mut self.rasterizer, font_key, code, self.font_size)?;
+        self.system_fallback_table.insert(range, font_key);
+        Some((Some(font_key), glyph))
+    }
+
+    // 基底文字と結合文字を同じ原点に重ねて 1 つのグリフにする
+    fn rasterize_cluster(&mut self, key: GlyphKey) -> Option<(Option<FontKey>, RasterizedGlyph)> {
+        let (font_key, base) = self.rasterize(key.code, key.style)?;
+
+        // 空白の上の結合文字は結合文字だけを描く
+        let mut glyphs = Vec::new();
+        if key.code != ' ' {
+            glyphs.push(base.clone());
+        }
+        // 異体字セレクターは見た目を選ぶだけで描くものはない
+        for code in key.combining().filter(|c| !is_variation_selector(*c)) {
+            if let Some((_, glyph)) = self.rasterize(code, key.style) {
+                glyphs.push(glyph);
+            }
+        }
+
+        // 重ねるものがなければカラー絵文字の色を保つためにそのまま使う
+        if glyphs.len() <= 1 {
+            return Some((font_key, base));
+        }
+
+        let Some(mut cluster) = compose(&glyphs) else {
+            return Some((font_key, base));
         };
+        cluster.character = key.code;
+        cluster.advance = base.advance;
+        Some((font_key, cluster))
+    }
+
+    // 整形したグリフをセルの原点からのオフセットどおりに重ねる
+    fn rasterize_shaped(
+        &mut self,
+        style: FontStyle,
+        shaped: &ShapedCluster,
+    ) -> Option<RasterizedGlyph> {
+        let text_shaper = self.text_shaper.as_mut()?;
+        let mut glyphs = text_shaper.rasterize(style, shaped);
+
+        // 重ねるものがなければカラー絵文字の色を保つためにそのまま使う
+        if glyphs.len() <= 1 {
+            return glyphs.pop();
+        }
+        compose(&glyphs)
+    }
+}
+
+fn get_glyph(
+    rasterizer: &mut Rasterizer,
+    font_key: FontKey,
+    code: char,
+    size: Size,
+) -> Option<RasterizedGlyph> {
+    rasterizer
+        .get_glyph(crossfont::GlyphKey {
+            character: code,
+            font_key,
+            size,
+        })
+        .ok()
+}
 
-        glyph
+fn is_variation_selector(code: char) -> bool {
+    matches!(code, '\u{FE00}'..='\u{FE0F}')
+}
+
+// セルいっぱいの枠
+fn tofu(cell_metrics: &CellMetrics) -> RasterizedGlyph {
+    let width = cell_metrics.cell_width() as i32;
+    let height = cell_metrics.cell_height() as i32;
+
+    // 隣のセルとくっつかないように 1 ピクセル内側に描く
+    let mut buffer = vec![0u8; (3 * width * height) as usize];
+    for y in 1..(height - 1) {
+        for x in 1..(width - 1) {
+            let is_edge = x == 1 || x == width - 2 || y == 1 || y == height - 2;
+            if is_edge {
+                let index = 3 * (x + y * width) as usize;
+                buffer[index..index + 3].fill(255);
+            }
+        }
     }
 
-    pub fn acquire_rasterized_glyph(&mut self, code: char) -> Option<&RasterizedGlyph> {
-        if !self.extract(code) {
-            return None;
+    // セルの左上にぴったり置かれるように上端をベースラインに合わせる
+    RasterizedGlyph {
+        character: '\0',
+        width,
+        height,
+        top: cell_metrics.baseline(),
+        left: 0,
+        advance: (width, 0),
+        buffer: BitmapBuffer::Rgb(buffer),
+    }
+}
+
+// ベースラインと左端を揃えてグリフを重ね合わせる
+fn compose(glyphs: &[RasterizedGlyph]) -> Option<RasterizedGlyph> {
+    let left = glyphs.iter().map(|g| g.left).min()?;
+    let right = glyphs.iter().map(|g| g.left + g.width).max()?;
+    let top = glyphs.iter().map(|g| g.top).max()?;
+    let bottom = glyphs.iter().map(|g| g.top - g.height).min()?;
+    let width = (right - left).max(0);
+    let height = (top - bottom).max(0);
+
+    let mut buffer = vec![0u8; (3 * width * height) as usize];
+    for glyph in glyphs {
+        let (offset_x, offset_y) = (glyph.left - left, top - glyph.top);
+        for y in 0..glyph.height {
+            for x in 0..glyph.width {
+                let src_index = (x + y * glyph.width) as usize;
+                let dst_index = 3 * ((offset_x + x) + (offset_y + y) * width) as usize;
+                let coverage = match &glyph.buffer {
+                    BitmapBuffer::Rgb(buffer) => [
+                        buffer[3 * src_index],
+                        buffer[3 * src_index + 1],
+                        buffer[3 * src_index + 2],
+                    ],
+                    BitmapBuffer::Rgba(buffer) => [buffer[4 * src_index + 3]; 3],
+                };
+                for (dst, src) in buffer[dst_index..dst_index + 3].iter_mut().zip(coverage) {
+                    *dst = (*dst).max(src);
+                }
+            }
+        }
+    }
+
+    Some(RasterizedGlyph {
+        character: glyphs[0].character,
+        width,
+        height,
+        top,
+        left,
+        advance: glyphs[0].advance,
+        buffer: BitmapBuffer::Rgb(buffer),
+    })
+}
+
+// crossfont に読ませるフォントの指定
+// 整形でも同じフォントファイルを探せるように読み込めた指定を覚えておく
+#[derive(Debug, Clone)]
+pub struct FaceRequest {
+    pub family: String,
+
+    // 指定があればスタイル名で探し、なければ太さと傾きで探す
+    pub style: Option<String>,
+    pub slant: Slant,
+    pub weight: Weight,
+}
+
+impl FaceRequest {
+    fn new(face: &FontFace, slant: Slant, weight: Weight) -> Self {
+        Self {
+            family: face.family.clone().unwrap_or_else(default_font_family),
+            style: face.style.clone(),
+            slant,
+            weight,
         }
+    }
 
-        self.rasterized_glyph_table.get(&code)
+    pub fn font_desc(&self) -> FontDesc {
+        let style = match &self.style {
+            Some(style) => Style::Specific(style.clone()),
+            None => Style::Description {
+                slant: self.slant,
+                weight: self.weight,
+            },
+        };
+        FontDesc::new(self.family.clone(), style)
     }
 }
 
+fn load_face(
+    rasterizer: &mut Rasterizer,
+    request: FaceRequest,
+    size: Size,
+) -> Result<(FontKey, FaceRequest), crossfont::Error> {
+    // 指定のフォントが見つからなかったら既定のフォントで読み直す
+    if let Ok(font_key) = rasterizer.load_font(&request.font_desc(), size) {
+        return Ok((font_key, request));
+    }
+    let request = FaceRequest {
+        family: default_font_family(),
+        ..request
+    };
+    let font_key = rasterizer.load_font(&request.font_desc(), size)?;
+    Ok((font_key, request))
+}
+
+// 設定したフォントのどれにもない文字を持つフォントのファミリーを fontconfig に探させる
+#[cfg(not(any(target_os = "macos", windows)))]
+fn system_fallback_family(code: char, slant: Slant, weight: Weight) -> Option<String> {
+    use crossfont::ft::fc;
+
+    let config = fc::Config::get_current();
+    let mut charset = fc::CharSet::new();
+    charset.add(code);
+    let mut pattern = fc::Pattern::new();
+    pattern.add_charset(&charset);
+    pattern.set_weight(weight.into());
+    pattern.set_slant(slant.into());
+    pattern.config_substitute(config, fc::MatchKind::Pattern);
+    pattern.default_substitute();
+
+    // いちばん近いフォントが返ってくるので、本当にその文字を持っているか確かめる
+    let font = fc::font_match(config, &pattern)?;
+    if !font.get_charset()?.has_char(code) {
+        return None;
+    }
+    font.family().next().map(str::to_string)
+}
+
+// CoreText や DirectWrite はフォントごとにフォールバックを持っている
+#[cfg(any(target_os = "macos", windows))]
+fn system_fallback_family(_code: char, _slant: Slant, _weight: Weight) -> Option<String> {
+    None
+}
+
+fn default_font_family() -> String {
+    #[cfg(not(any(target_os = "macos", windows)))]
+    let family = "monospace";
+
+    #[cfg(target_os = "macos")]
+    let family = "Menlo";
+
+    #[cfg(target_os = "windows")]
+    let family = "Consolas";
+
+    family.to_string()
+}
+
 #[cfg(test)]
 mod tests {
+    use std::collections::HashSet;
+
     use bmp::Image;
-    use crossfont::BitmapBuffer;
+    use crossfont::{BitmapBuffer, RasterizedGlyph};
+
+    use crate::{config::Delta, gfx::CellMetrics};
+
+    use super::{compose, tofu, GlyphKey, GlyphManager};
 
-    use super::GlyphManager;
+    fn filled_glyph(left: i32, top: i32, width: i32, height: i32) -> RasterizedGlyph {
+        RasterizedGlyph {
+            character: 'a',
+            width,
+            height,
+            top,
+            left,
+            advance: (8, 0),
+            buffer: BitmapBuffer::Rgb(vec![255; (3 * width * height) as usize]),
+        }
+    }
 
     // グリフ抽出の検証
     // リポジトリのルートに「愛」が出力される
@@ -115,7 +669,7 @@ mod tests {
         let mut glyph_manager = GlyphManager::new();
         glyph_manager.extract_alphabet();
         let (buffer, width, height) = {
-            let rasterized_glyph = &glyph_manager.get_rasterized_glyph('K');
+            let rasterized_glyph = &glyph_manager.get_rasterized_glyph('K'.into());
             let buffer = &rasterized_glyph.buffer;
             match buffer {
                 BitmapBuffer::Rgb(buffer) => {
@@ -139,4 +693,67 @@ mod tests {
 
         image.save("image.png").unwrap();
     }
+
+    // 追い出したグリフは表から消えて、次に使うときにラスタライズしなおす
+    #[test]
+    fn evict() {
+        let mut glyph_manager = GlyphManager::new();
+        let glyph_id = glyph_manager.extract('a'.into());
+        glyph_manager.evict(&HashSet::from([glyph_id]));
+        assert!(!glyph_manager.rasterized_glyph_table.contains_key(&glyph_id));
+        assert!(!glyph_manager
+            .glyph_id_table
+            .contains_key(&GlyphKey::from('a')));
+
+        assert_eq!(glyph_manager.extract('a'.into()), glyph_id);
+        assert!(glyph_manager.rasterized_glyph_table.contains_key(&glyph_id));
+    }
+
+    // 結合文字はベースラインを揃えて基底文字の上に重なる
+    #[test]
+    fn compose_cluster() {
+        let base = filled_glyph(1, 8, 6, 8);
+        let mark = filled_glyph(2, 11, 2, 2);
+        let cluster = compose(&[base, mark]).unwrap();
+        assert_eq!(
+            (cluster.left, cluster.top, cluster.width, cluster.height),
+            (1, 11, 6, 11)
+        );
+
+        let BitmapBuffer::Rgb(buffer) = &cluster.buffer else {
+            unreachable!();
+        };
+        let pixel = |x: i32, y: i32| buffer[(3 * (x + y * cluster.width)) as usize];
+        assert_eq!(pixel(0, 0), 0);
+        assert_eq!(pixel(1, 0), 255);
+        assert_eq!(pixel(0, 2), 0);
+        assert_eq!(pixel(0, 3), 255);
+    }
+
+    // 豆腐はセルの左上に置かれる枠
+    #[test]
+    fn tofu_box() {
+        let metrics = crossfont::Metrics {
+            average_advance: 8.0,
+            line_height: 16.0,
+            descent: -4.0,
+            underline_position: -2.0,
+            underline_thickness: 1.0,
+            strikeout_position: 4.0,
+            strikeout_thickness: 1.0,
+        };
+        let cell_metrics = CellMetrics::new(&metrics, Delta::default(), Delta::default());
+        let glyph = tofu(&cell_metrics);
+        assert_eq!((glyph.width, glyph.height), (8, 16));
+        assert_eq!(cell_metrics.glyph_position(glyph.left, glyph.top), (0, 0));
+
+        let BitmapBuffer::Rgb(buffer) = &glyph.buffer else {
+            unreachable!();
+        };
+        let pixel = |x: i32, y: i32| buffer[(3 * (x + y * glyph.width)) as usize];
+        assert_eq!(pixel(0, 0), 0);
+        assert_eq!(pixel(1, 1), 255);
+        assert_eq!(pixel(6, 8), 255);
+        assert_eq!(pixel(3, 8), 0);
+    }
 }
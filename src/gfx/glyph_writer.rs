@@ -1,31 +1,43 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use crossfont::BitmapBuffer;
 
-use super::GlyphManager;
+use super::{
+    glyph_atlas::{GlyphAtlas, PADDING},
+    glyph_manager::{GlyphId, GlyphKey},
+    GlyphManager,
+};
 
-#[derive(Clone, Copy)]
-struct CharacterCache {
-    pub x: u32,
-    pub y: u32,
-    pub width: u32,
-    pub height: u32,
-}
+// アトラスの 1 ページの大きさ
+pub const ATLAS_PAGE_SIZE: u32 = 1024;
+
+// ページ数の上限。超えたら古いグリフを追い出す
+// カラー絵文字は RGBA で 4 倍の容量になるので少なめにする
+pub const MAX_ATLAS_PAGE_COUNT: u32 = 16;
+pub const MAX_COLOR_ATLAS_PAGE_COUNT: u32 = 4;
 
 pub struct CharacterData {
     pub uv_begin: [f32; 2],
     pub uv_end: [f32; 2],
+    pub page: u32,
+    pub is_color: bool,
 }
 
 pub struct GlyphImagePatch {
+    page: u32,
     offset_x: u32,
     offset_y: u32,
     width: u32,
     height: u32,
+    is_color: bool,
     pixel_data: Vec<u8>,
 }
 
 impl GlyphImagePatch {
+    pub fn page(&self) -> u32 {
+        self.page
+    }
+
     pub fn offset_x(&self) -> u32 {
         self.offset_x
     }
@@ -42,149 +54,169 @@ impl GlyphImagePatch {
         self.height
     }
 
+    // RGBA のピクセルか
+    pub fn is_color(&self) -> bool {
+        self.is_color
+    }
+
     pub fn pixels(&self) -> &[u8] {
         &self.pixel_data
     }
 }
 
 pub struct GlyphWriter {
-    image_width: u32,
-    image_height: u32,
-
-    // キャッシュ
-    character_data: HashMap<char, CharacterCache>,
+    // グリフの配置。ピクセルは持たずにパッチとして渡す
+    atlas: GlyphAtlas<GlyphId>,
+    color_atlas: GlyphAtlas<GlyphId>,
 
-    // 現在どこまでテクスチャーを利用しているか
-    current_x: u32,
-    current_y: u32,
-
-    buffer: Vec<u8>,
+    glyph_id_table: HashMap<GlyphKey, GlyphId>,
 }
 
 impl GlyphWriter {
     pub fn new() -> Self {
-        let mut buffer = Vec::default();
-        buffer.resize(4096 * 4096, 0);
-
         Self {
-            image_width: 64 * 64, /*64 ピクセルを 64 文字で 4096 ピクセル*/
-            image_height: 64 * 64,
-            character_data: HashMap::default(),
-            current_x: 0,
-            current_y: 0,
-            buffer,
+            atlas: GlyphAtlas::new(ATLAS_PAGE_SIZE, MAX_ATLAS_PAGE_COUNT),
+            color_atlas: GlyphAtlas::new(ATLAS_PAGE_SIZE, MAX_COLOR_ATLAS_PAGE_COUNT),
+            glyph_id_table: HashMap::default(),
         }
     }
 
+    // 画面に見えているすべての文字をアトラスに置いて、新しく置いたグリフのパッチを返す
+    // 渡した文字は今のフレームで使うものとして追い出さないので、変化のなかったセルのグリフも消えない
     pub fn execute<T>(&mut self, codes: T, glyph_manager: &mut GlyphManager) -> Vec<GlyphImagePatch>
     where
-        T: Iterator<Item = char>,
+        T: Iterator<Item = GlyphKey>,
     {
-        let diff_items = codes
-            .filter_map(|code| {
-                if self.character_data.contains_key(&code) {
-                    return None;
-                };
-
-                // 空白はグリフが存在しないので特別扱い
-                if code == ' ' {
-                    return Some((
-                        ' ',
-                        CharacterCache {
-                            x: 4000,
-                            y: 4000,
-                            width: 32,
-                            height: 32,
-                        },
-                    ));
-                }
-
-                let Some(glyph) = glyph_manager.acquire_rasterized_glyph(code) else {
-                    return None;
-                };
-
-                let offset_x = self.current_x * 64;
-                let offset_y = self.current_y * 64;
-                let character_data = CharacterCache {
-                    x: offset_x,
-                    y: offset_y,
-                    width: glyph.width as u32,
-                    height: glyph.height as u32,
-                };
-                // x がはじまで到達したら、y は次の行に移動して x は先頭に戻る
-                self.current_y += (self.current_x + 1) / 64;
-                self.current_x = (self.current_x + 1) % 64;
-                Some((code, character_data))
-            })
-            .collect::<Vec<(char, CharacterCache)>>();
-
-        // キャッシュに反映
-        for (code, character_cache) in &diff_items {
-            self.character_data.insert(*code, *character_cache);
+        self.atlas.begin_frame();
+        self.color_atlas.begin_frame();
+
+        // 新しいグリフを置く前に、もう置いてあるグリフを使ったことにしておく
+        let codes = codes.collect::<Vec<GlyphKey>>();
+        for code in &codes {
+            if let Some(glyph_id) = self.glyph_id_table.get(code) {
+                self.atlas.get(glyph_id);
+                self.color_atlas.get(glyph_id);
+            }
         }
 
-        // グリフのパッチ
-        let glyph_image_patches = diff_items
-            .iter()
-            .filter_map(|(code, character_cache)| {
-                // グリフを取得
-                let Some(glyph) = glyph_manager.acquire_rasterized_glyph(*code) else {
-                    return None;
-                };
-                let buffer = match &glyph.buffer {
+        let mut glyph_image_patches = Vec::new();
+        let mut evicted_glyph_ids = HashSet::new();
+        for code in codes {
+            // 空白はグリフが存在しないので何も置かない
+            if code.code == ' ' && !code.has_combining() {
+                continue;
+            }
+
+            // 同じフォントの同じグリフに行き着いたらアトラスの領域を共有する
+            let glyph_id = match self.glyph_id_table.get(&code) {
+                Some(glyph_id) => *glyph_id,
+                None => {
+                    let glyph_id = glyph_manager.extract(code);
+                    self.glyph_id_table.insert(code, glyph_id);
+                    glyph_id
+                }
+            };
+
+            // カラー絵文字は別のアトラスに置く
+            let glyph = glyph_manager.get_rasterized_glyph_by_id(glyph_id);
+            let is_color = matches!(glyph.buffer, BitmapBuffer::Rgba(_));
+            let atlas = if is_color {
+                &mut self.color_atlas
+            } else {
+                &mut self.atlas
+            };
+            if atlas.get(&glyph_id).is_some() {
+                continue;
+            }
+
+            // ページに収まらないほど大きいグリフや、今のフレームのグリフでいっぱいなら描かない
+            let (width, height) = (glyph.width.max(0) as u32, glyph.height.max(0) as u32);
+            let Some(insertion) = atlas.insert(glyph_id, width, height) else {
+                continue;
+            };
+            evicted_glyph_ids.extend(insertion.evicted.iter().copied());
+
+            // 追い出したグリフの跡が残らないように余白も 0 で埋める
+            let region = insertion.region;
+            let (patch_width, patch_height) = (region.width + PADDING, region.height + PADDING);
+            let bytes_per_pixel = if is_color { 4 } else { 1 };
+            let mut pixel_data = vec![0u8; (bytes_per_pixel * patch_width * patch_height) as usize];
+            for (y, row) in pixel_data
+                .chunks_mut((bytes_per_pixel * patch_width) as usize)
+                .take(region.height as usize)
+                .enumerate()
+            {
+                let row = &mut row[..(bytes_per_pixel * region.width) as usize];
+                let begin = y * region.width as usize;
+                let end = begin + region.width as usize;
+                match &glyph.buffer {
                     BitmapBuffer::Rgb(buffer) => {
-                        buffer.chunks(3).map(|rgb| rgb[0]).collect::<Vec<u8>>()
+                        for (dst, rgb) in row.iter_mut().zip(buffer[3 * begin..3 * end].chunks(3)) {
+                            *dst = rgb[0];
+                        }
                     }
-                    BitmapBuffer::Rgba(_) => Vec::default(),
-                };
-
-                Some(GlyphImagePatch {
-                    offset_x: character_cache.x,
-                    offset_y: character_cache.y,
-                    width: character_cache.width,
-                    height: character_cache.height,
-                    pixel_data: buffer.clone(),
-                })
-            })
-            .collect();
+                    BitmapBuffer::Rgba(buffer) => row.copy_from_slice(&buffer[4 * begin..4 * end]),
+                }
+            }
+            glyph_image_patches.push(GlyphImagePatch {
+                page: region.page,
+                offset_x: region.x,
+                offset_y: region.y,
+                width: patch_width,
+                height: patch_height,
+                is_color,
+                pixel_data,
+            });
+        }
+
+        // 追い出したグリフはラスタライズした画像も捨てて、次に使うときに作り直す
+        // 同じフレームで置きなおしたグリフは残す
+        evicted_glyph_ids.retain(|glyph_id| {
+            self.atlas.peek(glyph_id).is_none() && self.color_atlas.peek(glyph_id).is_none()
+        });
+        if !evicted_glyph_ids.is_empty() {
+            self.glyph_id_table
+                .retain(|_, glyph_id| !evicted_glyph_ids.contains(glyph_id));
+            glyph_manager.evict(&evicted_glyph_ids);
+        }
 
         glyph_image_patches
     }
 
-    pub fn get_clip_rect(&self, code: char) -> CharacterData {
-        let data = match self.character_data.get(&code) {
-            Some(data) => data,
-            None => {
-                // なければ豆腐
-                self.character_data.get(&'-').unwrap()
-            }
+    pub fn get_clip_rect(&self, code: GlyphKey) -> CharacterData {
+        // アトラスにない文字は何も描かない
+        let region =
+            self.glyph_id_table
+                .get(&code)
+                .and_then(|glyph_id| match self.atlas.peek(glyph_id) {
+                    Some(region) => Some((region, false)),
+                    None => self.color_atlas.peek(glyph_id).map(|region| (region, true)),
+                });
+        let Some((region, is_color)) = region else {
+            return CharacterData {
+                uv_begin: [0.0, 0.0],
+                uv_end: [0.0, 0.0],
+                page: 0,
+                is_color: false,
+            };
         };
 
-        let uv_begin_x = data.x as f32 / 4096.0;
-        let uv_begin_y = data.y as f32 / 4096.0;
-        let uv_width = data.width as f32 / 4096.0;
-        let uv_height = data.height as f32 / 4096.0;
+        let page_size = ATLAS_PAGE_SIZE as f32;
+        let uv_begin_x = region.x as f32 / page_size;
+        let uv_begin_y = region.y as f32 / page_size;
+        let uv_width = region.width as f32 / page_size;
+        let uv_height = region.height as f32 / page_size;
         CharacterData {
             uv_begin: [uv_begin_x, uv_begin_y],
             uv_end: [uv_begin_x + uv_width, uv_begin_y + uv_height],
+            page: region.page,
+            is_color,
         }
     }
 
-    // Uint_R
-    // Debug 用途
-    #[allow(dead_code)]
-    pub fn get_buffer(&self) -> &[u8] {
-        &self.buffer
-    }
-
-    #[allow(dead_code)]
-    pub fn width(&self) -> u32 {
-        self.image_width
-    }
-
     #[allow(dead_code)]
-    pub fn height(&self) -> u32 {
-        self.image_height
+    pub fn page_size(&self) -> u32 {
+        self.atlas.page_size()
     }
 }
 
@@ -192,7 +224,7 @@ impl GlyphWriter {
 mod tests {
     use bmp::Image;
 
-    use crate::gfx::GlyphManager;
+    use crate::gfx::{glyph_manager::GlyphKey, GlyphManager};
 
     use super::GlyphWriter;
 
@@ -203,10 +235,12 @@ mod tests {
         glyph_manager.extract_alphabet();
 
         let mut glyph_writer = GlyphWriter::new();
-        let image_patches = glyph_writer.execute(' '..='~', &mut glyph_manager);
+        let image_patches =
+            glyph_writer.execute((' '..='~').map(GlyphKey::from), &mut glyph_manager);
 
-        let mut image = Image::new(glyph_writer.width(), glyph_writer.height());
-        for image_patch in image_patches {
+        let page_size = glyph_writer.page_size();
+        let mut image = Image::new(page_size, page_size);
+        for image_patch in image_patches.iter().filter(|patch| patch.page == 0) {
             for y in 0..image_patch.height {
                 for x in 0..image_patch.width {
                     let src_index = x + y * image_patch.width();
@@ -226,14 +260,17 @@ mod tests {
             }
         }
 
-        image.save("placed_glyph.png").unwrap();
+        image
+            .save(std::env::temp_dir().join("placed_glyph.png"))
+            .unwrap();
     }
 
     #[test]
     fn patch() {
         let mut glyph_manager = GlyphManager::new();
         let mut glyph_writer = GlyphWriter::new();
-        let image_patches = glyph_writer.execute('a'..'d', &mut glyph_manager);
+        let image_patches =
+            glyph_writer.execute(('a'..'d').map(GlyphKey::from), &mut glyph_manager);
 
         for image_patch in image_patches {
             let mut image = Image::new(image_patch.width, image_patch.height);
@@ -249,8 +286,11 @@ mod tests {
                 }
             }
 
-            let file_name = format!("{}x{}.png", image_patch.offset_x, image_patch.offset_y);
-            image.save(file_name).unwrap();
+            let file_name = format!(
+                "{}_{}x{}.png",
+                image_patch.page, image_patch.offset_x, image_patch.offset_y
+            );
+            image.save(std::env::temp_dir().join(file_name)).unwrap();
         }
     }
 }
@@ -1,10 +1,23 @@
+mod builtin_glyph;
+mod cell_metrics;
+mod color_palette;
 mod content_plotter;
 mod detail;
+mod glyph_atlas;
 mod glyph_manager;
 mod glyph_writer;
+mod preedit;
 mod renderer;
+mod scroll_indicator;
+mod search_overlay;
+mod text_shaper;
 
-pub use content_plotter::ContentPlotter;
+pub use cell_metrics::CellMetrics;
+pub use color_palette::ColorPalette;
+pub use content_plotter::{ContentPlotter, PaneViewport};
 pub use glyph_manager::GlyphManager;
 pub use glyph_writer::GlyphWriter;
+pub use preedit::Preedit;
 pub use renderer::{Renderer, RendererUpdateParams};
+pub use scroll_indicator::ScrollIndicator;
+pub use search_overlay::SearchOverlay;
@@ -0,0 +1,106 @@
+use alacritty_terminal::{
+    grid::Indexed,
+    index::{Column, Point},
+    term::cell::{Cell, Flags},
+};
+use unicode_width::UnicodeWidthChar;
+
+// IME で変換中の文字列
+// 端末の内容には書き込まず、カーソルの位置から下線つきで重ねて描く
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Preedit {
+    text: String,
+
+    // 変換中の文字列の中のカーソルのバイト位置。なければ末尾に置く
+    cursor: Option<usize>,
+}
+
+impl Preedit {
+    pub fn new(text: String, cursor: Option<usize>) -> Self {
+        Self { text, cursor }
+    }
+
+    // origin から並べたセル
+    // 全角文字は後ろにスペーサーを置いて、行からはみ出した分は描かない
+    pub fn cells(&self, origin: Point, columns: usize) -> Vec<Indexed<Cell>> {
+        let mut cells = Vec::new();
+        let mut column = origin.column.0;
+        for c in self.text.chars() {
+            let width = c.width().unwrap_or_default();
+            if width == 0 {
+                continue;
+            }
+            if column + width > columns {
+                break;
+            }
+
+            let flags = if width == 2 {
+                Flags::UNDERLINE | Flags::WIDE_CHAR
+            } else {
+                Flags::UNDERLINE
+            };
+            cells.push(Self::cell(origin, column, c, flags));
+            if width == 2 {
+                let spacer = Flags::UNDERLINE | Flags::WIDE_CHAR_SPACER;
+                cells.push(Self::cell(origin, column + 1, ' ', spacer));
+            }
+            column += width;
+        }
+        cells
+    }
+
+    // 端末のカーソルからずらす列数
+    pub fn cursor_offset(&self) -> usize {
+        let end = self.cursor.unwrap_or(self.text.len()).min(self.text.len());
+        self.text
+            .get(..end)
+            .unwrap_or_default()
+            .chars()
+            .map(|c| c.width().unwrap_or_default())
+            .sum()
+    }
+
+    fn cell(origin: Point, column: usize, c: char, flags: Flags) -> Indexed<Cell> {
+        Indexed {
+            point: Point::new(origin.line, Column(column)),
+            cell: Cell {
+                c,
+                flags,
+                ..Default::default()
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alacritty_terminal::{
+        index::{Column, Line, Point},
+        term::cell::Flags,
+    };
+
+    use super::Preedit;
+
+    // 全角文字は 2 セル使い、行からはみ出した分は描かない
+    #[test]
+    fn cells() {
+        let preedit = Preedit::new("aあい".to_string(), None);
+        let cells = preedit.cells(Point::new(Line(1), Column(2)), 6);
+        let layout = cells
+            .iter()
+            .map(|cell| (cell.point.column.0, cell.c))
+            .collect::<Vec<_>>();
+        assert_eq!(layout, [(2, 'a'), (3, 'あ'), (4, ' ')]);
+        assert!(cells[1].flags.contains(Flags::WIDE_CHAR | Flags::UNDERLINE));
+        assert!(cells[2].flags.contains(Flags::WIDE_CHAR_SPACER));
+    }
+
+    #[test]
+    fn cursor_offset() {
+        assert_eq!(
+            Preedit::new("aあい".to_string(), Some(4)).cursor_offset(),
+            3
+        );
+        assert_eq!(Preedit::new("aあい".to_string(), None).cursor_offset(), 5);
+    }
+}
@@ -8,7 +8,11 @@ use winit::{
 
 use super::{
     content_plotter::Diff,
-    detail::{BackgroundRenderer, CursorRenderer, ScanBufferRenderer, TextRenderer},
+    detail::{
+        BackgroundRenderer, CellBackgroundRenderer, CellDecorationRenderer, CursorRenderer,
+        ScanBufferRenderer, ScrollIndicatorRenderer, TextRenderer,
+    },
+    ScrollIndicator,
 };
 
 pub struct RendererUpdateParams<TPath: AsRef<Path>> {
@@ -18,6 +22,9 @@ pub struct RendererUpdateParams<TPath: AsRef<Path>> {
     diff: Diff,
     image_path: Option<TPath>,
     image_alpha: Option<f32>,
+
+    // フォーカスしているペインを描くときだけ更新する
+    scroll_indicator: Option<ScrollIndicator>,
 }
 
 impl<TPath: AsRef<Path>> RendererUpdateParams<TPath> {
@@ -29,6 +36,7 @@ impl<TPath: AsRef<Path>> RendererUpdateParams<TPath> {
             diff: Diff::default(),
             image_path: None,
             image_alpha: None,
+            scroll_indicator: None,
         }
     }
 
@@ -51,6 +59,11 @@ impl<TPath: AsRef<Path>> RendererUpdateParams<TPath> {
         self.image_path = path;
         self
     }
+
+    pub fn with_scroll_indicator(mut self, scroll_indicator: ScrollIndicator) -> Self {
+        self.scroll_indicator = Some(scroll_indicator);
+        self
+    }
 }
 
 pub struct Renderer<'a> {
@@ -59,12 +72,21 @@ pub struct Renderer<'a> {
     adapter_table: HashMap<WindowId, wgpu::Adapter>,
     surface_table: HashMap<WindowId, wgpu::Surface<'a>>,
 
+    // セルの背景色
+    cell_background_renderer: CellBackgroundRenderer<'a>,
+
     // テキスト描画
     text_renderer: TextRenderer<'a>,
 
+    // 下線と取り消し線
+    cell_decoration_renderer: CellDecorationRenderer<'a>,
+
     // カーソル
     cursor_renderer: CursorRenderer<'a>,
 
+    // スクロールバックの位置
+    scroll_indicator_renderer: ScrollIndicatorRenderer<'a>,
+
     // 背景
     background_renderer: BackgroundRenderer<'a>,
 
@@ -83,12 +105,21 @@ impl<'a> Renderer<'a> {
             adapter_table: Default::default(),
             surface_table: Default::default(),
 
+            // セルの背景色
+            cell_background_renderer: CellBackgroundRenderer::new(),
+
             // テキスト描画
             text_renderer: TextRenderer::new(),
 
+            // 下線と取り消し線
+            cell_decoration_renderer: CellDecorationRenderer::new(),
+
             // カーソル
             cursor_renderer: CursorRenderer::new(),
 
+            // スクロールバックの位置
+            scroll_indicator_renderer: ScrollIndicatorRenderer::new(),
+
             // 背景
             background_renderer: BackgroundRenderer::new(),
 
@@ -149,15 +180,27 @@ impl<'a> Renderer<'a> {
         };
         surface.configure(&device, &config);
 
+        // セルの背景色描画
+        self.cell_background_renderer
+            .register(id, &device, config.format);
+
         // テキスト描画
         self.text_renderer
             .register(id, &device, config.format)
             .await;
 
+        // 下線と取り消し線の描画
+        self.cell_decoration_renderer
+            .register(id, &device, config.format);
+
         // カーソル描画
         self.cursor_renderer
             .register(id, &device, &queue, config.format);
 
+        // スクロールバックの位置の描画
+        self.scroll_indicator_renderer
+            .register(id, &device, config.format);
+
         // スキャンバッファー描画
         self.scan_buffer_renderer
             .register(id, &device, swapchain_format);
@@ -168,11 +211,32 @@ impl<'a> Renderer<'a> {
         self.surface_table.insert(id, surface);
     }
 
+    // ウィンドウを閉じたら、そのウィンドウのデバイスに作ったリソースをすべて捨てる
+    pub fn unregister(&mut self, id: WindowId) {
+        self.cell_background_renderer.unregister(id);
+        self.text_renderer.unregister(id);
+        self.cell_decoration_renderer.unregister(id);
+        self.cursor_renderer.unregister(id);
+        self.scroll_indicator_renderer.unregister(id);
+        self.background_renderer.unregister(id);
+        self.scan_buffer_renderer.unregister(id);
+
+        self.surface_table.remove(&id);
+        self.adapter_table.remove(&id);
+        self.queue_table.remove(&id);
+        self.device_table.remove(&id);
+    }
+
+    // 閉じたウィンドウのイベントが遅れて届くことがあるので、知らないウィンドウは無視する
     pub fn resize(&mut self, id: WindowId, width: u32, height: u32) {
-        let device = self.device_table.get(&id).unwrap();
-        let queue = self.queue_table.get(&id).unwrap();
-        let surface = self.surface_table.get(&id).unwrap();
-        let adapter = self.adapter_table.get(&id).unwrap();
+        let (Some(device), Some(queue), Some(surface), Some(adapter)) = (
+            self.device_table.get(&id),
+            self.queue_table.get(&id),
+            self.surface_table.get(&id),
+            self.adapter_table.get(&id),
+        ) else {
+            return;
+        };
         let swapchain_capabilities = surface.get_capabilities(adapter);
         let swapchain_format = swapchain_capabilities.formats[0];
         let config = wgpu::SurfaceConfiguration {
@@ -193,7 +257,8 @@ impl<'a> Renderer<'a> {
         surface.configure(device, &config);
 
         // カーソル
-        self.cursor_renderer.resize(width, height);
+        self.cursor_renderer.resize(id, width, height);
+        self.scroll_indicator_renderer.resize(id, width, height);
 
         // 背景描画
         // TODO: プラグイン化
@@ -237,19 +302,40 @@ impl<'a> Renderer<'a> {
             );
         }
 
-        let queue = self.queue_table.get(&id).unwrap();
+        let (Some(device), Some(queue)) = (self.device_table.get(&id), self.queue_table.get(&id))
+        else {
+            return;
+        };
+        self.cell_background_renderer
+            .update(queue, id, &render_update_params.diff);
         self.text_renderer
+            .update(device, queue, id, &render_update_params.diff);
+        self.cell_decoration_renderer
             .update(queue, id, &render_update_params.diff);
 
         // カーソルレンダラーの更新
         self.cursor_renderer
             .update(id, &render_update_params.diff, queue);
+
+        // スクロールバックの位置
+        if let Some(scroll_indicator) = &render_update_params.scroll_indicator {
+            self.scroll_indicator_renderer.update(
+                id,
+                scroll_indicator,
+                render_update_params.diff.viewport(),
+                queue,
+            );
+        }
     }
 
     pub fn render(&self, id: WindowId) {
-        let device = self.device_table.get(&id).unwrap();
-        let queue = self.queue_table.get(&id).unwrap();
-        let surface = self.surface_table.get(&id).unwrap();
+        let (Some(device), Some(queue), Some(surface)) = (
+            self.device_table.get(&id),
+            self.queue_table.get(&id),
+            self.surface_table.get(&id),
+        ) else {
+            return;
+        };
 
         let frame = surface.get_current_texture().unwrap();
 
@@ -291,6 +377,33 @@ impl<'a> Renderer<'a> {
             self.background_renderer.render(id, render_pass);
         }
 
+        // セルの背景色描画
+        {
+            let mut render_pass = command_encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: None,
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            render_pass.set_viewport(
+                0.0,
+                0.0,
+                frame.texture.size().width as f32,
+                frame.texture.size().height as f32,
+                0.0,
+                1.0,
+            );
+            self.cell_background_renderer.render(id, render_pass);
+        }
+
         // 文字描画
         {
             let mut render_pass = command_encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
@@ -318,6 +431,33 @@ impl<'a> Renderer<'a> {
             self.text_renderer.render(id, render_pass);
         }
 
+        // 下線と取り消し線の描画
+        {
+            let mut render_pass = command_encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: None,
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            render_pass.set_viewport(
+                0.0,
+                0.0,
+                frame.texture.size().width as f32,
+                frame.texture.size().height as f32,
+                0.0,
+                1.0,
+            );
+            self.cell_decoration_renderer.render(id, render_pass);
+        }
+
         // カーソル描画
         {
             let mut render_pass = command_encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
@@ -345,6 +485,33 @@ impl<'a> Renderer<'a> {
             self.cursor_renderer.render(id, render_pass);
         }
 
+        // スクロールバックの位置の描画
+        {
+            let mut render_pass = command_encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: None,
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            render_pass.set_viewport(
+                0.0,
+                0.0,
+                frame.texture.size().width as f32,
+                frame.texture.size().height as f32,
+                0.0,
+                1.0,
+            );
+            self.scroll_indicator_renderer.render(id, render_pass);
+        }
+
         // スキャンバッファーにコピー
         // 1. ドットバイドット対応
         // 2. 座標系調整
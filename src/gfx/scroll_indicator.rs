@@ -0,0 +1,70 @@
+// スクロールバックのどこを表示しているかを示すつまみ
+// 画面の右端に、履歴全体に対する表示中の範囲の割合で描く
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ScrollIndicator {
+    // 最新の行から何行さかのぼっているか
+    display_offset: usize,
+
+    history_size: usize,
+
+    screen_lines: usize,
+}
+
+impl ScrollIndicator {
+    // つまみの幅
+    const WIDTH: f32 = 6.0;
+
+    // 履歴が長くても見失わない高さ
+    const MIN_HEIGHT: f32 = 8.0;
+
+    pub fn new(display_offset: usize, history_size: usize, screen_lines: usize) -> Self {
+        Self {
+            display_offset,
+            history_size,
+            screen_lines,
+        }
+    }
+
+    // さかのぼっているときだけ表示する
+    pub fn is_visible(&self) -> bool {
+        self.display_offset > 0 && self.screen_lines > 0
+    }
+
+    // つまみのピクセル座標の矩形 (x, y, width, height)
+    pub fn rect(&self, size: (u32, u32)) -> Option<(f32, f32, f32, f32)> {
+        if !self.is_visible() {
+            return None;
+        }
+
+        let (width, height) = (size.0 as f32, size.1 as f32);
+        let total_lines = (self.history_size + self.screen_lines) as f32;
+        let thumb_height = (height * self.screen_lines as f32 / total_lines)
+            .max(Self::MIN_HEIGHT)
+            .min(height);
+
+        // 先頭の行が履歴のどこにあるか
+        let top_line = self.history_size.saturating_sub(self.display_offset) as f32;
+        let scrollable_lines = self.history_size.max(1) as f32;
+        let y = (height - thumb_height) * top_line / scrollable_lines;
+        Some((width - Self::WIDTH, y, Self::WIDTH, thumb_height))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ScrollIndicator;
+
+    #[test]
+    fn rect() {
+        // 最新の行を表示しているときは出さない
+        assert!(ScrollIndicator::new(0, 100, 20).rect((600, 400)).is_none());
+
+        // 一番古い行までさかのぼると上端につく
+        let (x, y, width, height) = ScrollIndicator::new(60, 60, 20).rect((600, 400)).unwrap();
+        assert_eq!((x, y, width, height), (594.0, 0.0, 6.0, 100.0));
+
+        // 半分さかのぼると中央に来る
+        let (_, y, _, _) = ScrollIndicator::new(30, 60, 20).rect((600, 400)).unwrap();
+        assert_eq!(y, 150.0);
+    }
+}
@@ -0,0 +1,150 @@
+use alacritty_terminal::{
+    grid::Indexed,
+    index::{Column, Line, Point},
+    term::{
+        cell::{Cell, Flags},
+        search::Match,
+    },
+};
+use unicode_width::UnicodeWidthChar;
+
+// 検索の入力欄と画面に見えている一致
+// 入力欄は端末の内容には書き込まず、画面の最下行に反転して重ねて描く
+// 貼り付けの確認のように一致のない入力欄だけを表示するのにも使う
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SearchOverlay {
+    bar: String,
+
+    matches: Vec<Match>,
+
+    // 次の検索の起点になる一致
+    focused_match: Option<Match>,
+}
+
+impl SearchOverlay {
+    pub fn new(bar: String, matches: Vec<Match>, focused_match: Option<Match>) -> Self {
+        Self {
+            bar,
+            matches,
+            focused_match,
+        }
+    }
+
+    // 一致した範囲にあれば、フォーカスしている一致かどうか
+    pub fn highlight(&self, point: Point) -> Option<bool> {
+        if self
+            .focused_match
+            .as_ref()
+            .is_some_and(|m| m.contains(&point))
+        {
+            return Some(true);
+        }
+        self.matches
+            .iter()
+            .any(|m| m.contains(&point))
+            .then_some(false)
+    }
+
+    // line の行を埋めるセル
+    // 行に収まらなければ入力中の末尾が見えるように先頭を削る
+    pub fn cells(&self, line: Line, columns: usize) -> Vec<Indexed<Cell>> {
+        let chars = self
+            .bar
+            .chars()
+            .filter(|c| c.width().unwrap_or_default() > 0)
+            .collect::<Vec<char>>();
+        let mut width = chars
+            .iter()
+            .map(|c| c.width().unwrap_or_default())
+            .sum::<usize>();
+        let mut begin = 0;
+        while width > columns && begin < chars.len() {
+            width -= chars[begin].width().unwrap_or_default();
+            begin += 1;
+        }
+
+        let mut cells = Vec::new();
+        let mut column = 0;
+        for c in &chars[begin..] {
+            if c.width() == Some(2) {
+                cells.push(Self::cell(
+                    line,
+                    column,
+                    *c,
+                    Flags::INVERSE | Flags::WIDE_CHAR,
+                ));
+                let spacer = Flags::INVERSE | Flags::WIDE_CHAR_SPACER;
+                cells.push(Self::cell(line, column + 1, ' ', spacer));
+                column += 2;
+            } else {
+                cells.push(Self::cell(line, column, *c, Flags::INVERSE));
+                column += 1;
+            }
+        }
+        for column in column..columns {
+            cells.push(Self::cell(line, column, ' ', Flags::INVERSE));
+        }
+        cells
+    }
+
+    // 入力欄のカーソルを置く列
+    pub fn cursor_column(&self, columns: usize) -> usize {
+        let width = self
+            .bar
+            .chars()
+            .map(|c| c.width().unwrap_or_default())
+            .sum::<usize>();
+        width.min(columns.saturating_sub(1))
+    }
+
+    fn cell(line: Line, column: usize, c: char, flags: Flags) -> Indexed<Cell> {
+        Indexed {
+            point: Point::new(line, Column(column)),
+            cell: Cell {
+                c,
+                flags,
+                ..Default::default()
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alacritty_terminal::index::{Column, Line, Point};
+
+    use super::SearchOverlay;
+
+    fn point(line: i32, column: usize) -> Point {
+        Point::new(Line(line), Column(column))
+    }
+
+    #[test]
+    fn highlight() {
+        let overlay = SearchOverlay::new(
+            String::new(),
+            vec![point(0, 1)..=point(0, 3), point(2, 0)..=point(2, 1)],
+            Some(point(2, 0)..=point(2, 1)),
+        );
+        assert_eq!(overlay.highlight(point(0, 2)), Some(false));
+        assert_eq!(overlay.highlight(point(2, 1)), Some(true));
+        assert_eq!(overlay.highlight(point(0, 4)), None);
+    }
+
+    // 行を埋めて、はみ出したら入力中の末尾を残す
+    #[test]
+    fn cells() {
+        let overlay = SearchOverlay::new("/abc".to_string(), Vec::new(), None);
+        let text = |columns| {
+            overlay
+                .cells(Line(9), columns)
+                .iter()
+                .map(|cell| cell.c)
+                .collect::<String>()
+        };
+        assert_eq!(text(6), "/abc  ");
+        assert_eq!(text(3), "abc");
+        assert_eq!(overlay.cursor_column(6), 4);
+        assert_eq!(overlay.cursor_column(3), 2);
+    }
+}
@@ -0,0 +1,438 @@
+use std::sync::Arc;
+
+use crossfont::{BitmapBuffer, RasterizedGlyph, Size};
+use rustybuzz::UnicodeBuffer;
+use swash::{
+    scale::{image::Content, Render, ScaleContext, Source, StrikeWith},
+    zeno::Format,
+    FontRef,
+};
+
+use super::glyph_manager::{FaceRequest, FontStyle};
+
+// 1 つのセルに重ねられる整形後のグリフの数
+// これを超えた分は描画しない
+pub const MAX_SHAPED_GLYPHS: usize = 4;
+
+// 整形したグリフ
+// オフセットはセルの原点からのピクセル数で y は上向き
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct ShapedGlyph {
+    pub glyph_index: u16,
+    pub x_offset: i16,
+    pub y_offset: i16,
+}
+
+// 1 つのセルに描くグリフの集まり
+// 空なら前のセルの合字に含まれているので何も描かない
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct ShapedCluster {
+    glyphs: [ShapedGlyph; MAX_SHAPED_GLYPHS],
+    len: u8,
+}
+
+impl ShapedCluster {
+    fn push(&mut self, glyph: ShapedGlyph) {
+        if let Some(dst) = self.glyphs.get_mut(self.len as usize) {
+            *dst = glyph;
+            self.len += 1;
+        }
+    }
+
+    pub fn glyphs(&self) -> &[ShapedGlyph] {
+        &self.glyphs[..self.len as usize]
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+// 整形に使うフォントファイルの中身
+// crossfont はフォントのデータを渡してくれないので、crossfont と同じ指定で同じファイルを探し直す
+struct ShapingFace {
+    // data を参照しているので data より先に破棄する
+    hb_face: rustybuzz::Face<'static>,
+
+    data: Arc<[u8]>,
+    index: u32,
+}
+
+impl ShapingFace {
+    fn new(data: Arc<[u8]>, index: u32) -> Option<Self> {
+        // Arc の中身は動かないうえに data と一緒にしか破棄されないので、
+        // 整形のたびに解析しなおさないように参照したまま持っておく
+        let bytes: &'static [u8] = unsafe { &*(data.as_ref() as *const [u8]) };
+        let hb_face = rustybuzz::Face::from_slice(bytes, index)?;
+        Some(Self {
+            hb_face,
+            data,
+            index,
+        })
+    }
+}
+
+// 同じ属性のセルの並びを整形して、合字や文脈で形の変わる文字をセルに割り当てる
+// 位置はセルの原点からの相対で持つのでグリッドからはずれない
+pub struct TextShaper {
+    // crossfont と同じファイルが見つからなかったスタイルは整形しない
+    regular: Option<ShapingFace>,
+    bold: Option<ShapingFace>,
+    italic: Option<ShapingFace>,
+    bold_italic: Option<ShapingFace>,
+
+    // crossfont と同じく 96 DPI で換算したピクセル数
+    pixel_size: f32,
+
+    scale_context: ScaleContext,
+}
+
+impl TextShaper {
+    // crossfont が読み込んだのと同じ指定からフォントファイルを探す
+    // 見つからなかったスタイルは知らせたうえで整形せずに描く
+    pub fn new(
+        regular: &FaceRequest,
+        bold: &FaceRequest,
+        italic: &FaceRequest,
+        bold_italic: &FaceRequest,
+        font_size: Size,
+    ) -> Self {
+        let load = |request: &FaceRequest| {
+            let face = locate_face(request, font_size)
+                .and_then(|(data, index)| ShapingFace::new(data, index));
+            if face.is_none() {
+                eprintln!(
+                    "font for shaping not found, drawn without shaping: {}",
+                    request.font_desc()
+                );
+            }
+            face
+        };
+
+        Self {
+            regular: load(regular),
+            bold: load(bold),
+            italic: load(italic),
+            bold_italic: load(bold_italic),
+            pixel_size: font_size.as_f32_pts() * 96.0 / 72.0,
+            scale_context: ScaleContext::new(),
+        }
+    }
+
+    // セルごとの文字列をまとめて整形して、セルごとのグリフを返す
+    // フォントにない文字を含むセルと、文字そのままのグリフになったセルは None になるので crossfont で描く
+    pub fn shape(&self, style: FontStyle, texts: &[&str]) -> Vec<Option<ShapedCluster>> {
+        let Some(face) = self.face(style) else {
+            return vec![None; texts.len()];
+        };
+        let hb_face = &face.hb_face;
+
+        let mut text = String::new();
+        let mut cell_begins = Vec::with_capacity(texts.len());
+        for cell_text in texts {
+            cell_begins.push(text.len());
+            text.push_str(cell_text);
+        }
+
+        let mut buffer = UnicodeBuffer::new();
+        buffer.push_str(&text);
+        buffer.guess_segment_properties();
+        let glyph_buffer = rustybuzz::shape(hb_face, &[], buffer);
+
+        let scale = self.pixel_size / hb_face.units_per_em() as f32;
+        let glyphs = glyph_buffer
+            .glyph_infos()
+            .iter()
+            .zip(glyph_buffer.glyph_positions())
+            .map(|(info, position)| RawGlyph {
+                glyph_index: info.glyph_id as u16,
+                cluster: info.cluster as usize,
+                x_advance: position.x_advance,
+                x_offset: position.x_offset,
+                y_offset: position.y_offset,
+            });
+        let mut clusters = assign_clusters(&cell_begins, glyphs, scale);
+
+        // 整形しても形の変わらなかった文字は、整形しない文字と同じ見た目になるよう crossfont に任せる
+        for (cluster, cell_text) in clusters.iter_mut().zip(texts) {
+            let nominal = |code: char| hb_face.glyph_index(code).map(|id| id.0);
+            if let Some(shaped) = cluster {
+                if is_nominal(shaped, cell_text, nominal) {
+                    *cluster = None;
+                }
+            }
+        }
+        clusters
+    }
+
+    // セルのグリフを描いて、オフセットを反映した部品を返す
+    // 重ね合わせは呼び出し側で行う
+    // crossfont にはグリフ番号で描く手段がないので、同じフォントファイルを同じ大きさで描く
+    pub fn rasterize(&mut self, style: FontStyle, cluster: &ShapedCluster) -> Vec<RasterizedGlyph> {
+        let face = match style {
+            FontStyle::Regular => self.regular.as_ref(),
+            FontStyle::Bold => self.bold.as_ref(),
+            FontStyle::Italic => self.italic.as_ref(),
+            FontStyle::BoldItalic => self.bold_italic.as_ref(),
+        };
+        let Some(font) = face.and_then(|face| FontRef::from_index(&face.data, face.index as usize))
+        else {
+            return Vec::new();
+        };
+
+        let mut scaler = self
+            .scale_context
+            .builder(font)
+            .size(self.pixel_size)
+            .hint(true)
+            .build();
+        let mut render = Render::new(&[
+            Source::ColorOutline(0),
+            Source::ColorBitmap(StrikeWith::BestFit),
+            Source::Outline,
+        ]);
+        render.format(Format::Alpha);
+
+        cluster
+            .glyphs()
+            .iter()
+            .filter_map(|glyph| {
+                let image = render.render(&mut scaler, glyph.glyph_index)?;
+                let (width, height) = (image.placement.width, image.placement.height);
+                if width == 0 || height == 0 {
+                    return None;
+                }
+
+                let buffer = match image.content {
+                    Content::Color => BitmapBuffer::Rgba(premultiply(image.data)),
+                    Content::Mask | Content::SubpixelMask => {
+                        BitmapBuffer::Rgb(image.data.iter().flat_map(|a| [*a; 3]).collect())
+                    }
+                };
+                Some(RasterizedGlyph {
+                    character: '\0',
+                    width: width as i32,
+                    height: height as i32,
+                    top: image.placement.top + glyph.y_offset as i32,
+                    left: image.placement.left + glyph.x_offset as i32,
+                    advance: (0, 0),
+                    buffer,
+                })
+            })
+            .collect()
+    }
+
+    fn face(&self, style: FontStyle) -> Option<&ShapingFace> {
+        match style {
+            FontStyle::Regular => self.regular.as_ref(),
+            FontStyle::Bold => self.bold.as_ref(),
+            FontStyle::Italic => self.italic.as_ref(),
+            FontStyle::BoldItalic => self.bold_italic.as_ref(),
+        }
+    }
+}
+
+// crossfont と同じく fontconfig で探して、最初に選ばれたファイルを読む
+#[cfg(not(any(target_os = "macos", windows)))]
+fn locate_face(request: &FaceRequest, font_size: Size) -> Option<(Arc<[u8]>, u32)> {
+    use crossfont::ft::fc;
+
+    let config = fc::Config::get_current();
+    let mut pattern = fc::Pattern::new();
+    pattern.add_family(&request.family);
+    pattern.add_pixelsize(f64::from(font_size.as_f32_pts() * 96.0 / 72.0));
+    match &request.style {
+        Some(style) => {
+            pattern.add_style(style);
+        }
+        None => {
+            pattern.set_weight(request.weight.into());
+            pattern.set_slant(request.slant.into());
+        }
+    }
+    pattern.config_substitute(config, fc::MatchKind::Pattern);
+    pattern.default_substitute();
+
+    let fonts = fc::font_sort(config, &pattern)?;
+    let font = (&fonts).into_iter().next()?;
+    let font = pattern.render_prepare(config, font);
+    let location = font.ft_face_location(0)?;
+    let data = std::fs::read(&location.path).ok()?;
+
+    // 上位のビットは可変フォントのインスタンス番号なのでファイル内のフェイスの番号だけ取り出す
+    Some((data.into(), (location.index & 0xFFFF) as u32))
+}
+
+// CoreText や DirectWrite からはファイルをもらえないので、同じファミリー名でシステムのフォントから探す
+// 別のファミリーで代用すると crossfont と見た目が変わるので、見つからなければあきらめる
+#[cfg(any(target_os = "macos", windows))]
+fn locate_face(request: &FaceRequest, _font_size: Size) -> Option<(Arc<[u8]>, u32)> {
+    use std::sync::OnceLock;
+
+    // システムのフォントを列挙するのは重いので、フォントを作り直すたびではなく最初の一度だけにする
+    static DATABASE: OnceLock<fontdb::Database> = OnceLock::new();
+    let database = DATABASE.get_or_init(|| {
+        let mut database = fontdb::Database::new();
+        database.load_system_fonts();
+        database
+    });
+
+    let weight = match request.weight {
+        crossfont::Weight::Normal => fontdb::Weight::NORMAL,
+        crossfont::Weight::Bold => fontdb::Weight::BOLD,
+    };
+    let style = match request.slant {
+        crossfont::Slant::Normal => fontdb::Style::Normal,
+        crossfont::Slant::Italic => fontdb::Style::Italic,
+        crossfont::Slant::Oblique => fontdb::Style::Oblique,
+    };
+    let id = database.query(&fontdb::Query {
+        families: &[fontdb::Family::Name(&request.family)],
+        weight,
+        style,
+        ..Default::default()
+    })?;
+    database.with_face_data(id, |data, index| (Arc::from(data), index))
+}
+
+// 1 つの文字がそのままフォントの既定のグリフになっている
+fn is_nominal(
+    shaped: &ShapedCluster,
+    cell_text: &str,
+    nominal: impl Fn(char) -> Option<u16>,
+) -> bool {
+    let mut chars = cell_text.chars();
+    let (Some(code), None) = (chars.next(), chars.next()) else {
+        return false;
+    };
+    match shaped.glyphs() {
+        [glyph] => {
+            glyph.x_offset == 0 && glyph.y_offset == 0 && nominal(code) == Some(glyph.glyph_index)
+        }
+        _ => false,
+    }
+}
+
+// 整形結果のグリフ。単位はフォントの単位
+struct RawGlyph {
+    glyph_index: u16,
+    // 元の文字列のバイト位置
+    cluster: usize,
+    x_advance: i32,
+    x_offset: i32,
+    y_offset: i32,
+}
+
+// グリフをクラスターの先頭のセルに割り当てる
+// 位置はクラスターの最初のグリフからの相対なので、合字がいくつセルをまたいでもグリッドからずれない
+fn assign_clusters(
+    cell_begins: &[usize],
+    glyphs: impl Iterator<Item = RawGlyph>,
+    scale: f32,
+) -> Vec<Option<ShapedCluster>> {
+    let mut clusters = vec![Some(ShapedCluster::default()); cell_begins.len()];
+    let mut origins = vec![None; cell_begins.len()];
+    let mut pen = 0;
+    for glyph in glyphs {
+        let Some(cell) = cell_begins
+            .partition_point(|begin| *begin <= glyph.cluster)
+            .checked_sub(1)
+        else {
+            continue;
+        };
+        let origin = *origins[cell].get_or_insert(pen);
+        let to_pixel = |value: i32| (value as f32 * scale).round() as i16;
+
+        // .notdef はこのフォントにない文字なので整形をあきらめる
+        if glyph.glyph_index == 0 {
+            clusters[cell] = None;
+        } else if let Some(cluster) = &mut clusters[cell] {
+            cluster.push(ShapedGlyph {
+                glyph_index: glyph.glyph_index,
+                x_offset: to_pixel(pen - origin + glyph.x_offset),
+                y_offset: to_pixel(glyph.y_offset),
+            });
+        }
+        pen += glyph.x_advance;
+    }
+    clusters
+}
+
+// FreeType の出力と同じく乗算済みアルファにそろえる
+fn premultiply(mut data: Vec<u8>) -> Vec<u8> {
+    for pixel in data.chunks_mut(4) {
+        let alpha = pixel[3] as u32;
+        for channel in &mut pixel[..3] {
+            *channel = (*channel as u32 * alpha / 255) as u8;
+        }
+    }
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{assign_clusters, is_nominal, RawGlyph, ShapedGlyph};
+
+    fn raw(glyph_index: u16, cluster: usize, x_advance: i32, x_offset: i32) -> RawGlyph {
+        RawGlyph {
+            glyph_index,
+            cluster,
+            x_advance,
+            x_offset,
+            y_offset: 0,
+        }
+    }
+
+    // 合字に含まれた後ろのセルは空になる
+    #[test]
+    fn ligature() {
+        let glyphs = [raw(10, 0, 200, 0), raw(11, 2, 100, 0)];
+        let clusters = assign_clusters(&[0, 1, 2], glyphs.into_iter(), 0.1);
+        assert_eq!(clusters[0].unwrap().glyphs()[0].glyph_index, 10);
+        assert!(clusters[1].unwrap().is_empty());
+        assert_eq!(
+            clusters[2].unwrap().glyphs(),
+            &[ShapedGlyph {
+                glyph_index: 11,
+                x_offset: 0,
+                y_offset: 0
+            }]
+        );
+    }
+
+    // 結合文字はクラスターの先頭からの位置で同じセルに重なる
+    #[test]
+    fn mark() {
+        let glyphs = [raw(10, 0, 100, 0), raw(20, 0, 0, -80), raw(11, 3, 100, 0)];
+        let clusters = assign_clusters(&[0, 3], glyphs.into_iter(), 0.1);
+        let glyphs = clusters[0].unwrap();
+        assert_eq!(glyphs.glyphs().len(), 2);
+        assert_eq!(glyphs.glyphs()[1].x_offset, 2);
+        assert_eq!(clusters[1].unwrap().glyphs()[0].x_offset, 0);
+    }
+
+    // フォントにない文字はフォールバックに任せる
+    #[test]
+    fn notdef() {
+        let glyphs = [raw(10, 0, 100, 0), raw(0, 1, 100, 0)];
+        let clusters = assign_clusters(&[0, 1], glyphs.into_iter(), 0.1);
+        assert!(clusters[0].is_some());
+        assert!(clusters[1].is_none());
+    }
+
+    // 文字そのままのグリフだけのセルは整形しない文字と同じく描く
+    #[test]
+    fn nominal() {
+        let glyphs = [raw(10, 0, 100, 0), raw(30, 1, 100, 0), raw(11, 2, 100, 0)];
+        let clusters = assign_clusters(&[0, 1, 2], glyphs.into_iter(), 0.1);
+        let nominal = |code: char| match code {
+            'a' => Some(10),
+            'b' => Some(11),
+            'c' => Some(12),
+            _ => None,
+        };
+        assert!(is_nominal(&clusters[0].unwrap(), "a", nominal));
+        assert!(!is_nominal(&clusters[1].unwrap(), "c", nominal));
+        assert!(!is_nominal(&clusters[2].unwrap(), "b\u{301}", nominal));
+    }
+}
@@ -0,0 +1,287 @@
+use alacritty_terminal::term::TermMode;
+use winit::keyboard::{Key, ModifiersState, NamedKey};
+
+use crate::config::{Action, KeyBinding};
+
+// 設定のキー
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum BindingKey {
+    Named(NamedKey),
+    // 小文字にそろえる
+    Character(String),
+}
+
+// 解釈済みの割り当て
+#[derive(Debug, Clone)]
+struct Binding {
+    key: BindingKey,
+    modifiers: ModifiersState,
+    // すべて有効なときだけ使う
+    mode: TermMode,
+    // どれか有効なら使わない
+    not_mode: TermMode,
+    action: Action,
+}
+
+// キー入力を pty に送る前に操作に割り当てる
+// 設定の割り当てを先に探して、なければ既定の割り当てを使う
+pub struct KeyBindingTable {
+    bindings: Vec<Binding>,
+
+    // 設定の変更を検出するために元の設定を持っておく
+    source: Vec<KeyBinding>,
+}
+
+impl KeyBindingTable {
+    pub fn new(key_bindings: &[KeyBinding]) -> Self {
+        let bindings = key_bindings
+            .iter()
+            .chain(Self::default_key_bindings().iter())
+            .filter_map(|key_binding| {
+                let binding = Self::parse(key_binding);
+                if binding.is_none() {
+                    eprintln!("invalid keybinding: {:?}", key_binding);
+                }
+                binding
+            })
+            .collect();
+
+        Self {
+            bindings,
+            source: key_bindings.to_vec(),
+        }
+    }
+
+    pub fn source(&self) -> &[KeyBinding] {
+        &self.source
+    }
+
+    // 修飾キーは完全に一致したものだけ使う
+    // 文字のキーはシフトなどで変わらないように修飾キーを外したキーで比べる
+    pub fn find(
+        &self,
+        key: &Key,
+        unmodified_key: &Key,
+        modifiers: ModifiersState,
+        mode: TermMode,
+    ) -> Option<&Action> {
+        let binding = self.bindings.iter().find(|binding| {
+            let is_key_matched = match (&binding.key, key, unmodified_key) {
+                (BindingKey::Named(named), Key::Named(key), _) => named == key,
+                (BindingKey::Character(c), _, Key::Character(key)) => *c == key.to_lowercase(),
+                _ => false,
+            };
+            is_key_matched
+                && binding.modifiers == modifiers
+                && mode.contains(binding.mode)
+                && !mode.intersects(binding.not_mode)
+        })?;
+        Some(&binding.action)
+    }
+
+    fn parse(key_binding: &KeyBinding) -> Option<Binding> {
+        let key = match Self::parse_named_key(&key_binding.key) {
+            Some(named) => BindingKey::Named(named),
+            None if key_binding.key.chars().count() == 1 => {
+                BindingKey::Character(key_binding.key.to_lowercase())
+            }
+            None => return None,
+        };
+
+        let mut modifiers = ModifiersState::empty();
+        for name in Self::split(&key_binding.mods) {
+            modifiers |= match name {
+                "Shift" => ModifiersState::SHIFT,
+                "Control" | "Ctrl" => ModifiersState::CONTROL,
+                "Alt" | "Option" => ModifiersState::ALT,
+                "Super" | "Command" => ModifiersState::SUPER,
+                _ => return None,
+            };
+        }
+
+        let (mut mode, mut not_mode) = (TermMode::empty(), TermMode::empty());
+        for name in Self::split(&key_binding.mode) {
+            let (target, name) = match name.strip_prefix('~') {
+                Some(name) => (&mut not_mode, name),
+                None => (&mut mode, name),
+            };
+            *target |= match name {
+                "AppCursor" => TermMode::APP_CURSOR,
+                "AppKeypad" => TermMode::APP_KEYPAD,
+                "AltScreen" => TermMode::ALT_SCREEN,
+                _ => return None,
+            };
+        }
+
+        Some(Binding {
+            key,
+            modifiers,
+            mode,
+            not_mode,
+            action: key_binding.action.clone(),
+        })
+    }
+
+    fn parse_named_key(name: &str) -> Option<NamedKey> {
+        let named = match name {
+            "Enter" => NamedKey::Enter,
+            "Tab" => NamedKey::Tab,
+            "Space" => NamedKey::Space,
+            "Backspace" => NamedKey::Backspace,
+            "Escape" => NamedKey::Escape,
+            "Insert" => NamedKey::Insert,
+            "Delete" => NamedKey::Delete,
+            "Home" => NamedKey::Home,
+            "End" => NamedKey::End,
+            "PageUp" => NamedKey::PageUp,
+            "PageDown" => NamedKey::PageDown,
+            "Up" | "ArrowUp" => NamedKey::ArrowUp,
+            "Down" | "ArrowDown" => NamedKey::ArrowDown,
+            "Left" | "ArrowLeft" => NamedKey::ArrowLeft,
+            "Right" | "ArrowRight" => NamedKey::ArrowRight,
+            "F1" => NamedKey::F1,
+            "F2" => NamedKey::F2,
+            "F3" => NamedKey::F3,
+            "F4" => NamedKey::F4,
+            "F5" => NamedKey::F5,
+            "F6" => NamedKey::F6,
+            "F7" => NamedKey::F7,
+            "F8" => NamedKey::F8,
+            "F9" => NamedKey::F9,
+            "F10" => NamedKey::F10,
+            "F11" => NamedKey::F11,
+            "F12" => NamedKey::F12,
+            _ => return None,
+        };
+        Some(named)
+    }
+
+    fn split(names: &str) -> impl Iterator<Item = &str> {
+        names
+            .split('|')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+    }
+
+    fn default_key_bindings() -> Vec<KeyBinding> {
+        let mut key_bindings = vec![
+            KeyBinding::new("C", "Control|Shift", Action::Copy),
+            KeyBinding::new("V", "Control|Shift", Action::Paste),
+            KeyBinding::new("N", "Control|Shift", Action::SpawnWindow),
+            KeyBinding::new("F", "Control|Shift", Action::SearchForward),
+            KeyBinding::new("B", "Control|Shift", Action::SearchBackward),
+            KeyBinding::new("=", "Control", Action::IncreaseFontSize),
+            KeyBinding::new("-", "Control", Action::DecreaseFontSize),
+            KeyBinding::new("0", "Control", Action::ResetFontSize),
+        ];
+
+        // 代替スクリーンではアプリがスクロールを扱う
+        for (key, action) in [
+            ("PageUp", Action::ScrollPageUp),
+            ("PageDown", Action::ScrollPageDown),
+            ("Home", Action::ScrollToTop),
+            ("End", Action::ScrollToBottom),
+        ] {
+            key_bindings.push(KeyBinding {
+                mode: "~AltScreen".to_string(),
+                ..KeyBinding::new(key, "Shift", action)
+            });
+        }
+
+        #[cfg(target_os = "macos")]
+        key_bindings.extend([
+            KeyBinding::new("C", "Super", Action::Copy),
+            KeyBinding::new("V", "Super", Action::Paste),
+            KeyBinding::new("N", "Super", Action::SpawnWindow),
+            KeyBinding::new("F", "Super", Action::SearchForward),
+            KeyBinding::new("B", "Super", Action::SearchBackward),
+        ]);
+
+        key_bindings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alacritty_terminal::term::TermMode;
+    use winit::keyboard::{Key, ModifiersState, NamedKey};
+
+    use super::KeyBindingTable;
+    use crate::config::{Action, KeyBinding};
+
+    fn character(c: &str) -> Key {
+        Key::Character(c.into())
+    }
+
+    // シフトで大文字になっても修飾キーを外したキーで比べる
+    #[test]
+    fn default_bindings() {
+        let table = KeyBindingTable::new(&[]);
+        let control_shift = ModifiersState::CONTROL | ModifiersState::SHIFT;
+        let action = table.find(
+            &character("C"),
+            &character("c"),
+            control_shift,
+            TermMode::empty(),
+        );
+        assert_eq!(action, Some(&Action::Copy));
+
+        // 修飾キーが一致しなければ割り当てない
+        let action = table.find(
+            &character("c"),
+            &character("c"),
+            ModifiersState::CONTROL,
+            TermMode::empty(),
+        );
+        assert!(action.is_none());
+    }
+
+    // 設定の割り当てが既定より優先され、モードで使い分けられる
+    #[test]
+    fn config_bindings() {
+        let table = KeyBindingTable::new(&[
+            KeyBinding::new("C", "Control|Shift", Action::None),
+            KeyBinding {
+                mode: "AltScreen".to_string(),
+                ..KeyBinding::new("Up", "Alt", Action::SendString("\x1b[1;3A".to_string()))
+            },
+        ]);
+        let control_shift = ModifiersState::CONTROL | ModifiersState::SHIFT;
+        let action = table.find(
+            &character("C"),
+            &character("c"),
+            control_shift,
+            TermMode::empty(),
+        );
+        assert_eq!(action, Some(&Action::None));
+
+        let up = Key::Named(NamedKey::ArrowUp);
+        let alt = ModifiersState::ALT;
+        assert!(table.find(&up, &up, alt, TermMode::empty()).is_none());
+        let action = table.find(&up, &up, alt, TermMode::ALT_SCREEN);
+        assert_eq!(action, Some(&Action::SendString("\x1b[1;3A".to_string())));
+
+        // 代替スクリーンではページ単位のスクロールを割り当てない
+        let page_up = Key::Named(NamedKey::PageUp);
+        let shift = ModifiersState::SHIFT;
+        let action = table.find(&page_up, &page_up, shift, TermMode::empty());
+        assert_eq!(action, Some(&Action::ScrollPageUp));
+        assert!(table
+            .find(&page_up, &page_up, shift, TermMode::ALT_SCREEN)
+            .is_none());
+    }
+
+    // 解釈できない割り当ては無視する
+    #[test]
+    fn invalid() {
+        let table = KeyBindingTable::new(&[
+            KeyBinding::new("Hyper", "", Action::Copy),
+            KeyBinding::new("A", "Meta", Action::Copy),
+        ]);
+        assert_eq!(table.source().len(), 2);
+        let a = character("a");
+        assert!(table
+            .find(&a, &a, ModifiersState::empty(), TermMode::empty())
+            .is_none());
+    }
+}
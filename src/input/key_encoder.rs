@@ -0,0 +1,407 @@
+use alacritty_terminal::term::TermMode;
+use winit::{
+    event::{ElementState, KeyEvent},
+    keyboard::{Key, KeyLocation, ModifiersState, NamedKey},
+    platform::modifier_supplement::KeyEventExtModifierSupplement,
+};
+
+use super::kitty_encoder;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyEventKind {
+    Press,
+    Repeat,
+    Release,
+}
+
+// 変換に必要なキー入力の情報
+pub struct KeyInput<'a> {
+    pub key: &'a Key,
+
+    // 修飾キーを外したキー。kitty のキーコードに使う
+    pub unmodified_key: &'a Key,
+
+    pub location: KeyLocation,
+
+    // IME やデッドキーで合成された文字。修飾キーのない文字入力に使う
+    pub text: Option<&'a str>,
+
+    pub modifiers: ModifiersState,
+    pub kind: KeyEventKind,
+}
+
+impl<'a> KeyInput<'a> {
+    pub fn new(key: &'a Key, modifiers: ModifiersState) -> Self {
+        Self {
+            key,
+            unmodified_key: key,
+            location: KeyLocation::Standard,
+            text: None,
+            modifiers,
+            kind: KeyEventKind::Press,
+        }
+    }
+
+    pub fn with_unmodified_key(mut self, unmodified_key: &'a Key) -> Self {
+        self.unmodified_key = unmodified_key;
+        self
+    }
+
+    pub fn with_location(mut self, location: KeyLocation) -> Self {
+        self.location = location;
+        self
+    }
+
+    pub fn with_text(mut self, text: Option<&'a str>) -> Self {
+        self.text = text;
+        self
+    }
+
+    pub fn with_kind(mut self, kind: KeyEventKind) -> Self {
+        self.kind = kind;
+        self
+    }
+}
+
+// キー入力を xterm と同じバイト列にして pty に送る
+// kitty keyboard protocol が有効なら CSI u で送る
+pub struct KeyEncoder;
+
+impl KeyEncoder {
+    pub fn encode_event(
+        event: &KeyEvent,
+        modifiers: ModifiersState,
+        mode: TermMode,
+    ) -> Option<Vec<u8>> {
+        let kind = match (event.state, event.repeat) {
+            (ElementState::Released, _) => KeyEventKind::Release,
+            (ElementState::Pressed, true) => KeyEventKind::Repeat,
+            (ElementState::Pressed, false) => KeyEventKind::Press,
+        };
+        let unmodified_key = event.key_without_modifiers();
+        let input = KeyInput::new(&event.logical_key, modifiers)
+            .with_unmodified_key(&unmodified_key)
+            .with_location(event.location)
+            .with_text(event.text.as_deref())
+            .with_kind(kind);
+        Self::encode(&input, mode)
+    }
+
+    // 送るものがなければ None
+    pub fn encode(input: &KeyInput, mode: TermMode) -> Option<Vec<u8>> {
+        if mode.intersects(TermMode::KITTY_KEYBOARD_PROTOCOL) {
+            return kitty_encoder::encode(input, mode);
+        }
+
+        // 従来の方式ではキーを離したことは伝えない
+        if input.kind == KeyEventKind::Release {
+            return None;
+        }
+
+        let (key, text, modifiers) = (input.key, input.text, input.modifiers);
+
+        // アプリケーションキーパッドモードならテンキーは SS3 で送る
+        if input.location == KeyLocation::Numpad && mode.contains(TermMode::APP_KEYPAD) {
+            if let Some(code) = Self::keypad_code(key) {
+                return Some(vec![0x1b, b'O', code]);
+            }
+        }
+
+        match key {
+            Key::Named(named) => Self::encode_named(*named, text, modifiers, mode),
+            Key::Character(characters) => Self::encode_character(characters, text, modifiers),
+            _ => text.map(|text| text.as_bytes().to_vec()),
+        }
+    }
+
+    fn encode_named(
+        key: NamedKey,
+        text: Option<&str>,
+        modifiers: ModifiersState,
+        mode: TermMode,
+    ) -> Option<Vec<u8>> {
+        let parameter = Self::modifier_parameter(modifiers);
+        let with_alt = |bytes: &[u8]| {
+            let mut result = Vec::with_capacity(bytes.len() + 1);
+            if modifiers.alt_key() {
+                result.push(0x1b);
+            }
+            result.extend_from_slice(bytes);
+            Some(result)
+        };
+
+        // カーソルキーと Home/End は修飾キーがなければ DECCKM で SS3 と CSI を切り替える
+        let cursor = match key {
+            NamedKey::ArrowUp => Some(b'A'),
+            NamedKey::ArrowDown => Some(b'B'),
+            NamedKey::ArrowRight => Some(b'C'),
+            NamedKey::ArrowLeft => Some(b'D'),
+            NamedKey::Home => Some(b'H'),
+            NamedKey::End => Some(b'F'),
+            _ => None,
+        };
+        if let Some(code) = cursor {
+            let mut bytes = match parameter {
+                Some(parameter) => format!("\x1b[1;{}", parameter).into_bytes(),
+                None if mode.contains(TermMode::APP_CURSOR) => vec![0x1b, b'O'],
+                None => vec![0x1b, b'['],
+            };
+            bytes.push(code);
+            return Some(bytes);
+        }
+
+        // F1 から F4 は SS3、修飾キーつきなら CSI 1;m
+        let function = match key {
+            NamedKey::F1 => Some(b'P'),
+            NamedKey::F2 => Some(b'Q'),
+            NamedKey::F3 => Some(b'R'),
+            NamedKey::F4 => Some(b'S'),
+            _ => None,
+        };
+        if let Some(code) = function {
+            let mut bytes = match parameter {
+                Some(parameter) => format!("\x1b[1;{}", parameter).into_bytes(),
+                None => vec![0x1b, b'O'],
+            };
+            bytes.push(code);
+            return Some(bytes);
+        }
+
+        // CSI n ~ で送るキー
+        let tilde = match key {
+            NamedKey::Insert => Some(2),
+            NamedKey::Delete => Some(3),
+            NamedKey::PageUp => Some(5),
+            NamedKey::PageDown => Some(6),
+            NamedKey::F5 => Some(15),
+            NamedKey::F6 => Some(17),
+            NamedKey::F7 => Some(18),
+            NamedKey::F8 => Some(19),
+            NamedKey::F9 => Some(20),
+            NamedKey::F10 => Some(21),
+            NamedKey::F11 => Some(23),
+            NamedKey::F12 => Some(24),
+            _ => None,
+        };
+        if let Some(number) = tilde {
+            return Some(match parameter {
+                Some(parameter) => format!("\x1b[{};{}~", number, parameter).into_bytes(),
+                None => format!("\x1b[{}~", number).into_bytes(),
+            });
+        }
+
+        match key {
+            NamedKey::Enter => with_alt(b"\r"),
+            NamedKey::Tab if modifiers.shift_key() => with_alt(b"\x1b[Z"),
+            NamedKey::Tab => with_alt(b"\t"),
+            NamedKey::Backspace if modifiers.control_key() => with_alt(b"\x08"),
+            NamedKey::Backspace => with_alt(b"\x7f"),
+            NamedKey::Escape => with_alt(b"\x1b"),
+            NamedKey::Space if modifiers.control_key() => with_alt(b"\x00"),
+            NamedKey::Space => with_alt(b" "),
+            _ => text.map(|text| text.as_bytes().to_vec()),
+        }
+    }
+
+    fn encode_character(
+        characters: &str,
+        text: Option<&str>,
+        modifiers: ModifiersState,
+    ) -> Option<Vec<u8>> {
+        // Super との組み合わせはショートカットに使うので送らない
+        if modifiers.super_key() {
+            return None;
+        }
+
+        let mut bytes = Vec::new();
+        if modifiers.alt_key() {
+            bytes.push(0x1b);
+        }
+
+        // Ctrl との組み合わせは制御文字にする
+        let mut chars = characters.chars();
+        let control = match (chars.next(), chars.next()) {
+            (Some(c), None) if modifiers.control_key() => Self::control_character(c),
+            _ => None,
+        };
+        match control {
+            Some(control) => bytes.push(control),
+            None => {
+                // 修飾キーがなければデッドキーなどで合成された文字を使う
+                let text = match text {
+                    Some(text) if !modifiers.alt_key() && !modifiers.control_key() => text,
+                    _ => characters,
+                };
+                bytes.extend_from_slice(text.as_bytes());
+            }
+        }
+        Some(bytes)
+    }
+
+    // Ctrl と組み合わせたときの制御文字
+    fn control_character(c: char) -> Option<u8> {
+        let code = match c.to_ascii_lowercase() {
+            c @ 'a'..='z' => c as u8 - b'a' + 1,
+            '@' | '2' | ' ' => 0x00,
+            '[' | '3' => 0x1b,
+            '\\' | '4' => 0x1c,
+            ']' | '5' => 0x1d,
+            '^' | '6' => 0x1e,
+            '_' | '7' | '/' => 0x1f,
+            '?' | '8' => 0x7f,
+            _ => return None,
+        };
+        Some(code)
+    }
+
+    // アプリケーションキーパッドモードで SS3 の後に続く文字
+    fn keypad_code(key: &Key) -> Option<u8> {
+        let code = match key {
+            Key::Named(NamedKey::Enter) => b'M',
+            Key::Character(characters) => match characters.as_str() {
+                digit @ ("0" | "1" | "2" | "3" | "4" | "5" | "6" | "7" | "8" | "9") => {
+                    b'p' + (digit.as_bytes()[0] - b'0')
+                }
+                "*" => b'j',
+                "+" => b'k',
+                "," => b'l',
+                "-" => b'm',
+                "." => b'n',
+                "/" => b'o',
+                "=" => b'X',
+                _ => return None,
+            },
+            _ => return None,
+        };
+        Some(code)
+    }
+
+    // CSI の修飾キーのパラメーター。修飾キーがなければ None
+    pub(super) fn modifier_parameter(modifiers: ModifiersState) -> Option<u8> {
+        let mut parameter = 1;
+        if modifiers.shift_key() {
+            parameter += 1;
+        }
+        if modifiers.alt_key() {
+            parameter += 2;
+        }
+        if modifiers.control_key() {
+            parameter += 4;
+        }
+        if modifiers.super_key() {
+            parameter += 8;
+        }
+        (parameter != 1).then_some(parameter)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alacritty_terminal::term::TermMode;
+    use winit::keyboard::{Key, KeyLocation, ModifiersState, NamedKey};
+
+    use super::{KeyEncoder, KeyEventKind, KeyInput};
+
+    fn character(c: &str) -> Key {
+        Key::Character(c.into())
+    }
+
+    #[test]
+    fn encode() {
+        let none = ModifiersState::empty();
+        let shift = ModifiersState::SHIFT;
+        let alt = ModifiersState::ALT;
+        let ctrl = ModifiersState::CONTROL;
+        let normal = TermMode::empty();
+        let app_cursor = TermMode::APP_CURSOR;
+
+        #[rustfmt::skip]
+        let table: Vec<(Key, ModifiersState, TermMode, &[u8])> = vec![
+            (Key::Named(NamedKey::ArrowUp), none, normal, b"\x1b[A"),
+            (Key::Named(NamedKey::ArrowDown), none, normal, b"\x1b[B"),
+            (Key::Named(NamedKey::ArrowRight), none, normal, b"\x1b[C"),
+            (Key::Named(NamedKey::ArrowLeft), none, normal, b"\x1b[D"),
+            (Key::Named(NamedKey::ArrowUp), none, app_cursor, b"\x1bOA"),
+            (Key::Named(NamedKey::Home), none, app_cursor, b"\x1bOH"),
+            (Key::Named(NamedKey::End), none, normal, b"\x1b[F"),
+            (Key::Named(NamedKey::ArrowUp), shift, app_cursor, b"\x1b[1;2A"),
+            (Key::Named(NamedKey::ArrowLeft), ctrl, normal, b"\x1b[1;5D"),
+            (Key::Named(NamedKey::ArrowRight), alt | ctrl, normal, b"\x1b[1;7C"),
+            (Key::Named(NamedKey::PageUp), none, normal, b"\x1b[5~"),
+            (Key::Named(NamedKey::PageDown), shift, normal, b"\x1b[6;2~"),
+            (Key::Named(NamedKey::Insert), none, normal, b"\x1b[2~"),
+            (Key::Named(NamedKey::Delete), ctrl, normal, b"\x1b[3;5~"),
+            (Key::Named(NamedKey::F1), none, normal, b"\x1bOP"),
+            (Key::Named(NamedKey::F4), shift, normal, b"\x1b[1;2S"),
+            (Key::Named(NamedKey::F5), none, normal, b"\x1b[15~"),
+            (Key::Named(NamedKey::F12), ctrl, normal, b"\x1b[24;5~"),
+            (Key::Named(NamedKey::Enter), none, normal, b"\r"),
+            (Key::Named(NamedKey::Enter), alt, normal, b"\x1b\r"),
+            (Key::Named(NamedKey::Tab), none, normal, b"\t"),
+            (Key::Named(NamedKey::Tab), shift, normal, b"\x1b[Z"),
+            (Key::Named(NamedKey::Backspace), none, normal, b"\x7f"),
+            (Key::Named(NamedKey::Backspace), ctrl, normal, b"\x08"),
+            (Key::Named(NamedKey::Escape), none, normal, b"\x1b"),
+            (Key::Named(NamedKey::Space), ctrl, normal, b"\x00"),
+            (character("a"), none, normal, b"a"),
+            (character("A"), shift, normal, b"A"),
+            (character("c"), ctrl, normal, b"\x03"),
+            (character("["), ctrl, normal, b"\x1b"),
+            (character("x"), alt, normal, b"\x1bx"),
+            (character("c"), alt | ctrl, normal, b"\x1b\x03"),
+            (character("あ"), none, normal, "あ".as_bytes()),
+        ];
+
+        for (key, modifiers, mode, expected) in table {
+            let bytes = KeyEncoder::encode(&KeyInput::new(&key, modifiers), mode);
+            assert_eq!(
+                bytes.as_deref(),
+                Some(expected),
+                "{:?} {:?} {:?}",
+                key,
+                modifiers,
+                mode
+            );
+        }
+    }
+
+    // テンキーはアプリケーションキーパッドモードのときだけ SS3 で送る
+    #[test]
+    fn keypad() {
+        let none = ModifiersState::empty();
+        let app_keypad = TermMode::APP_KEYPAD;
+
+        #[rustfmt::skip]
+        let table: Vec<(Key, TermMode, &[u8])> = vec![
+            (character("1"), TermMode::empty(), b"1"),
+            (character("1"), app_keypad, b"\x1bOq"),
+            (character("0"), app_keypad, b"\x1bOp"),
+            (character("+"), app_keypad, b"\x1bOk"),
+            (Key::Named(NamedKey::Enter), app_keypad, b"\x1bOM"),
+            (Key::Named(NamedKey::Enter), TermMode::empty(), b"\r"),
+        ];
+
+        for (key, mode, expected) in table {
+            let input = KeyInput::new(&key, none).with_location(KeyLocation::Numpad);
+            let bytes = KeyEncoder::encode(&input, mode);
+            assert_eq!(bytes.as_deref(), Some(expected), "{:?} {:?}", key, mode);
+        }
+    }
+
+    // 修飾キーのない文字入力は合成済みの文字を送り、Super との組み合わせは送らない
+    #[test]
+    fn text() {
+        let key = character("e");
+        let input = KeyInput::new(&key, ModifiersState::empty()).with_text(Some("é"));
+        let bytes = KeyEncoder::encode(&input, TermMode::empty());
+        assert_eq!(bytes.as_deref(), Some("é".as_bytes()));
+
+        let key = character("c");
+        let input = KeyInput::new(&key, ModifiersState::SUPER).with_text(Some("c"));
+        assert!(KeyEncoder::encode(&input, TermMode::empty()).is_none());
+
+        // 従来の方式では離したキーは送らない
+        let input = KeyInput::new(&key, ModifiersState::empty()).with_kind(KeyEventKind::Release);
+        assert!(KeyEncoder::encode(&input, TermMode::empty()).is_none());
+    }
+}
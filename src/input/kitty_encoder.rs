@@ -0,0 +1,342 @@
+use alacritty_terminal::term::TermMode;
+use winit::keyboard::{Key, KeyLocation, NamedKey};
+
+use super::key_encoder::{KeyEncoder, KeyEventKind, KeyInput};
+
+// kitty keyboard protocol のキー
+// code と終端文字で CSI code ; modifiers u や CSI 1 ; modifiers A の形になる
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct KittyKey {
+    code: u32,
+    terminator: u8,
+    kind: KittyKeyKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum KittyKeyKind {
+    // 文字を入力するキー
+    Text,
+
+    // Enter, Tab, Backspace は修飾キーがなければ従来のバイト列のまま
+    // プログラムが異常終了してもシェルで reset を打てるようにするため
+    Legacy,
+
+    // 矢印キーやファンクションキーなど
+    Functional,
+
+    // 修飾キーとロックキー。すべてのキーを CSI u で送るときだけ報告する
+    Modifier,
+}
+
+impl KittyKey {
+    fn new(code: u32, terminator: u8, kind: KittyKeyKind) -> Self {
+        Self {
+            code,
+            terminator,
+            kind,
+        }
+    }
+
+    fn functional(code: u32, terminator: u8) -> Self {
+        Self::new(code, terminator, KittyKeyKind::Functional)
+    }
+}
+
+// 有効なフラグにしたがって CSI u のバイト列にする
+// https://sw.kovidgoyal.net/kitty/keyboard-protocol/
+pub fn encode(input: &KeyInput, mode: TermMode) -> Option<Vec<u8>> {
+    let report_event_types = mode.contains(TermMode::REPORT_EVENT_TYPES);
+    let report_all_keys = mode.contains(TermMode::REPORT_ALL_KEYS_AS_ESC);
+
+    // イベントの種類を伝えないなら、離したことは送らずリピートは押したことにする
+    let kind = match input.kind {
+        KeyEventKind::Release if !report_event_types => return None,
+        KeyEventKind::Repeat if !report_event_types => KeyEventKind::Press,
+        kind => kind,
+    };
+
+    let modifiers = input.modifiers;
+    let Some(key) = kitty_key(input) else {
+        // 対応するキーコードがなければ文字だけ送る
+        return (kind != KeyEventKind::Release && !report_all_keys)
+            .then(|| input.text.map(|text| text.as_bytes().to_vec()))
+            .flatten();
+    };
+
+    // 曖昧にならないキーは従来どおり送る
+    let legacy = !report_all_keys
+        && match key.kind {
+            KittyKeyKind::Text => {
+                !(modifiers.alt_key() || modifiers.control_key() || modifiers.super_key())
+            }
+            KittyKeyKind::Legacy => modifiers.is_empty(),
+            KittyKeyKind::Functional => {
+                modifiers.is_empty() && kind == KeyEventKind::Press && key.terminator != b'u'
+            }
+            KittyKeyKind::Modifier => return None,
+        };
+    if legacy {
+        // 文字は離したことを伝えられない
+        if kind == KeyEventKind::Release {
+            return None;
+        }
+        let mode = mode.difference(TermMode::KITTY_KEYBOARD_PROTOCOL);
+        return KeyEncoder::encode(input, mode);
+    }
+
+    let mut sequence = format!("\x1b[{}", key.code);
+
+    // シフトを押したときの文字
+    // 基本レイアウトのキーは分からないので送らない
+    if key.kind == KittyKeyKind::Text
+        && mode.contains(TermMode::REPORT_ALTERNATE_KEYS)
+        && modifiers.shift_key()
+    {
+        if let Some(shifted) = first_character(input.key) {
+            if shifted as u32 != key.code {
+                sequence.push_str(&format!(":{}", shifted as u32));
+            }
+        }
+    }
+
+    let parameter = KeyEncoder::modifier_parameter(modifiers);
+    let event_type = match kind {
+        KeyEventKind::Press => None,
+        KeyEventKind::Repeat => Some(2),
+        KeyEventKind::Release => Some(3),
+    };
+    let text = associated_text(input, mode, kind);
+    if parameter.is_some() || event_type.is_some() || text.is_some() {
+        sequence.push(';');
+        if parameter.is_some() || event_type.is_some() {
+            sequence.push_str(&parameter.unwrap_or(1).to_string());
+        }
+        if let Some(event_type) = event_type {
+            sequence.push_str(&format!(":{event_type}"));
+        }
+    } else if key.code == 1 && key.terminator != b'u' {
+        // CSI 1 A は CSI A と書く
+        sequence.truncate(2);
+    }
+    if let Some(text) = text {
+        sequence.push(';');
+        sequence.push_str(&text);
+    }
+    sequence.push(key.terminator as char);
+
+    Some(sequence.into_bytes())
+}
+
+fn kitty_key(input: &KeyInput) -> Option<KittyKey> {
+    if input.location == KeyLocation::Numpad {
+        if let Some(key) = keypad_key(input.key) {
+            return Some(key);
+        }
+    }
+
+    match input.key {
+        Key::Named(named) => named_key(*named, input.location),
+        Key::Character(_) => {
+            // キーコードはシフトを外した小文字
+            let base = first_character(input.unmodified_key)
+                .or_else(|| first_character(input.key))?
+                .to_lowercase()
+                .next()?;
+            Some(KittyKey::new(base as u32, b'u', KittyKeyKind::Text))
+        }
+        _ => None,
+    }
+}
+
+fn named_key(named: NamedKey, location: KeyLocation) -> Option<KittyKey> {
+    use KittyKeyKind::{Legacy, Modifier, Text};
+
+    let right = location == KeyLocation::Right;
+    let key = match named {
+        NamedKey::Space => KittyKey::new(32, b'u', Text),
+        NamedKey::Enter => KittyKey::new(13, b'u', Legacy),
+        NamedKey::Tab => KittyKey::new(9, b'u', Legacy),
+        NamedKey::Backspace => KittyKey::new(127, b'u', Legacy),
+        NamedKey::Escape => KittyKey::functional(27, b'u'),
+        NamedKey::Insert => KittyKey::functional(2, b'~'),
+        NamedKey::Delete => KittyKey::functional(3, b'~'),
+        NamedKey::ArrowLeft => KittyKey::functional(1, b'D'),
+        NamedKey::ArrowRight => KittyKey::functional(1, b'C'),
+        NamedKey::ArrowUp => KittyKey::functional(1, b'A'),
+        NamedKey::ArrowDown => KittyKey::functional(1, b'B'),
+        NamedKey::PageUp => KittyKey::functional(5, b'~'),
+        NamedKey::PageDown => KittyKey::functional(6, b'~'),
+        NamedKey::Home => KittyKey::functional(1, b'H'),
+        NamedKey::End => KittyKey::functional(1, b'F'),
+        NamedKey::PrintScreen => KittyKey::functional(57361, b'u'),
+        NamedKey::Pause => KittyKey::functional(57362, b'u'),
+        NamedKey::ContextMenu => KittyKey::functional(57363, b'u'),
+        NamedKey::F1 => KittyKey::functional(1, b'P'),
+        NamedKey::F2 => KittyKey::functional(1, b'Q'),
+        // CSI R はカーソル位置の報告とぶつかるので F3 は ~ で送る
+        NamedKey::F3 => KittyKey::functional(13, b'~'),
+        NamedKey::F4 => KittyKey::functional(1, b'S'),
+        NamedKey::F5 => KittyKey::functional(15, b'~'),
+        NamedKey::F6 => KittyKey::functional(17, b'~'),
+        NamedKey::F7 => KittyKey::functional(18, b'~'),
+        NamedKey::F8 => KittyKey::functional(19, b'~'),
+        NamedKey::F9 => KittyKey::functional(20, b'~'),
+        NamedKey::F10 => KittyKey::functional(21, b'~'),
+        NamedKey::F11 => KittyKey::functional(23, b'~'),
+        NamedKey::F12 => KittyKey::functional(24, b'~'),
+        NamedKey::CapsLock => KittyKey::new(57358, b'u', Modifier),
+        NamedKey::ScrollLock => KittyKey::new(57359, b'u', Modifier),
+        NamedKey::NumLock => KittyKey::new(57360, b'u', Modifier),
+        NamedKey::Shift => KittyKey::new(if right { 57447 } else { 57441 }, b'u', Modifier),
+        NamedKey::Control => KittyKey::new(if right { 57448 } else { 57442 }, b'u', Modifier),
+        NamedKey::Alt => KittyKey::new(if right { 57449 } else { 57443 }, b'u', Modifier),
+        NamedKey::Super => KittyKey::new(if right { 57450 } else { 57444 }, b'u', Modifier),
+        _ => return None,
+    };
+    Some(key)
+}
+
+// テンキーは専用のキーコードで送る
+fn keypad_key(key: &Key) -> Option<KittyKey> {
+    let code = match key {
+        Key::Named(NamedKey::Enter) => return Some(KittyKey::functional(57414, b'u')),
+        Key::Character(characters) => match characters.as_str() {
+            digit @ ("0" | "1" | "2" | "3" | "4" | "5" | "6" | "7" | "8" | "9") => {
+                57399 + digit.parse::<u32>().ok()?
+            }
+            "." => 57409,
+            "/" => 57410,
+            "*" => 57411,
+            "-" => 57412,
+            "+" => 57413,
+            "=" => 57415,
+            _ => return None,
+        },
+        _ => return None,
+    };
+    Some(KittyKey::new(code, b'u', KittyKeyKind::Text))
+}
+
+// キーで入力される文字をコードポイントを : でつないで送る
+// すべてのキーを CSI u で送るときしか使えない
+fn associated_text(input: &KeyInput, mode: TermMode, kind: KeyEventKind) -> Option<String> {
+    if !mode.contains(TermMode::REPORT_ASSOCIATED_TEXT | TermMode::REPORT_ALL_KEYS_AS_ESC)
+        || kind == KeyEventKind::Release
+    {
+        return None;
+    }
+
+    let codes = input
+        .text?
+        .chars()
+        .filter(|c| !c.is_control())
+        .map(|c| (c as u32).to_string())
+        .collect::<Vec<_>>();
+    (!codes.is_empty()).then(|| codes.join(":"))
+}
+
+fn first_character(key: &Key) -> Option<char> {
+    match key {
+        Key::Character(characters) => characters.chars().next(),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alacritty_terminal::term::TermMode;
+    use winit::keyboard::{Key, KeyLocation, ModifiersState, NamedKey};
+
+    use crate::input::key_encoder::{KeyEncoder, KeyEventKind, KeyInput};
+
+    fn character(c: &str) -> Key {
+        Key::Character(c.into())
+    }
+
+    // Ctrl+I と Tab を区別できる
+    #[test]
+    fn disambiguate() {
+        let mode = TermMode::DISAMBIGUATE_ESC_CODES;
+        let none = ModifiersState::empty();
+        let ctrl = ModifiersState::CONTROL;
+        let cases = [
+            (character("i"), ctrl, "\x1b[105;5u"),
+            (Key::Named(NamedKey::Tab), none, "\t"),
+            (Key::Named(NamedKey::Tab), ctrl, "\x1b[9;5u"),
+            (Key::Named(NamedKey::Escape), none, "\x1b[27u"),
+            (Key::Named(NamedKey::Enter), none, "\r"),
+            (Key::Named(NamedKey::ArrowUp), none, "\x1b[A"),
+            (Key::Named(NamedKey::ArrowUp), ctrl, "\x1b[1;5A"),
+            (Key::Named(NamedKey::F3), ctrl, "\x1b[13;5~"),
+            (character("a"), ModifiersState::ALT, "\x1b[97;3u"),
+            (character("a"), none, "a"),
+        ];
+        for (key, modifiers, expected) in cases {
+            let input = KeyInput::new(&key, modifiers).with_text(Some("a"));
+            let bytes = KeyEncoder::encode(&input, mode);
+            assert_eq!(bytes.as_deref(), Some(expected.as_bytes()), "{key:?}");
+        }
+    }
+
+    // イベントの種類を伝えるときだけ離したこととリピートを送る
+    #[test]
+    fn event_types() {
+        let key = Key::Named(NamedKey::ArrowLeft);
+        let none = ModifiersState::empty();
+        let release = KeyInput::new(&key, none).with_kind(KeyEventKind::Release);
+        let repeat = KeyInput::new(&key, none).with_kind(KeyEventKind::Repeat);
+
+        let mode = TermMode::DISAMBIGUATE_ESC_CODES;
+        assert!(KeyEncoder::encode(&release, mode).is_none());
+        assert_eq!(
+            KeyEncoder::encode(&repeat, mode).as_deref(),
+            Some(&b"\x1b[D"[..])
+        );
+
+        let mode = mode | TermMode::REPORT_EVENT_TYPES;
+        let bytes = KeyEncoder::encode(&release, mode);
+        assert_eq!(bytes.as_deref(), Some(&b"\x1b[1;1:3D"[..]));
+        let bytes = KeyEncoder::encode(&repeat, mode);
+        assert_eq!(bytes.as_deref(), Some(&b"\x1b[1;1:2D"[..]));
+
+        // 文字は離したことを伝えられない
+        let key = character("a");
+        let input = KeyInput::new(&key, none).with_kind(KeyEventKind::Release);
+        assert!(KeyEncoder::encode(&input, mode).is_none());
+    }
+
+    // すべてのキーを CSI u で送り、シフトした文字と入力される文字を付ける
+    #[test]
+    fn all_keys() {
+        let mode = TermMode::DISAMBIGUATE_ESC_CODES
+            | TermMode::REPORT_ALL_KEYS_AS_ESC
+            | TermMode::REPORT_ALTERNATE_KEYS
+            | TermMode::REPORT_ASSOCIATED_TEXT;
+        let none = ModifiersState::empty();
+
+        let key = character("a");
+        let input = KeyInput::new(&key, none).with_text(Some("a"));
+        let bytes = KeyEncoder::encode(&input, mode);
+        assert_eq!(bytes.as_deref(), Some(&b"\x1b[97;;97u"[..]));
+
+        let (key, unmodified_key) = (character("A"), character("a"));
+        let input = KeyInput::new(&key, ModifiersState::SHIFT)
+            .with_unmodified_key(&unmodified_key)
+            .with_text(Some("A"));
+        let bytes = KeyEncoder::encode(&input, mode);
+        assert_eq!(bytes.as_deref(), Some(&b"\x1b[97:65;2;65u"[..]));
+
+        let key = Key::Named(NamedKey::Enter);
+        let bytes = KeyEncoder::encode(&KeyInput::new(&key, none), mode);
+        assert_eq!(bytes.as_deref(), Some(&b"\x1b[13u"[..]));
+
+        let key = Key::Named(NamedKey::Shift);
+        let input = KeyInput::new(&key, none).with_location(KeyLocation::Right);
+        let bytes = KeyEncoder::encode(&input, mode);
+        assert_eq!(bytes.as_deref(), Some(&b"\x1b[57447u"[..]));
+
+        // 修飾キーだけを押したことは曖昧さをなくすだけなら送らない
+        let mode = TermMode::DISAMBIGUATE_ESC_CODES;
+        assert!(KeyEncoder::encode(&input, mode).is_none());
+    }
+}
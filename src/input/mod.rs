@@ -0,0 +1,12 @@
+mod key_binding_table;
+mod key_encoder;
+mod kitty_encoder;
+mod mouse_encoder;
+mod mouse_state;
+mod paste_encoder;
+
+pub use key_binding_table::KeyBindingTable;
+pub use key_encoder::KeyEncoder;
+pub use mouse_encoder::MouseEncoder;
+pub use mouse_state::MouseState;
+pub use paste_encoder::PasteEncoder;
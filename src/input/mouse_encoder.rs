@@ -0,0 +1,239 @@
+use alacritty_terminal::{index::Point, term::TermMode};
+use winit::{
+    event::{ElementState, MouseButton},
+    keyboard::ModifiersState,
+};
+
+// マウスの操作を端末のマウスモードに合わせたバイト列にする
+// 報告するかどうかは MOUSE_REPORT_CLICK (1000), MOUSE_DRAG (1002), MOUSE_MOTION (1003) で決まり
+// 形式は SGR (1006), UTF-8 (1005), なければ X10 と同じ CSI M Cb Cx Cy
+// X10 のモード (DECSET 9) は alacritty_terminal が保持していないので扱わない
+pub struct MouseEncoder;
+
+impl MouseEncoder {
+    // ボタンを押した、離した
+    pub fn encode_button(
+        button: MouseButton,
+        state: ElementState,
+        point: Point<usize>,
+        modifiers: ModifiersState,
+        mode: TermMode,
+    ) -> Option<Vec<u8>> {
+        if !mode.intersects(TermMode::MOUSE_MODE) {
+            return None;
+        }
+
+        let code = Self::button_code(button)? + Self::modifier_code(modifiers);
+        Self::encode(code, state == ElementState::Released, point, mode)
+    }
+
+    // カーソルの移動
+    // ボタンを押したままなら MOUSE_DRAG か MOUSE_MOTION、押していなければ MOUSE_MOTION のときだけ送る
+    pub fn encode_motion(
+        button: Option<MouseButton>,
+        point: Point<usize>,
+        modifiers: ModifiersState,
+        mode: TermMode,
+    ) -> Option<Vec<u8>> {
+        let code = match button {
+            Some(button) if mode.intersects(TermMode::MOUSE_DRAG | TermMode::MOUSE_MOTION) => {
+                Self::button_code(button)?
+            }
+            None if mode.contains(TermMode::MOUSE_MOTION) => 3,
+            _ => return None,
+        };
+        Self::encode(
+            code + 32 + Self::modifier_code(modifiers),
+            false,
+            point,
+            mode,
+        )
+    }
+
+    // ホイール。正なら上向きで、1 行ごとにボタン 4 か 5 を押したことにする
+    pub fn encode_wheel(
+        lines: i32,
+        point: Point<usize>,
+        modifiers: ModifiersState,
+        mode: TermMode,
+    ) -> Option<Vec<u8>> {
+        if !mode.intersects(TermMode::MOUSE_MODE) || lines == 0 {
+            return None;
+        }
+
+        let code = if lines > 0 { 64 } else { 65 } + Self::modifier_code(modifiers);
+        let report = Self::encode(code, false, point, mode)?;
+        Some(report.repeat(lines.unsigned_abs() as usize))
+    }
+
+    // 代替スクリーンでマウスモードが無効なら、ホイールを矢印キーとして送る
+    pub fn encode_alternate_scroll(lines: i32, mode: TermMode) -> Option<Vec<u8>> {
+        if !mode.contains(TermMode::ALT_SCREEN | TermMode::ALTERNATE_SCROLL) || lines == 0 {
+            return None;
+        }
+
+        let prefix: &[u8] = if mode.contains(TermMode::APP_CURSOR) {
+            b"\x1bO"
+        } else {
+            b"\x1b["
+        };
+        let arrow = if lines > 0 { b'A' } else { b'B' };
+        let bytes = [prefix, &[arrow]].concat();
+        Some(bytes.repeat(lines.unsigned_abs() as usize))
+    }
+
+    fn encode(code: u8, released: bool, point: Point<usize>, mode: TermMode) -> Option<Vec<u8>> {
+        let (column, line) = (point.column.0 + 1, point.line + 1);
+
+        // SGR は離したボタンも分かり、座標の上限もない
+        if mode.contains(TermMode::SGR_MOUSE) {
+            let terminator = if released { 'm' } else { 'M' };
+            return Some(format!("\x1b[<{code};{column};{line}{terminator}").into_bytes());
+        }
+
+        // それ以外の形式ではどのボタンを離したかは送れない
+        let code = if released { 3 | (code & !3) } else { code };
+        let mut bytes = vec![0x1b, b'[', b'M', 32 + code];
+        for position in [column, line] {
+            if mode.contains(TermMode::UTF8_MOUSE) {
+                // 2 バイトの UTF-8 で表せる範囲まで
+                let c = char::from_u32(32 + position as u32).filter(|c| (*c as u32) < 0x800)?;
+                let mut buffer = [0; 4];
+                bytes.extend_from_slice(c.encode_utf8(&mut buffer).as_bytes());
+            } else {
+                // 1 バイトで表せない位置は送れない
+                bytes.push(u8::try_from(32 + position).ok()?);
+            }
+        }
+        Some(bytes)
+    }
+
+    fn button_code(button: MouseButton) -> Option<u8> {
+        let code = match button {
+            MouseButton::Left => 0,
+            MouseButton::Middle => 1,
+            MouseButton::Right => 2,
+            MouseButton::Back => 128,
+            MouseButton::Forward => 129,
+            MouseButton::Other(_) => return None,
+        };
+        Some(code)
+    }
+
+    fn modifier_code(modifiers: ModifiersState) -> u8 {
+        let mut code = 0;
+        if modifiers.shift_key() {
+            code += 4;
+        }
+        if modifiers.alt_key() {
+            code += 8;
+        }
+        if modifiers.control_key() {
+            code += 16;
+        }
+        code
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alacritty_terminal::{
+        index::{Column, Point},
+        term::TermMode,
+    };
+    use winit::{
+        event::{ElementState, MouseButton},
+        keyboard::ModifiersState,
+    };
+
+    use super::MouseEncoder;
+
+    fn point(column: usize, line: usize) -> Point<usize> {
+        Point::new(line, Column(column))
+    }
+
+    #[test]
+    fn button() {
+        let none = ModifiersState::empty();
+        let click = TermMode::MOUSE_REPORT_CLICK;
+        let cases = [
+            (ElementState::Pressed, click, &b"\x1b[M !\""[..]),
+            (ElementState::Released, click, &b"\x1b[M#!\""[..]),
+            (
+                ElementState::Pressed,
+                click | TermMode::SGR_MOUSE,
+                &b"\x1b[<0;1;2M"[..],
+            ),
+            (
+                ElementState::Released,
+                click | TermMode::SGR_MOUSE,
+                &b"\x1b[<0;1;2m"[..],
+            ),
+        ];
+        for (state, mode, expected) in cases {
+            let bytes =
+                MouseEncoder::encode_button(MouseButton::Left, state, point(0, 1), none, mode);
+            assert_eq!(bytes.as_deref(), Some(expected), "{state:?} {mode:?}");
+        }
+
+        // マウスモードでなければ送らない
+        let bytes = MouseEncoder::encode_button(
+            MouseButton::Left,
+            ElementState::Pressed,
+            point(0, 0),
+            none,
+            TermMode::empty(),
+        );
+        assert!(bytes.is_none());
+    }
+
+    // 1 バイトで表せない位置は UTF-8 なら送れる
+    #[test]
+    fn coordinates() {
+        let none = ModifiersState::empty();
+        let far = point(300, 0);
+        let mode = TermMode::MOUSE_REPORT_CLICK;
+        let pressed = ElementState::Pressed;
+        assert!(
+            MouseEncoder::encode_button(MouseButton::Right, pressed, far, none, mode).is_none()
+        );
+
+        let mode = mode | TermMode::UTF8_MOUSE;
+        let bytes = MouseEncoder::encode_button(MouseButton::Right, pressed, far, none, mode);
+        let mut expected = b"\x1b[M\"".to_vec();
+        expected.extend_from_slice('\u{14d}'.to_string().as_bytes());
+        expected.push(b'!');
+        assert_eq!(bytes, Some(expected));
+    }
+
+    #[test]
+    fn motion() {
+        let none = ModifiersState::empty();
+        let sgr = TermMode::SGR_MOUSE;
+
+        // ボタンを押していない移動は MOUSE_MOTION のときだけ
+        let mode = TermMode::MOUSE_DRAG | sgr;
+        assert!(MouseEncoder::encode_motion(None, point(1, 1), none, mode).is_none());
+        let bytes = MouseEncoder::encode_motion(Some(MouseButton::Left), point(1, 1), none, mode);
+        assert_eq!(bytes.as_deref(), Some(&b"\x1b[<32;2;2M"[..]));
+
+        let mode = TermMode::MOUSE_MOTION | sgr;
+        let bytes = MouseEncoder::encode_motion(None, point(1, 1), ModifiersState::CONTROL, mode);
+        assert_eq!(bytes.as_deref(), Some(&b"\x1b[<51;2;2M"[..]));
+    }
+
+    #[test]
+    fn wheel() {
+        let none = ModifiersState::empty();
+        let mode = TermMode::MOUSE_REPORT_CLICK | TermMode::SGR_MOUSE;
+        let bytes = MouseEncoder::encode_wheel(-2, point(0, 0), none, mode);
+        assert_eq!(bytes.as_deref(), Some(&b"\x1b[<65;1;1M\x1b[<65;1;1M"[..]));
+
+        // 代替スクリーンでは矢印キーになる
+        let mode = TermMode::ALT_SCREEN | TermMode::ALTERNATE_SCROLL | TermMode::APP_CURSOR;
+        assert!(MouseEncoder::encode_wheel(1, point(0, 0), none, mode).is_none());
+        let bytes = MouseEncoder::encode_alternate_scroll(1, mode);
+        assert_eq!(bytes.as_deref(), Some(&b"\x1bOA"[..]));
+        assert!(MouseEncoder::encode_alternate_scroll(1, TermMode::ALTERNATE_SCROLL).is_none());
+    }
+}
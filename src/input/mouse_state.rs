@@ -0,0 +1,85 @@
+use std::time::{Duration, Instant};
+
+use alacritty_terminal::index::Point;
+use winit::event::MouseButton;
+
+// この間隔で同じセルをクリックしたらダブルクリックとみなす
+const MULTI_CLICK_INTERVAL: Duration = Duration::from_millis(400);
+
+// ウィンドウごとのマウスの状態
+#[derive(Debug, Default, Clone, Copy)]
+pub struct MouseState {
+    // ウィンドウの左上からのピクセル座標
+    pub position: (f64, f64),
+
+    // 押したままのボタン。ドラッグの報告に使う
+    pub pressed_button: Option<MouseButton>,
+
+    // 最後に報告したセル。同じセル内の移動は報告しない
+    pub last_point: Option<Point<usize>>,
+
+    // ドラッグで選択範囲を広げている
+    pub is_selecting: bool,
+
+    last_click: Option<(Instant, Point<usize>)>,
+    click_count: u8,
+
+    // タッチパッドのスクロールで 1 行に満たなかったピクセル数
+    scroll_pixels: f64,
+}
+
+impl MouseState {
+    // 連続したクリックの回数。トリプルクリックの次は 1 に戻る
+    pub fn click(&mut self, point: Point<usize>, now: Instant) -> u8 {
+        let is_continued = self.last_click.is_some_and(|(time, last_point)| {
+            last_point == point && now.duration_since(time) <= MULTI_CLICK_INTERVAL
+        });
+        self.click_count = if is_continued {
+            self.click_count % 3 + 1
+        } else {
+            1
+        };
+        self.last_click = Some((now, point));
+        self.click_count
+    }
+
+    // ピクセル単位のスクロールをためて行数にする。正なら上向き
+    pub fn scroll_pixels(&mut self, pixels: f64, cell_height: f64) -> i32 {
+        self.scroll_pixels += pixels;
+        let lines = (self.scroll_pixels / cell_height).trunc();
+        self.scroll_pixels -= lines * cell_height;
+        lines as i32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use alacritty_terminal::index::{Column, Point};
+
+    use super::MouseState;
+
+    #[test]
+    fn click() {
+        let mut mouse_state = MouseState::default();
+        let point = Point::new(0, Column(0));
+        let now = Instant::now();
+        let counts =
+            [0, 100, 200, 300].map(|ms| mouse_state.click(point, now + Duration::from_millis(ms)));
+        assert_eq!(counts, [1, 2, 3, 1]);
+
+        // 間があいたり別のセルだったりしたら数え直す
+        let later = now + Duration::from_secs(1);
+        assert_eq!(mouse_state.click(point, later), 1);
+        assert_eq!(mouse_state.click(Point::new(0, Column(1)), later), 1);
+    }
+
+    // 1 行に満たない分は次のスクロールに持ち越す
+    #[test]
+    fn scroll_pixels() {
+        let mut mouse_state = MouseState::default();
+        let lines = [6.0, 6.0, 25.0, -30.0].map(|pixels| mouse_state.scroll_pixels(pixels, 10.0));
+        assert_eq!(lines, [0, 1, 2, -2]);
+    }
+}
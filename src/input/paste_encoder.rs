@@ -0,0 +1,85 @@
+use alacritty_terminal::term::TermMode;
+
+use crate::config::Newline;
+
+const PASTE_BEGIN: &str = "\x1b[200~";
+const PASTE_END: &str = "\x1b[201~";
+
+// 貼り付ける文字列を端末のモードに合わせたバイト列にする
+pub struct PasteEncoder;
+
+impl PasteEncoder {
+    pub fn encode(text: &str, newline: Newline, mode: TermMode) -> Vec<u8> {
+        let text = Self::convert_newline(text, newline);
+        if !mode.contains(TermMode::BRACKETED_PASTE) {
+            return text.into_bytes();
+        }
+
+        // 途中で貼り付けを終わらせられないように、含まれている開始と終了の印を取り除く
+        // 取り除いた結果また印ができることもあるのでなくなるまで繰り返す
+        let mut text = text;
+        while text.contains(PASTE_BEGIN) || text.contains(PASTE_END) {
+            text = text.replace(PASTE_BEGIN, "").replace(PASTE_END, "");
+        }
+        format!("{PASTE_BEGIN}{text}{PASTE_END}").into_bytes()
+    }
+
+    // 貼り付けただけでコマンドが実行されるかもしれない文字列か
+    // 複数行か、タブ以外の制御文字を含んでいる
+    pub fn needs_confirmation(text: &str) -> bool {
+        text.chars().any(|c| c.is_control() && c != '\t')
+    }
+
+    fn convert_newline(text: &str, newline: Newline) -> String {
+        let to = match newline {
+            Newline::Keep => return text.to_string(),
+            Newline::Cr => "\r",
+            Newline::Lf => "\n",
+            Newline::CrLf => "\r\n",
+        };
+        text.replace("\r\n", "\n")
+            .replace('\r', "\n")
+            .replace('\n', to)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alacritty_terminal::term::TermMode;
+
+    use super::PasteEncoder;
+    use crate::config::Newline;
+
+    #[test]
+    fn bracketed() {
+        let mode = TermMode::BRACKETED_PASTE;
+        let bytes = PasteEncoder::encode("ls\n", Newline::Cr, mode);
+        assert_eq!(bytes, b"\x1b[200~ls\r\x1b[201~");
+
+        // 終了の印を埋め込んでも取り除かれる
+        let bytes = PasteEncoder::encode("a\x1b[20\x1b[201~1~b", Newline::Keep, mode);
+        assert_eq!(bytes, b"\x1b[200~ab\x1b[201~");
+    }
+
+    #[test]
+    fn newline() {
+        let mode = TermMode::empty();
+        let text = "a\r\nb\nc\rd";
+        let cases = [
+            (Newline::Cr, &b"a\rb\rc\rd"[..]),
+            (Newline::Lf, &b"a\nb\nc\nd"[..]),
+            (Newline::CrLf, &b"a\r\nb\r\nc\r\nd"[..]),
+            (Newline::Keep, text.as_bytes()),
+        ];
+        for (newline, expected) in cases {
+            assert_eq!(PasteEncoder::encode(text, newline, mode), expected);
+        }
+    }
+
+    #[test]
+    fn needs_confirmation() {
+        assert!(!PasteEncoder::needs_confirmation("echo\thello"));
+        assert!(PasteEncoder::needs_confirmation("rm -rf build\n"));
+        assert!(PasteEncoder::needs_confirmation("a\x1b[Ab"));
+    }
+}
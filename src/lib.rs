@@ -1,6 +1,9 @@
 mod app;
+mod cli;
+mod clipboard;
 mod config;
 mod gfx;
+mod input;
 mod multiplexers;
 mod tty;
 mod util;
@@ -8,4 +11,5 @@ mod window;
 mod workspace;
 
 pub use app::App;
+pub use cli::CommandLineOptions;
 pub use config::{Config, ConfigService};
@@ -1,6 +1,22 @@
-use shalacritty::App;
+use shalacritty::{App, CommandLineOptions};
 
 #[tokio::main]
 async fn main() {
-    App::run().await;
+    let options = match CommandLineOptions::parse(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{}\n\n{}", message, CommandLineOptions::usage());
+            std::process::exit(2);
+        }
+    };
+    if options.is_help {
+        println!("{}", CommandLineOptions::usage());
+        return;
+    }
+    if options.is_version {
+        println!("shalacritty {}", env!("CARGO_PKG_VERSION"));
+        return;
+    }
+
+    App::run(options).await;
 }
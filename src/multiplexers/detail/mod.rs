@@ -1,3 +1,3 @@
 mod virtual_window_manager;
 
-pub use virtual_window_manager::{VirtualWindowId, VirtualWindowManager};
+pub use virtual_window_manager::{SplitDirection, VirtualWindowId, VirtualWindowManager};
@@ -13,6 +13,17 @@ impl Default for VirtualWindowId {
     }
 }
 
+// 子ウィンドウを並べる向き
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SplitDirection {
+    // 左右に並べる
+    Horizontal,
+
+    // 上下に並べる
+    #[default]
+    Vertical,
+}
+
 pub struct VirtualWindow {
     #[allow(dead_code)]
     pub width: u32,
@@ -36,8 +47,14 @@ pub struct VirtualWindowManager {
     // 親ウィンドウ -> 子ウィンドウ
     hierarchy_table: HashMap<VirtualWindowId, Vec<VirtualWindowId>>,
 
+    // 子ウィンドウを並べる向き。なければ上下に並べる
+    direction_table: HashMap<VirtualWindowId, SplitDirection>,
+
     // 各ウィンドウの実際のサイズ
     actual_size_table: HashMap<VirtualWindowId, (u32, u32)>,
+
+    // 各ウィンドウの左上の位置。ルート直下のウィンドウの左上からの距離
+    actual_position_table: HashMap<VirtualWindowId, (u32, u32)>,
 }
 
 impl VirtualWindowManager {
@@ -49,11 +66,16 @@ impl VirtualWindowManager {
         let mut actual_size_table = HashMap::new();
         actual_size_table.insert(root_window_id, (0, 0));
 
+        let mut actual_position_table = HashMap::new();
+        actual_position_table.insert(root_window_id, (0, 0));
+
         Self {
             root_window_id,
             virtual_window_table: HashMap::new(),
             hierarchy_table,
+            direction_table: HashMap::new(),
             actual_size_table,
+            actual_position_table,
         }
     }
 
@@ -73,59 +95,70 @@ impl VirtualWindowManager {
 
             *width = window.width;
             *height = window.height;
+            self.actual_position_table.insert(*parent_window_id, (0, 0));
         }
 
         for parent_window_id in parent_ids {
             Self::update_recursive(
                 &mut self.actual_size_table,
+                &mut self.actual_position_table,
                 *parent_window_id,
-                &self.virtual_window_table,
+                &self.direction_table,
                 &self.hierarchy_table,
             );
         }
     }
 
+    // 子ウィンドウは親を並べる向きに等分して、割り切れない分は最後の子に足す
     fn update_recursive(
         actual_size_table: &mut HashMap<VirtualWindowId, (u32, u32)>,
+        actual_position_table: &mut HashMap<VirtualWindowId, (u32, u32)>,
         id: VirtualWindowId,
-        virtual_window_table: &HashMap<VirtualWindowId, VirtualWindow>,
+        direction_table: &HashMap<VirtualWindowId, SplitDirection>,
         hierarchy_table: &HashMap<VirtualWindowId, Vec<VirtualWindowId>>,
     ) {
         let Some(children) = hierarchy_table.get(&id) else {
             panic!();
         };
 
-        let Some((_parent_width, parent_height)) = actual_size_table.get(&id) else {
+        let Some((parent_width, parent_height)) = actual_size_table.get(&id).copied() else {
             return;
         };
+        let (parent_x, parent_y) = actual_position_table.get(&id).copied().unwrap_or_default();
 
-        let mut h = *parent_height;
-        for child_id in children {
-            let Some((width, height)) = actual_size_table.get_mut(child_id) else {
-                continue;
-            };
-
-            let Some(child_window) = virtual_window_table.get(child_id) else {
-                continue;
-            };
-
-            let is_last_item = children.last().unwrap() == child_id;
-            let new_actual_height = if is_last_item {
-                h
+        let direction = direction_table.get(&id).copied().unwrap_or_default();
+        let length = match direction {
+            SplitDirection::Horizontal => parent_width,
+            SplitDirection::Vertical => parent_height,
+        };
+        let count = children.len().max(1) as u32;
+        for (index, child_id) in children.iter().enumerate() {
+            let index = index as u32;
+            let offset = length / count * index;
+            let child_length = if index + 1 == count {
+                length - offset
             } else {
-                child_window.height.min(h)
+                length / count
             };
 
-            *width = child_window.width;
-            *height = new_actual_height;
-            h -= new_actual_height;
+            let (size, position) = match direction {
+                SplitDirection::Horizontal => {
+                    ((child_length, parent_height), (parent_x + offset, parent_y))
+                }
+                SplitDirection::Vertical => {
+                    ((parent_width, child_length), (parent_x, parent_y + offset))
+                }
+            };
+            actual_size_table.insert(*child_id, size);
+            actual_position_table.insert(*child_id, position);
         }
 
         for child_id in children {
             Self::update_recursive(
                 actual_size_table,
+                actual_position_table,
                 *child_id,
-                virtual_window_table,
+                direction_table,
                 hierarchy_table,
             );
         }
@@ -159,10 +192,110 @@ impl VirtualWindowManager {
 
         // サイズ計算用のデータを追加
         self.actual_size_table.insert(id, (0, 0));
+        self.actual_position_table.insert(id, (0, 0));
 
         Some(id)
     }
 
+    // ウィンドウを分けて、新しいウィンドウを後ろに並べる
+    // 親が同じ向きに並べていればその並びに加え、違う向きならふたつを包むウィンドウに置き換える
+    // ルート直下のウィンドウは分けられないので、その子を分ける
+    pub fn split(
+        &mut self,
+        id: VirtualWindowId,
+        direction: SplitDirection,
+    ) -> Option<VirtualWindowId> {
+        let parent_id = self.find_parent(id)?;
+        if parent_id == self.root_window_id {
+            return None;
+        }
+
+        let children = self.hierarchy_table.get(&parent_id)?;
+        let parent_direction = self.direction_table.get(&parent_id).copied();
+        if children.len() == 1 || parent_direction.unwrap_or_default() == direction {
+            let index = children.iter().position(|child_id| *child_id == id)?;
+            let new_id = self.spawn_virtual_window_with_parent(0, 0, parent_id)?;
+            let children = self.hierarchy_table.get_mut(&parent_id)?;
+            children.pop();
+            children.insert(index + 1, new_id);
+            self.direction_table.insert(parent_id, direction);
+            return Some(new_id);
+        }
+
+        // 包むウィンドウを元のウィンドウの位置に差し込む
+        let container_id = self.spawn_virtual_window_with_parent(0, 0, parent_id)?;
+        let children = self.hierarchy_table.get_mut(&parent_id)?;
+        children.pop();
+        let index = children.iter().position(|child_id| *child_id == id)?;
+        children[index] = container_id;
+        self.hierarchy_table.insert(container_id, vec![id]);
+        self.direction_table.insert(container_id, direction);
+        self.spawn_virtual_window_with_parent(0, 0, container_id)
+    }
+
+    // 子ウィンドウも含めて取り除く
+    // 親の子がひとつだけ残ったら、ルート直下のウィンドウでなければ残った子で親を置き換える
+    pub fn remove_virtual_window(&mut self, id: VirtualWindowId) {
+        let Some(parent_id) = self.find_parent(id) else {
+            return;
+        };
+        if let Some(children) = self.hierarchy_table.get_mut(&parent_id) {
+            children.retain(|child_id| *child_id != id);
+        }
+        self.remove_recursive(id);
+
+        let Some(grandparent_id) = self.find_parent(parent_id) else {
+            return;
+        };
+        if grandparent_id == self.root_window_id {
+            return;
+        }
+        let Some(&[child_id]) = self.hierarchy_table.get(&parent_id).map(Vec::as_slice) else {
+            return;
+        };
+        if let Some(children) = self.hierarchy_table.get_mut(&grandparent_id) {
+            for sibling_id in children.iter_mut() {
+                if *sibling_id == parent_id {
+                    *sibling_id = child_id;
+                }
+            }
+        }
+        self.hierarchy_table.insert(parent_id, Vec::default());
+        self.remove_recursive(parent_id);
+    }
+
+    fn remove_recursive(&mut self, id: VirtualWindowId) {
+        for child_id in self.hierarchy_table.remove(&id).unwrap_or_default() {
+            self.remove_recursive(child_id);
+        }
+        self.virtual_window_table.remove(&id);
+        self.direction_table.remove(&id);
+        self.actual_size_table.remove(&id);
+        self.actual_position_table.remove(&id);
+    }
+
+    fn find_parent(&self, id: VirtualWindowId) -> Option<VirtualWindowId> {
+        self.hierarchy_table
+            .iter()
+            .find(|(_, children)| children.contains(&id))
+            .map(|(parent_id, _)| *parent_id)
+    }
+
+    // 子を持たないウィンドウを左上から並んでいる順に列挙する
+    pub fn leaves(&self, id: VirtualWindowId) -> Vec<VirtualWindowId> {
+        let Some(children) = self.hierarchy_table.get(&id) else {
+            return Vec::default();
+        };
+        if children.is_empty() {
+            return vec![id];
+        }
+
+        children
+            .iter()
+            .flat_map(|child_id| self.leaves(*child_id))
+            .collect()
+    }
+
     pub fn resize(&mut self, width: u32, height: u32) {
         let Some(root_windows) = self.hierarchy_table.get(&self.root_window_id) else {
             return;
@@ -177,11 +310,20 @@ impl VirtualWindowManager {
         }
     }
 
+    // ルート直下のウィンドウのうちひとつだけ大きさを変える
+    pub fn resize_window(&mut self, id: VirtualWindowId, width: u32, height: u32) {
+        let Some(window) = self.virtual_window_table.get_mut(&id) else {
+            return;
+        };
+
+        window.width = width;
+        window.height = height;
+    }
+
     pub fn try_get_window(&self, id: VirtualWindowId) -> Option<&VirtualWindow> {
         self.virtual_window_table.get(&id)
     }
 
-    #[allow(dead_code)]
     pub fn try_get_actual_size(&self, id: VirtualWindowId) -> Option<(u32, u32)> {
         let Some((width, height)) = self.actual_size_table.get(&id) else {
             return None;
@@ -189,12 +331,16 @@ impl VirtualWindowManager {
 
         Some((*width, *height))
     }
+
+    pub fn try_get_actual_position(&self, id: VirtualWindowId) -> Option<(u32, u32)> {
+        self.actual_position_table.get(&id).copied()
+    }
 }
 
 #[cfg(test)]
 mod tests {
 
-    use super::VirtualWindowManager;
+    use super::{SplitDirection, VirtualWindowManager};
 
     // 一番親のウィンドウのサイズ
     #[test]
@@ -253,4 +399,76 @@ mod tests {
         assert_eq!(width, 640);
         assert_eq!(height, 240);
     }
+
+    // 左右に分けると幅を等分して並べる
+    #[test]
+    fn split_horizontal() {
+        let mut manager = VirtualWindowManager::new();
+        let id = manager.spawn_virtual_window(640, 480);
+        let child_id0 = manager
+            .spawn_virtual_window_with_parent(640, 480, id)
+            .unwrap();
+        let child_id1 = manager
+            .split(child_id0, SplitDirection::Horizontal)
+            .unwrap();
+        manager.uodate();
+
+        assert_eq!(manager.leaves(id), vec![child_id0, child_id1]);
+        assert_eq!(manager.try_get_actual_size(child_id0), Some((320, 480)));
+        assert_eq!(manager.try_get_actual_position(child_id0), Some((0, 0)));
+        assert_eq!(manager.try_get_actual_size(child_id1), Some((320, 480)));
+        assert_eq!(manager.try_get_actual_position(child_id1), Some((320, 0)));
+    }
+
+    // 違う向きに分けると、分けたウィンドウの領域の中で並べる
+    #[test]
+    fn split_nested() {
+        let mut manager = VirtualWindowManager::new();
+        let id = manager.spawn_virtual_window(640, 480);
+        let child_id0 = manager
+            .spawn_virtual_window_with_parent(640, 480, id)
+            .unwrap();
+        let child_id1 = manager
+            .split(child_id0, SplitDirection::Horizontal)
+            .unwrap();
+        let child_id2 = manager.split(child_id1, SplitDirection::Vertical).unwrap();
+        manager.uodate();
+
+        assert_eq!(manager.leaves(id), vec![child_id0, child_id1, child_id2]);
+        assert_eq!(manager.try_get_actual_size(child_id0), Some((320, 480)));
+        assert_eq!(manager.try_get_actual_size(child_id1), Some((320, 240)));
+        assert_eq!(manager.try_get_actual_position(child_id1), Some((320, 0)));
+        assert_eq!(manager.try_get_actual_size(child_id2), Some((320, 240)));
+        assert_eq!(manager.try_get_actual_position(child_id2), Some((320, 240)));
+
+        // ルート直下のウィンドウは分けられない
+        assert!(manager.split(id, SplitDirection::Vertical).is_none());
+    }
+
+    // 取り除いたウィンドウの領域は残ったウィンドウが使う
+    #[test]
+    fn remove() {
+        let mut manager = VirtualWindowManager::new();
+        let id = manager.spawn_virtual_window(640, 480);
+        let child_id0 = manager
+            .spawn_virtual_window_with_parent(640, 480, id)
+            .unwrap();
+        let child_id1 = manager
+            .split(child_id0, SplitDirection::Horizontal)
+            .unwrap();
+        let child_id2 = manager.split(child_id1, SplitDirection::Vertical).unwrap();
+
+        manager.remove_virtual_window(child_id1);
+        manager.uodate();
+        assert_eq!(manager.leaves(id), vec![child_id0, child_id2]);
+        assert_eq!(manager.try_get_actual_size(child_id2), Some((320, 480)));
+        assert_eq!(manager.try_get_actual_position(child_id2), Some((320, 0)));
+        assert!(manager.try_get_window(child_id1).is_none());
+
+        manager.remove_virtual_window(child_id0);
+        manager.uodate();
+        assert_eq!(manager.leaves(id), vec![child_id2]);
+        assert_eq!(manager.try_get_actual_size(child_id2), Some((640, 480)));
+        assert_eq!(manager.try_get_actual_position(child_id2), Some((0, 0)));
+    }
 }
@@ -1,3 +1,5 @@
+mod search_state;
 mod teletype_manager;
 
-pub use teletype_manager::{TeletypeId, TeletypeManager};
+pub use search_state::SearchState;
+pub use teletype_manager::{ClipboardRequest, TeletypeId, TeletypeManager, TerminalQuery};
@@ -0,0 +1,164 @@
+use alacritty_terminal::{
+    index::{Direction, Point},
+    term::search::{Match, RegexSearch},
+};
+
+// ペインごとの検索の状態
+// 文字を打つたびに検索を始めた位置から探しなおす
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchState {
+    query: String,
+
+    direction: Direction,
+
+    // 無効なら大文字と小文字を区別しない
+    is_case_sensitive: bool,
+
+    // 無効なら入力をそのままの文字列として探す
+    is_regex: bool,
+
+    origin: Point,
+
+    focused_match: Option<Match>,
+}
+
+impl SearchState {
+    pub fn new(direction: Direction, origin: Point) -> Self {
+        Self {
+            query: String::new(),
+            direction,
+            is_case_sensitive: false,
+            is_regex: false,
+            origin,
+            focused_match: None,
+        }
+    }
+
+    pub fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    pub fn set_direction(&mut self, direction: Direction) {
+        self.direction = direction;
+    }
+
+    pub fn origin(&self) -> Point {
+        self.origin
+    }
+
+    pub fn push_str(&mut self, text: &str) {
+        self.query.push_str(text);
+    }
+
+    pub fn pop(&mut self) {
+        self.query.pop();
+    }
+
+    pub fn toggle_case_sensitive(&mut self) {
+        self.is_case_sensitive = !self.is_case_sensitive;
+    }
+
+    pub fn toggle_regex(&mut self) {
+        self.is_regex = !self.is_regex;
+    }
+
+    pub fn focused_match(&self) -> Option<&Match> {
+        self.focused_match.as_ref()
+    }
+
+    pub fn set_focused_match(&mut self, focused_match: Option<Match>) {
+        self.focused_match = focused_match;
+    }
+
+    // 入力が空か、正規表現として解釈できなければ None
+    pub fn regex(&self) -> Option<RegexSearch> {
+        RegexSearch::new(&self.pattern()?).ok()
+    }
+
+    // 大文字と小文字の区別はフラグで明示して、入力に大文字があるかで切り替えないようにする
+    fn pattern(&self) -> Option<String> {
+        if self.query.is_empty() {
+            return None;
+        }
+
+        let flag = if self.is_case_sensitive {
+            "(?-i)"
+        } else {
+            "(?i)"
+        };
+        let pattern = if self.is_regex {
+            self.query.clone()
+        } else {
+            escape(&self.query)
+        };
+        Some(format!("{}{}", flag, pattern))
+    }
+
+    // 入力欄に表示する文字列
+    // 前方は /、後方は ? から始めて、有効なオプションを添える
+    pub fn bar(&self) -> String {
+        let prefix = match self.direction {
+            Direction::Right => '/',
+            Direction::Left => '?',
+        };
+        let mut options = Vec::new();
+        if self.is_case_sensitive {
+            options.push("case");
+        }
+        if self.is_regex {
+            options.push("regex");
+        }
+        if options.is_empty() {
+            format!("{}{}", prefix, self.query)
+        } else {
+            format!("[{}] {}{}", options.join(","), prefix, self.query)
+        }
+    }
+}
+
+// 正規表現の特殊文字をそのままの文字として扱う
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if "\\.+*?()|[]{}^$#&-~".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use alacritty_terminal::index::{Column, Direction, Line, Point};
+
+    use super::SearchState;
+
+    #[test]
+    fn pattern() {
+        let mut search_state = SearchState::new(Direction::Right, Point::new(Line(0), Column(0)));
+        assert!(search_state.pattern().is_none());
+
+        // 既定では特殊文字もそのまま探し、大文字と小文字を区別しない
+        search_state.push_str("a.b*");
+        assert_eq!(search_state.pattern().unwrap(), r"(?i)a\.b\*");
+
+        search_state.toggle_case_sensitive();
+        search_state.toggle_regex();
+        assert_eq!(search_state.pattern().unwrap(), "(?-i)a.b*");
+
+        search_state.pop();
+        assert_eq!(search_state.pattern().unwrap(), "(?-i)a.b");
+    }
+
+    #[test]
+    fn bar() {
+        let mut search_state = SearchState::new(Direction::Left, Point::new(Line(0), Column(0)));
+        search_state.push_str("foo");
+        assert_eq!(search_state.bar(), "?foo");
+
+        search_state.toggle_regex();
+        search_state.set_direction(Direction::Right);
+        assert_eq!(search_state.bar(), "[regex] /foo");
+    }
+}
@@ -1,6 +1,12 @@
 use alacritty_terminal::event_loop::{EventLoopSender, State};
-use alacritty_terminal::term::RenderableContent;
+use alacritty_terminal::grid::Scroll;
+use alacritty_terminal::index::{Boundary, Column, Direction, Line, Point, Side};
+use alacritty_terminal::selection::{Selection, SelectionType};
+use alacritty_terminal::term::color::Colors;
+use alacritty_terminal::term::search::{Match, RegexIter, RegexSearch};
+use alacritty_terminal::term::{self, Osc52, RenderableContent, TermMode};
 use alacritty_terminal::tty::{Options, Pty, Shell};
+use alacritty_terminal::vte::ansi::Rgb;
 use alacritty_terminal::Term;
 use alacritty_terminal::{
     event::{EventListener, WindowSize},
@@ -9,20 +15,62 @@ use alacritty_terminal::{
     sync::FairMutex,
 };
 use std::collections::HashMap;
+use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 use std::thread::JoinHandle;
 
+use crate::{clipboard::ClipboardType, config};
+
 #[derive(Clone, Copy, PartialEq, Eq, Hash)]
 pub struct TeletypeId {
     internal: u64,
 }
 
+// アプリが OSC 52 で要求したクリップボードの操作
+pub enum ClipboardRequest {
+    Store(ClipboardType, String),
+
+    // 読み出した文字列を端末に返す応答に整形する
+    Load(
+        ClipboardType,
+        Arc<dyn Fn(&str) -> String + Sync + Send + 'static>,
+    ),
+}
+
+type ClipboardRequestList = Arc<Mutex<Vec<(TeletypeId, ClipboardRequest)>>>;
+
+// アプリからの問い合わせ。答えは端末に返す応答に整形する
+pub enum TerminalQuery {
+    // OSC 4/10/11/12 で問い合わせたパレットの番号の色
+    Color(usize, Arc<dyn Fn(Rgb) -> String + Sync + Send + 'static>),
+
+    // CSI 14 t で問い合わせたテキスト領域のピクセル数
+    TextAreaSize(Arc<dyn Fn(WindowSize) -> String + Sync + Send + 'static>),
+}
+
+type TerminalQueryList = Arc<Mutex<Vec<(TeletypeId, TerminalQuery)>>>;
+
 pub struct TeletypeManager {
     terminal_table: HashMap<TeletypeId, Arc<FairMutex<Term<EventProxy>>>>,
     io_handle_table: HashMap<TeletypeId, JoinHandle<(EventLoop<Pty, EventProxy>, State)>>,
     dirty_table: Arc<Mutex<HashMap<TeletypeId, bool>>>,
     ptr_write_table: Arc<Mutex<HashMap<TeletypeId, Vec<u8>>>>,
+
+    // OSC 0/2 で変わったタイトル。None はリセット
+    title_table: Arc<Mutex<HashMap<TeletypeId, Option<String>>>>,
+
+    // 要求された順に処理する
+    clipboard_requests: ClipboardRequestList,
+
+    terminal_queries: TerminalQueryList,
+
+    // 作業ディレクトリを調べるための子プロセスの ID
+    pid_table: HashMap<TeletypeId, u32>,
+
     current_id: u64,
+
+    // すべての端末で共有する設定
+    term_config: term::Config,
 }
 
 impl TeletypeManager {
@@ -32,7 +80,18 @@ impl TeletypeManager {
             io_handle_table: HashMap::default(),
             dirty_table: Arc::new(Mutex::new(HashMap::default())),
             ptr_write_table: Arc::new(Mutex::new(HashMap::default())),
+            title_table: Arc::new(Mutex::new(HashMap::default())),
+            clipboard_requests: Arc::new(Mutex::new(Vec::default())),
+            terminal_queries: Arc::new(Mutex::new(Vec::default())),
+            pid_table: HashMap::default(),
             current_id: 0,
+            // kitty keyboard protocol はアプリが要求したときだけ有効になる
+            // OSC 52 は設定に合わせて Workspace で許可するので端末ではすべて通す
+            term_config: term::Config {
+                kitty_keyboard: true,
+                osc52: Osc52::CopyPaste,
+                ..Default::default()
+            },
         }
     }
 
@@ -51,12 +110,13 @@ impl TeletypeManager {
         }
     }
 
-    pub fn create_teletype(&mut self) -> (TeletypeId, EventLoopSender) {
-        self.create_teletype_with_size(SizeInfo::new())
+    pub fn create_teletype(&mut self, shell: &config::Shell) -> (TeletypeId, EventLoopSender) {
+        self.create_teletype_with_size(shell, SizeInfo::new())
     }
 
     pub fn create_teletype_with_size<TDimension>(
         &mut self,
+        shell: &config::Shell,
         size: TDimension,
     ) -> (TeletypeId, EventLoopSender)
     where
@@ -67,13 +127,19 @@ impl TeletypeManager {
         };
         self.current_id += 1;
 
+        #[cfg(not(target_os = "windows"))]
+        let default_program = "bash";
+        #[cfg(target_os = "windows")]
+        let default_program = "cmd.exe";
+        let program = shell.program.as_deref().unwrap_or(default_program);
+
+        // 環境変数は子プロセスにだけ渡す
+        // このプロセスの環境変数はほかのスレッドからも読まれるので書き換えない
         let pty_config = &Options {
-            #[cfg(not(target_os = "windows"))]
-            shell: Some(Shell::new("bash".to_string(), Vec::default())),
-            #[cfg(target_os = "windows")]
-            shell: Some(Shell::new("cmd.exe".to_string(), Vec::default())),
-            working_directory: None,
+            shell: Some(Shell::new(program.to_string(), shell.args.clone())),
+            working_directory: shell.working_directory.clone(),
             hold: true,
+            env: child_env(shell),
         };
         let window_size = WindowSize {
             num_lines: 64,
@@ -83,12 +149,20 @@ impl TeletypeManager {
         };
 
         let pty = alacritty_terminal::tty::new(pty_config, window_size, id.internal).unwrap();
+        #[cfg(not(target_os = "windows"))]
+        self.pid_table.insert(id, pty.child().id());
 
         self.dirty_table.lock().unwrap().insert(id, true);
-        let event_proxy =
-            EventProxy::new(id, self.dirty_table.clone(), self.ptr_write_table.clone());
+        let event_proxy = EventProxy::new(
+            id,
+            self.dirty_table.clone(),
+            self.ptr_write_table.clone(),
+            self.title_table.clone(),
+            self.clipboard_requests.clone(),
+            self.terminal_queries.clone(),
+        );
         let terminal =
-            alacritty_terminal::Term::new(Default::default(), &size, event_proxy.clone());
+            alacritty_terminal::Term::new(self.term_config.clone(), &size, event_proxy.clone());
         let terminal = Arc::new(FairMutex::new(terminal));
 
         let event_loop = EventLoop::new(
@@ -109,21 +183,72 @@ impl TeletypeManager {
         (id, channel)
     }
 
+    // 閉じたペインの端末を捨てる
+    // 入出力のスレッドは終了してから update で取り除く
+    pub fn remove_teletype(&mut self, id: TeletypeId) {
+        self.terminal_table.remove(&id);
+        self.dirty_table.lock().unwrap().remove(&id);
+        self.ptr_write_table.lock().unwrap().remove(&id);
+        self.pid_table.remove(&id);
+    }
+
     pub fn is_dirty(&self, id: TeletypeId) -> bool {
         *self.dirty_table.lock().unwrap().get(&id).unwrap()
     }
 
-    pub fn consume_ptr_write(&self) -> Vec<Vec<u8>> {
+    pub fn consume_ptr_write(&self) -> Vec<(TeletypeId, Vec<u8>)> {
         self.terminal_table
             .keys()
-            .filter_map(|id| self.ptr_write_table.lock().unwrap().remove(&id))
+            .filter_map(|id| {
+                let ptr_write = self.ptr_write_table.lock().unwrap().remove(id)?;
+                Some((*id, ptr_write))
+            })
             .collect()
     }
 
+    // 前回から変わったタイトル
+    // XTWINOPS で積んだタイトルは端末が持っていて、取り出したら変更として届く
+    pub fn consume_title(&self) -> Vec<(TeletypeId, Option<String>)> {
+        self.title_table.lock().unwrap().drain().collect()
+    }
+
+    pub fn consume_clipboard_requests(&self) -> Vec<(TeletypeId, ClipboardRequest)> {
+        std::mem::take(&mut *self.clipboard_requests.lock().unwrap())
+    }
+
+    pub fn consume_terminal_queries(&self) -> Vec<(TeletypeId, TerminalQuery)> {
+        std::mem::take(&mut *self.terminal_queries.lock().unwrap())
+    }
+
+    // アプリが OSC 4/10/11 で上書きした色
+    pub fn colors(&self, id: TeletypeId) -> Colors {
+        match self.terminal_table.get(&id) {
+            Some(terminal) => *terminal.lock().colors(),
+            None => Colors::default(),
+        }
+    }
+
+    // シェルの作業ディレクトリ
+    #[cfg(target_os = "linux")]
+    pub fn working_directory(&self, id: TeletypeId) -> Option<PathBuf> {
+        let pid = self.pid_table.get(&id)?;
+        std::fs::read_link(format!("/proc/{}/cwd", pid)).ok()
+    }
+
+    // /proc がないので調べられない
+    #[cfg(not(target_os = "linux"))]
+    pub fn working_directory(&self, _id: TeletypeId) -> Option<PathBuf> {
+        None
+    }
+
     pub fn is_empty(&self) -> bool {
         self.io_handle_table.is_empty()
     }
 
+    pub fn set_dirty(&mut self, id: TeletypeId) {
+        self.dirty_table.lock().unwrap().insert(id, true);
+    }
+
     pub fn clear_dirty(&mut self, id: TeletypeId) {
         *self.dirty_table.lock().unwrap().get_mut(&id).unwrap() = false;
     }
@@ -135,15 +260,187 @@ impl TeletypeManager {
         func(terminal.renderable_content());
     }
 
-    pub fn resize(&mut self, id: TeletypeId, width: u32, height: u32) {
+    // 入力の変換に使う端末のモード
+    pub fn mode(&self, id: TeletypeId) -> TermMode {
+        match self.terminal_table.get(&id) {
+            Some(terminal) => *terminal.lock().mode(),
+            None => TermMode::empty(),
+        }
+    }
+
+    // スクロールバックを表示する位置を動かす。Delta は正なら過去にさかのぼる
+    pub fn scroll_display(&mut self, id: TeletypeId, scroll: Scroll) {
+        let Some(terminal) = self.terminal_table.get(&id) else {
+            return;
+        };
+        let mut terminal = terminal.lock();
+        let display_offset = terminal.grid().display_offset();
+        terminal.scroll_display(scroll);
+        let is_scrolled = terminal.grid().display_offset() != display_offset;
+        drop(terminal);
+
+        // 動かなければ描画しなおさない
+        if is_scrolled {
+            self.set_dirty(id);
+        }
+    }
+
+    // 表示している位置とスクロールバックの行数
+    pub fn scroll_position(&self, id: TeletypeId) -> (usize, usize) {
+        let Some(terminal) = self.terminal_table.get(&id) else {
+            return (0, 0);
+        };
+        let terminal = terminal.lock();
+        let grid = terminal.grid();
+        (grid.display_offset(), grid.history_size())
+    }
+
+    // 検索を始める位置。前方なら表示中の左上、後方なら右下から探す
+    pub fn search_origin(&self, id: TeletypeId, direction: Direction) -> Point {
+        let Some(terminal) = self.terminal_table.get(&id) else {
+            return Point::new(Line(0), Column(0));
+        };
+        let terminal = terminal.lock();
+        let display_offset = terminal.grid().display_offset() as i32;
+        match direction {
+            Direction::Right => Point::new(Line(-display_offset), Column(0)),
+            Direction::Left => Point::new(
+                Line(terminal.screen_lines() as i32 - 1 - display_offset),
+                terminal.last_column(),
+            ),
+        }
+    }
+
+    // origin から探して、見つかった一致が見えるようにスクロールする
+    pub fn search_from(
+        &mut self,
+        id: TeletypeId,
+        regex: &mut RegexSearch,
+        origin: Point,
+        direction: Direction,
+    ) -> Option<Match> {
+        let terminal = self.terminal_table.get(&id)?;
+        let mut terminal = terminal.lock();
+        let side = match direction {
+            Direction::Right => Side::Left,
+            Direction::Left => Side::Right,
+        };
+        let search_match = terminal.search_next(regex, origin, direction, side, None)?;
+        terminal.scroll_to_point(*search_match.start());
+        drop(terminal);
+
+        self.set_dirty(id);
+        Some(search_match)
+    }
+
+    // 一致の次のセルから探す。端まで探したら反対の端に戻る
+    pub fn search_after(
+        &mut self,
+        id: TeletypeId,
+        regex: &mut RegexSearch,
+        search_match: &Match,
+        direction: Direction,
+    ) -> Option<Match> {
+        let origin = {
+            let terminal = self.terminal_table.get(&id)?.lock();
+            match direction {
+                Direction::Right => search_match.end().add(&*terminal, Boundary::None, 1),
+                Direction::Left => search_match.start().sub(&*terminal, Boundary::None, 1),
+            }
+        };
+        self.search_from(id, regex, origin, direction)
+    }
+
+    // 表示中の行にある一致
+    pub fn visible_matches(&self, id: TeletypeId, regex: &mut RegexSearch) -> Vec<Match> {
+        let Some(terminal) = self.terminal_table.get(&id) else {
+            return Vec::new();
+        };
+        let terminal = terminal.lock();
+        let display_offset = terminal.grid().display_offset() as i32;
+        let start = Point::new(Line(-display_offset), Column(0));
+        let end = Point::new(
+            Line(terminal.screen_lines() as i32 - 1 - display_offset),
+            terminal.last_column(),
+        );
+        RegexIter::new(start, end, Direction::Right, &*terminal, regex).collect()
+    }
+
+    // 表示中のセルから選択を始める
+    pub fn start_selection(
+        &mut self,
+        id: TeletypeId,
+        ty: SelectionType,
+        point: Point<usize>,
+        side: Side,
+    ) {
+        let Some(terminal) = self.terminal_table.get(&id) else {
+            return;
+        };
+        let mut terminal = terminal.lock();
+        let point = term::viewport_to_point(terminal.grid().display_offset(), point);
+        terminal.selection = Some(Selection::new(ty, point, side));
+        drop(terminal);
+        self.set_dirty(id);
+    }
+
+    pub fn update_selection(&mut self, id: TeletypeId, point: Point<usize>, side: Side) {
+        let Some(terminal) = self.terminal_table.get(&id) else {
+            return;
+        };
+        let mut terminal = terminal.lock();
+        let point = term::viewport_to_point(terminal.grid().display_offset(), point);
+        let Some(selection) = &mut terminal.selection else {
+            return;
+        };
+        selection.update(point, side);
+        drop(terminal);
+        self.set_dirty(id);
+    }
+
+    pub fn selection_to_string(&self, id: TeletypeId) -> Option<String> {
+        self.terminal_table.get(&id)?.lock().selection_to_string()
+    }
+
+    // ダブルクリックで単語を選択するときの区切り文字
+    pub fn semantic_escape_chars(&self) -> &str {
+        &self.term_config.semantic_escape_chars
+    }
+
+    // すべての端末に反映するので、変わったときだけ呼ぶ
+    pub fn set_semantic_escape_chars(&mut self, semantic_escape_chars: &str) {
+        self.term_config.semantic_escape_chars = semantic_escape_chars.to_string();
+        self.apply_term_config();
+    }
+
+    // スクロールバックに残す行数。減らしたらあふれた行は捨てる
+    pub fn scrolling_history(&self) -> usize {
+        self.term_config.scrolling_history
+    }
+
+    // すべての端末に反映するので、変わったときだけ呼ぶ
+    pub fn set_scrolling_history(&mut self, history: usize) {
+        self.term_config.scrolling_history = history;
+        self.apply_term_config();
+    }
+
+    fn apply_term_config(&mut self) {
+        for (id, terminal) in &self.terminal_table {
+            terminal.lock().set_options(self.term_config.clone());
+            self.dirty_table.lock().unwrap().insert(*id, true);
+        }
+    }
+
+    pub fn resize(&mut self, id: TeletypeId, window_size: WindowSize) {
         let Some(term) = self.terminal_table.get(&id) else {
             return;
         };
 
-        let line = height as usize / 16;
-        let columns = width as usize / 16;
-        term.lock()
-            .resize(SizeInfo::new_with(128 /*total*/, line, columns));
+        // スクロールバックの行数は設定で決まるので、ここでは画面の行数に足しておくだけ
+        let line = window_size.num_lines as usize;
+        let columns = window_size.num_cols as usize;
+        let total = line + self.term_config.scrolling_history;
+        term.lock().resize(SizeInfo::new_with(total, line, columns));
     }
 }
 
@@ -151,6 +448,9 @@ struct EventProxy {
     id: TeletypeId,
     dirty_table: Arc<Mutex<HashMap<TeletypeId, bool>>>,
     ptr_write_table: Arc<Mutex<HashMap<TeletypeId, Vec<u8>>>>,
+    title_table: Arc<Mutex<HashMap<TeletypeId, Option<String>>>>,
+    clipboard_requests: ClipboardRequestList,
+    terminal_queries: TerminalQueryList,
 }
 
 impl EventProxy {
@@ -158,11 +458,35 @@ impl EventProxy {
         id: TeletypeId,
         dirty_table: Arc<Mutex<HashMap<TeletypeId, bool>>>,
         ptr_write_table: Arc<Mutex<HashMap<TeletypeId, Vec<u8>>>>,
+        title_table: Arc<Mutex<HashMap<TeletypeId, Option<String>>>>,
+        clipboard_requests: ClipboardRequestList,
+        terminal_queries: TerminalQueryList,
     ) -> Self {
         Self {
             dirty_table,
             id,
             ptr_write_table,
+            title_table,
+            clipboard_requests,
+            terminal_queries,
+        }
+    }
+
+    fn push_terminal_query(&self, query: TerminalQuery) {
+        self.terminal_queries.lock().unwrap().push((self.id, query));
+    }
+
+    fn push_clipboard_request(&self, request: ClipboardRequest) {
+        self.clipboard_requests
+            .lock()
+            .unwrap()
+            .push((self.id, request));
+    }
+
+    fn clipboard_type(ty: term::ClipboardType) -> ClipboardType {
+        match ty {
+            term::ClipboardType::Clipboard => ClipboardType::Clipboard,
+            term::ClipboardType::Selection => ClipboardType::Selection,
         }
     }
 }
@@ -179,6 +503,33 @@ impl EventListener for EventProxy {
                     .unwrap()
                     .insert(self.id, str.into_bytes());
             }
+            alacritty_terminal::event::Event::Title(title) => {
+                self.title_table
+                    .lock()
+                    .unwrap()
+                    .insert(self.id, Some(title));
+            }
+            alacritty_terminal::event::Event::ResetTitle => {
+                self.title_table.lock().unwrap().insert(self.id, None);
+            }
+            alacritty_terminal::event::Event::ClipboardStore(ty, text) => {
+                self.push_clipboard_request(ClipboardRequest::Store(
+                    Self::clipboard_type(ty),
+                    text,
+                ));
+            }
+            alacritty_terminal::event::Event::ClipboardLoad(ty, format) => {
+                self.push_clipboard_request(ClipboardRequest::Load(
+                    Self::clipboard_type(ty),
+                    format,
+                ));
+            }
+            alacritty_terminal::event::Event::ColorRequest(index, format) => {
+                self.push_terminal_query(TerminalQuery::Color(index, format));
+            }
+            alacritty_terminal::event::Event::TextAreaSizeRequest(format) => {
+                self.push_terminal_query(TerminalQuery::TextAreaSize(format));
+            }
             alacritty_terminal::event::Event::Bell => {
                 // とりあえず未サポート
             }
@@ -191,12 +542,6 @@ impl EventListener for EventProxy {
             _ => {
                 println!("{:?}", event)
             } // alacritty_terminal::event::Event::MouseCursorDirty => todo!(),
-              // alacritty_terminal::event::Event::Title(_) => todo!(),
-              // alacritty_terminal::event::Event::ResetTitle => todo!(),
-              // alacritty_terminal::event::Event::ClipboardStore(_, _) => todo!(),
-              // alacritty_terminal::event::Event::ClipboardLoad(_, _) => todo!(),
-              // alacritty_terminal::event::Event::ColorRequest(_, _) => todo!(),
-              // alacritty_terminal::event::Event::TextAreaSizeRequest(_) => todo!(),
         }
     }
 }
@@ -207,6 +552,9 @@ impl Clone for EventProxy {
             id: self.id,
             dirty_table: Arc::clone(&self.dirty_table),
             ptr_write_table: Arc::clone(&self.ptr_write_table),
+            title_table: Arc::clone(&self.title_table),
+            clipboard_requests: Arc::clone(&self.clipboard_requests),
+            terminal_queries: Arc::clone(&self.terminal_queries),
         }
     }
 }
@@ -248,3 +596,57 @@ impl Dimensions for SizeInfo {
         self.columns
     }
 }
+
+// 子プロセスに渡す環境変数
+// 名前が空だったり = を含んだりする変数は設定できないので飛ばす
+fn child_env(shell: &config::Shell) -> HashMap<String, String> {
+    let mut env = HashMap::from([("TERM".to_string(), shell.term.clone())]);
+    for (key, value) in &shell.env {
+        if key.is_empty() || key.contains(|c| c == '=' || c == '\0') {
+            eprintln!("invalid environment variable name: {:?}", key);
+            continue;
+        }
+        env.insert(key.clone(), value.clone());
+    }
+    env
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, HashMap};
+
+    use crate::config;
+
+    use super::child_env;
+
+    fn shell(env: &[(&str, &str)]) -> config::Shell {
+        config::Shell {
+            program: None,
+            args: vec!["-l".to_string()],
+            env: env
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect::<BTreeMap<String, String>>(),
+            working_directory: None,
+            term: "xterm-256color".to_string(),
+        }
+    }
+
+    // TERM と設定した変数を渡し、設定できない名前の変数は飛ばす
+    #[test]
+    fn env() {
+        let env = child_env(&shell(&[("A", "1=2"), ("", "x"), ("B=C", "y")]));
+        let expected = HashMap::from([
+            ("TERM".to_string(), "xterm-256color".to_string()),
+            ("A".to_string(), "1=2".to_string()),
+        ]);
+        assert_eq!(env, expected);
+    }
+
+    // shell.env で TERM を指定すれば shell.term より優先する
+    #[test]
+    fn env_overrides_term() {
+        let env = child_env(&shell(&[("TERM", "dumb")]));
+        assert_eq!(env.get("TERM").map(String::as_str), Some("dumb"));
+    }
+}
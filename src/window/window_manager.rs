@@ -11,6 +11,16 @@ pub struct WindowManager {
     ids: Vec<WindowId>,
 
     window_table: HashMap<WindowId, Arc<Window>>,
+
+    // 新しく開くウィンドウのタイトル
+    title: String,
+
+    // ウィンドウに表示しているタイトル
+    // タイトルは必ず set_window_title で変えて、同じタイトルなら設定しなおさない
+    window_title_table: HashMap<WindowId, String>,
+
+    // X11 と Wayland のウィンドウのクラス名
+    class: Option<String>,
 }
 
 impl WindowManager {
@@ -19,23 +29,72 @@ impl WindowManager {
         Self {
             ids: Vec::default(),
             window_table: Default::default(),
+            title: "Shalacritty".to_string(),
+            window_title_table: HashMap::default(),
+            class: None,
         }
     }
 
+    pub fn set_title(&mut self, title: String) {
+        self.title = title;
+    }
+
+    pub fn set_class(&mut self, class: Option<String>) {
+        self.class = class;
+    }
+
     pub async fn create_window<T>(&mut self, event_loop: &EventLoopWindowTarget<T>) -> WindowId {
         // カラーターゲットの最大値を 2048x2048 に設定しているのでウィンドウサイズもそれを超えないようにしている
-        let window = WindowBuilder::new()
+        let builder = WindowBuilder::new()
+            .with_title(&self.title)
             .with_transparent(true)
             .with_min_inner_size(PhysicalSize::new(300, 300))
-            .with_max_inner_size(PhysicalSize::new(4096, 4096))
-            .build(event_loop)
-            .unwrap();
+            .with_max_inner_size(PhysicalSize::new(4096, 4096));
+
+        // ウィンドウマネージャーがウィンドウを見分けるのに使う
+        // X11 と Wayland は同じ設定を共有しているので一度指定すればどちらでも使われる
+        #[cfg(all(
+            unix,
+            not(any(target_os = "macos", target_os = "android", target_os = "ios"))
+        ))]
+        let builder = match &self.class {
+            Some(class) => {
+                use winit::platform::x11::WindowBuilderExtX11;
+                builder.with_name(class, class)
+            }
+            None => builder,
+        };
+
+        let window = builder.build(event_loop).unwrap();
+
+        // 日本語などを入力できるように IME を有効にする
+        window.set_ime_allowed(true);
+
         let id = window.id();
         self.ids.push(id);
         self.window_table.insert(id, Arc::new(window));
+        self.window_title_table.insert(id, self.title.clone());
         id
     }
 
+    // 表示しているタイトルと違うときだけ設定する
+    pub fn set_window_title(&mut self, id: WindowId, title: String) {
+        if self.window_title_table.get(&id) == Some(&title) {
+            return;
+        }
+        let Some(window) = self.window_table.get(&id) else {
+            return;
+        };
+        window.set_title(&title);
+        self.window_title_table.insert(id, title);
+    }
+
+    pub fn remove_window(&mut self, id: WindowId) {
+        self.ids.retain(|window_id| *window_id != id);
+        self.window_table.remove(&id);
+        self.window_title_table.remove(&id);
+    }
+
     pub fn try_get_window(&self, id: WindowId) -> Option<Arc<Window>> {
         let Some(window) = self.window_table.get(&id) else {
             return None;
@@ -6,6 +6,7 @@ use alacritty_terminal::{
 };
 
 use crate::{
+    config::Shell,
     multiplexers::IShellManager,
     tty::{TeletypeId, TeletypeManager},
 };
@@ -13,14 +14,18 @@ use crate::{
 pub struct MultiplexersAdapter {
     teletype_manager: TeletypeManager,
     event_loop_sender_table: HashMap<TeletypeId, EventLoopSender>,
+
+    // タイルで起動するプログラム
+    shell: Shell,
 }
 
 impl MultiplexersAdapter {
     #[allow(dead_code)]
-    pub fn new() -> Self {
+    pub fn new(shell: Shell) -> Self {
         Self {
             teletype_manager: TeletypeManager::new(),
             event_loop_sender_table: HashMap::default(),
+            shell,
         }
     }
 }
@@ -29,7 +34,7 @@ impl IShellManager for MultiplexersAdapter {
     type Id = TeletypeId;
 
     fn spawn(&mut self) -> Self::Id {
-        let (id, event_loop_sender) = self.teletype_manager.create_teletype();
+        let (id, event_loop_sender) = self.teletype_manager.create_teletype(&self.shell);
         self.event_loop_sender_table.insert(id, event_loop_sender);
         id
     }
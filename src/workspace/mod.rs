@@ -1,25 +1,47 @@
 mod detail;
 mod diff_calculator;
 
-use std::{borrow::Cow, collections::HashMap, sync::Arc};
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::Instant,
+};
 
 use alacritty_terminal::{
-    event::WindowSize,
     event_loop::{EventLoopSender, Msg},
+    grid::Scroll,
+    index::{Column, Direction, Point, Side},
+    selection::SelectionType,
+    term::TermMode,
+};
+use winit::{
+    dpi::{PhysicalPosition, PhysicalSize},
+    event::{ElementState, Ime, KeyEvent, MouseButton, MouseScrollDelta},
+    event_loop::EventLoopWindowTarget,
+    keyboard::{Key, ModifiersState, NamedKey, PhysicalKey},
+    platform::modifier_supplement::KeyEventExtModifierSupplement,
+    window::{Fullscreen, Window, WindowId},
 };
-use winit::{event_loop::EventLoopWindowTarget, window::WindowId};
 
 use crate::{
-    gfx::{ContentPlotter, GlyphManager, Renderer, RendererUpdateParams},
+    cli::CommandLineOptions,
+    clipboard::{ClipboardService, ClipboardType},
+    config::{Action, Shell},
+    gfx::{
+        CellMetrics, ColorPalette, ContentPlotter, GlyphManager, PaneViewport, Preedit, Renderer,
+        RendererUpdateParams, ScrollIndicator, SearchOverlay,
+    },
+    input::{KeyBindingTable, KeyEncoder, MouseEncoder, MouseState, PasteEncoder},
 
     // 本体は detail 以下にはアクセスさせたくない
     // multiplexers モジュールへの移植途中の互換性保持として直接参照している
     multiplexers::{
-        detail::{VirtualWindowId, VirtualWindowManager},
+        detail::{SplitDirection, VirtualWindowId, VirtualWindowManager},
         TileManager,
     },
 
-    tty::{TeletypeId, TeletypeManager},
+    tty::{ClipboardRequest, SearchState, TeletypeId, TeletypeManager, TerminalQuery},
     window::WindowManager,
     Config,
     ConfigService,
@@ -32,21 +54,62 @@ pub struct Workspace<'a> {
     #[allow(dead_code)]
     config_service: Arc<ConfigService>,
     glyph_manager: GlyphManager,
+    color_palette: ColorPalette,
     teletype_manager: TeletypeManager,
     window_manager: WindowManager,
-    content_plotter: ContentPlotter,
+
+    // グリフのアトラスはウィンドウのデバイスに置くのでウィンドウごとに持つ
+    content_plotter_table: HashMap<WindowId, ContentPlotter>,
+
     renderer: Renderer<'a>,
     window_tty_table: HashMap<WindowId, Vec<TeletypeId>>,
-    sender: Option<EventLoopSender>,
+    sender_table: HashMap<TeletypeId, EventLoopSender>,
+    key_binding_table: KeyBindingTable,
+    mouse_state_table: HashMap<WindowId, MouseState>,
 
-    #[allow(dead_code)]
+    // 割り当てた操作に使ったキー。離すまでのリピートと離したことも端末に送らない
+    bound_key_table: HashMap<WindowId, HashSet<PhysicalKey>>,
+    clipboard_service: ClipboardService,
+
+    // IME で変換中の文字列
+    preedit_table: HashMap<WindowId, Preedit>,
+
+    // 確認を待っている貼り付けの文字列
+    pending_paste_table: HashMap<WindowId, String>,
+
+    // 検索中のペイン
+    search_table: HashMap<TeletypeId, SearchState>,
+
+    // アプリが OSC 0/2 で設定したタイトル
+    title_table: HashMap<TeletypeId, String>,
+
+    // 設定のフォントサイズに加えるポイント数
+    font_size_delta: f32,
+
+    // イベントループを参照できる App にウィンドウの生成を頼む
+    is_spawn_window_requested: bool,
+
+    // 次に開くウィンドウで使うプロファイル
+    spawn_profile: Option<String>,
+
+    // ウィンドウを開いたプロファイル。分割したペインも同じプロファイルで起動する
+    window_profile_table: HashMap<WindowId, String>,
+
+    // 最初のウィンドウで起動するプログラムはコマンドラインで上書きする
+    command_line_options: CommandLineOptions,
+
+    // ウィンドウを分割した仮想的な領域
+    // ウィンドウ全体の領域の子孫のうち、子を持たない領域をペインにする
     virtual_window_manager: VirtualWindowManager,
 
+    // Window -> ウィンドウ全体の VirtualWindow
+    window_virtual_window_table: HashMap<WindowId, VirtualWindowId>,
+
     // VirtualWindow -> Tty
     virtual_window_tty_table: HashMap<VirtualWindowId, TeletypeId>,
 
-    // 操作対象となっているウィンドウ
-    active_window_id: Option<VirtualWindowId>,
+    // ウィンドウごとに操作対象となっているペイン
+    active_window_table: HashMap<WindowId, VirtualWindowId>,
 
     tile_manager: TileManager<MultiplexersAdapter>,
 
@@ -54,58 +117,112 @@ pub struct Workspace<'a> {
 }
 
 impl<'a> Workspace<'a> {
-    pub fn new() -> Self {
+    pub fn new(command_line_options: CommandLineOptions) -> Self {
         let instance = wgpu::Instance::default();
-        let config_service = Arc::new(ConfigService::new());
-        let glyph_manager = GlyphManager::new();
-        let teletype_manager = TeletypeManager::new();
-        let window_manager = WindowManager::new();
-        let content_plotter = ContentPlotter::new();
+        let config_service = Arc::new(ConfigService::new_with(
+            command_line_options.config_file.as_deref(),
+            command_line_options.config_override_tables(),
+        ));
+        let (font, color_palette, key_binding_table, history, shell, title) = {
+            let config = config_service.read().unwrap();
+            (
+                config.font.clone(),
+                ColorPalette::new(&config.colors),
+                KeyBindingTable::new(&config.keybindings),
+                config.scrolling.history,
+                config.shell.clone(),
+                config.window.title.clone(),
+            )
+        };
+        let glyph_manager = GlyphManager::new_with_font(&font);
+        let mut teletype_manager = TeletypeManager::new();
+        teletype_manager.set_scrolling_history(history);
+        let mut window_manager = WindowManager::new();
+        window_manager.set_title(command_line_options.title.clone().unwrap_or(title));
+        window_manager.set_class(command_line_options.class.clone());
         let renderer = Renderer::new();
 
         // ウィンドウを分割した仮想的な領域
-        let mut virtual_window_manager = VirtualWindowManager::new();
-        let id = virtual_window_manager.spawn_virtual_window(64, 64);
-        let _virtual_window = virtual_window_manager.try_get_window(id);
+        let virtual_window_manager = VirtualWindowManager::new();
 
-        let (tile_manager, _id) = TileManager::new(MultiplexersAdapter::new());
+        let (tile_manager, _id) = TileManager::new(MultiplexersAdapter::new(shell));
 
         Self {
             instance,
             config_service,
             glyph_manager,
+            color_palette,
             teletype_manager,
             window_manager,
-            content_plotter,
+            content_plotter_table: HashMap::default(),
             renderer,
             window_tty_table: HashMap::default(),
-            sender: None,
+            sender_table: HashMap::default(),
+            key_binding_table,
+            mouse_state_table: HashMap::default(),
+            bound_key_table: HashMap::default(),
+            clipboard_service: ClipboardService::new(),
+            preedit_table: HashMap::default(),
+            pending_paste_table: HashMap::default(),
+            search_table: HashMap::default(),
+            title_table: HashMap::default(),
+            font_size_delta: 0.0,
+            is_spawn_window_requested: false,
+            spawn_profile: None,
+            window_profile_table: HashMap::default(),
+            command_line_options,
             virtual_window_manager,
+            window_virtual_window_table: HashMap::default(),
             virtual_window_tty_table: HashMap::default(),
-            active_window_id: None,
+            active_window_table: HashMap::default(),
             tile_manager,
             old_config: None,
         }
     }
 
     pub async fn spawn_window<T>(&mut self, event_loop: &EventLoopWindowTarget<T>) {
+        let is_first_window = self.window_manager.ids().is_empty();
         let id = self.window_manager.create_window(event_loop).await;
         let window = self.window_manager.try_get_window(id).unwrap();
         let window_size = window.inner_size();
         self.renderer.register(id, &self.instance, window).await;
+        self.content_plotter_table.insert(id, ContentPlotter::new());
         self.renderer
             .resize(id, window_size.width, window_size.height);
 
-        let (tty_id, sender) = self.teletype_manager.create_teletype();
+        // プロファイルを指定されていなければ shell の設定で起動する
+        // グリフのアトラスや描画の状態はウィンドウごとに持つので、ほかのウィンドウと混ざらない
+        if let Some(profile) = self.spawn_profile.take() {
+            self.window_profile_table.insert(id, profile);
+        }
+        let mut shell = self.shell_for(id);
+        if is_first_window {
+            self.command_line_options.apply_to_shell(&mut shell);
+        }
+        let (tty_id, sender) = self.teletype_manager.create_teletype(&shell);
         self.window_tty_table.insert(id, vec![tty_id]);
-        self.sender = Some(sender);
+        self.sender_table.insert(tty_id, sender);
 
         // シェルを表示する領域
-        let virtual_window_id = self.virtual_window_manager.spawn_virtual_window(64, 64);
-        self.virtual_window_tty_table
-            .insert(virtual_window_id, tty_id);
+        // ウィンドウ全体の領域にペインをひとつ置いて、分割するときはペインを分ける
+        let virtual_window_id = self
+            .virtual_window_manager
+            .spawn_virtual_window(window_size.width, window_size.height);
+        self.window_virtual_window_table
+            .insert(id, virtual_window_id);
+        let Some(pane_id) = self
+            .virtual_window_manager
+            .spawn_virtual_window_with_parent(
+                window_size.width,
+                window_size.height,
+                virtual_window_id,
+            )
+        else {
+            return;
+        };
+        self.virtual_window_tty_table.insert(pane_id, tty_id);
 
-        self.active_window_id = Some(virtual_window_id);
+        self.active_window_table.insert(id, pane_id);
 
         // 初期サイズ反映
         self.resize(id, window_size.width, window_size.height);
@@ -115,199 +232,1121 @@ impl<'a> Workspace<'a> {
         self.teletype_manager.update();
         self.tile_manager.update();
 
-        for ptr_write in self.teletype_manager.consume_ptr_write() {
-            self.sender
-                .as_mut()
-                .unwrap()
-                .send(Msg::Input(Cow::Owned(ptr_write)))
-                .unwrap();
+        for (tty_id, ptr_write) in self.teletype_manager.consume_ptr_write() {
+            self.send_to_tty(tty_id, Msg::Input(Cow::Owned(ptr_write)));
         }
 
-        self.virtual_window_manager.uodate();
+        // アプリが OSC 52 で使うクリップボードは設定で許可した操作だけ通す
+        let osc52 = self.config_service.read().unwrap().osc52;
+        for (tty_id, request) in self.teletype_manager.consume_clipboard_requests() {
+            match request {
+                ClipboardRequest::Store(ty, text) => {
+                    self.clipboard_service.store_osc52(ty, text, osc52)
+                }
+                ClipboardRequest::Load(ty, format) => {
+                    if let Some(text) = self.clipboard_service.load_osc52(ty, osc52) {
+                        let reply = format(&text).into_bytes();
+                        self.send_to_tty(tty_id, Msg::Input(Cow::Owned(reply)));
+                    }
+                }
+            }
+        }
 
-        // 表示する要素が更新されていたら描画する要素に反映する
-        for (window_id, value) in &self.window_tty_table {
-            // 最描画要求
-            let Some(window) = self.window_manager.try_get_window(*window_id) else {
-                return;
+        // 色や大きさの問い合わせには今のパレットとペインの大きさで答える
+        for (tty_id, query) in self.teletype_manager.consume_terminal_queries() {
+            if let Some(reply) = self.answer_terminal_query(tty_id, query) {
+                self.send_to_tty(tty_id, Msg::Input(Cow::Owned(reply.into_bytes())));
+            }
+        }
+
+        // アプリが変えたタイトルを反映
+        for (tty_id, title) in self.teletype_manager.consume_title() {
+            match title {
+                Some(title) => self.title_table.insert(tty_id, title),
+                None => self.title_table.remove(&tty_id),
             };
-            window.request_redraw();
+        }
+        self.update_window_titles();
 
-            let (background, image_alpha, image_path) = if self.old_config.is_none() {
-                let config = self.config_service.read().unwrap();
-                self.old_config = Some(config.clone());
+        self.virtual_window_manager.uodate();
 
-                (
-                    Some(config.background.clear_color),
-                    Some(config.image_alpha),
-                    Some(config.image.clone()),
-                )
-            } else {
-                let old_config = self.old_config.as_ref().unwrap();
-                let config = self.config_service.read().unwrap();
-                let background =
-                    if old_config.background.clear_color == config.background.clear_color {
-                        None
-                    } else {
-                        Some(config.background.clear_color)
-                    };
-                let alpha = if old_config.image_alpha == config.image_alpha {
-                    None
-                } else {
-                    Some(config.image_alpha)
-                };
-                let image = if old_config.image == config.image {
-                    None
-                } else {
-                    Some(config.image.clone())
-                };
-                self.old_config = Some(config.clone());
+        // フォント設定の変更を反映
+        self.update_font();
+
+        // 配色の変更を反映
+        self.update_color_palette();
+
+        // 単語の区切り文字の変更を反映
+        let config = self.config_service.read().unwrap();
+        let semantic_escape_chars = &config.selection.semantic_escape_chars;
+        if self.teletype_manager.semantic_escape_chars() != semantic_escape_chars {
+            self.teletype_manager
+                .set_semantic_escape_chars(semantic_escape_chars);
+        }
+
+        // スクロールバックの行数の変更を反映
+        if self.teletype_manager.scrolling_history() != config.scrolling.history {
+            self.teletype_manager
+                .set_scrolling_history(config.scrolling.history);
+        }
 
-                (background, alpha, image)
+        // キーの割り当ての変更を反映
+        if self.key_binding_table.source() != config.keybindings.as_slice() {
+            self.key_binding_table = KeyBindingTable::new(&config.keybindings);
+        }
+        drop(config);
+
+        // 背景の設定が変わっていたらすべてのペインを描きなおして反映する
+        let (background, image_alpha, image_path) = self.consume_background_config();
+        if background.is_some() || image_alpha.is_some() || image_path.is_some() {
+            for tty_id in self.virtual_window_tty_table.values() {
+                self.teletype_manager.set_dirty(*tty_id);
+            }
+        }
+
+        // 表示する要素が更新されていたら描画する要素に反映する
+        for window_id in self.window_manager.ids().to_vec() {
+            // 最描画要求
+            let Some(window) = self.window_manager.try_get_window(window_id) else {
+                continue;
             };
+            window.request_redraw();
 
-            for id in value {
+            // 背景はウィンドウに 1 つなので最初に描くペインと一緒に渡す
+            let size = window.inner_size();
+            let mut background_params = Some((background, image_alpha, image_path.clone()));
+            for (_, tty_id, viewport) in self.pane_viewports(window_id) {
                 // 変化がなければなにもしない
-                if !self.teletype_manager.is_dirty(*id) {
+                if !self.teletype_manager.is_dirty(tty_id) {
                     continue;
                 }
 
                 // レンダラーに反映
-                self.teletype_manager.get_content(*id, |c| {
-                    let diff = self.content_plotter.calculate_diff(
-                        c,
-                        &mut self.glyph_manager,
-                        (window.inner_size().width, window.inner_size().height),
-                    );
-                    let update_params = RendererUpdateParams::new(
-                        window.inner_size().width,
-                        window.inner_size().height,
-                    )
-                    .with_diff(diff)
-                    .with_background_color(background)
-                    .with_image_alpha(image_alpha)
-                    .with_image_path(image_path.clone());
-                    self.renderer.update(*window_id, update_params);
-                });
+                let mut update_params = RendererUpdateParams::new(size.width, size.height);
+                if let Some((background, image_alpha, image_path)) = background_params.take() {
+                    update_params = update_params
+                        .with_background_color(background)
+                        .with_image_alpha(image_alpha)
+                        .with_image_path(image_path);
+                }
+                self.update_pane(window_id, tty_id, &viewport, update_params);
 
                 // ダーティフラグを解除
-                self.teletype_manager.clear_dirty(*id);
+                self.teletype_manager.clear_dirty(tty_id);
+            }
+        }
+    }
+
+    // 前回から変わった背景の設定
+    fn consume_background_config(&mut self) -> (Option<[f32; 4]>, Option<f32>, Option<String>) {
+        let config = self.config_service.read().unwrap();
+        let Some(old_config) = self.old_config.replace(config.clone()) else {
+            return (
+                Some(config.background.clear_color),
+                Some(config.image_alpha),
+                Some(config.image.clone()),
+            );
+        };
+
+        let background = if old_config.background.clear_color == config.background.clear_color {
+            None
+        } else {
+            Some(config.background.clear_color)
+        };
+        let alpha = if old_config.image_alpha == config.image_alpha {
+            None
+        } else {
+            Some(config.image_alpha)
+        };
+        let image = if old_config.image == config.image {
+            None
+        } else {
+            Some(config.image.clone())
+        };
+
+        (background, alpha, image)
+    }
+
+    // ペインの内容をウィンドウの中のペインの場所に描く
+    // 変換中の文字列とスクロールバックの位置はフォーカスしているペインにだけ重ねる
+    fn update_pane(
+        &mut self,
+        window_id: WindowId,
+        tty_id: TeletypeId,
+        viewport: &PaneViewport,
+        update_params: RendererUpdateParams<String>,
+    ) {
+        let Some(window) = self.window_manager.try_get_window(window_id) else {
+            return;
+        };
+
+        let preedit = self
+            .preedit_table
+            .get(&window_id)
+            .filter(|_| viewport.is_focused);
+        let search = self.search_overlay(window_id, tty_id);
+        let mut diff = None;
+        let mut cursor = None;
+        self.teletype_manager.get_content(tty_id, |c| {
+            cursor = Some((c.cursor.point, c.display_offset));
+            let Some(content_plotter) = self.content_plotter_table.get_mut(&window_id) else {
+                return;
+            };
+            diff = Some(content_plotter.calculate_diff(
+                c,
+                &mut self.glyph_manager,
+                &self.color_palette,
+                preedit,
+                search.as_ref(),
+                viewport,
+            ));
+        });
+        let Some(diff) = diff else {
+            return;
+        };
+
+        let mut update_params = update_params.with_diff(diff);
+        if viewport.is_focused {
+            let scroll_indicator = self.scroll_indicator(tty_id, viewport.size);
+            update_params = update_params.with_scroll_indicator(scroll_indicator);
+        }
+        self.renderer.update(window_id, update_params);
+
+        // IME の候補ウィンドウを端末のカーソルに合わせる
+        if let Some((point, display_offset)) = cursor.filter(|_| viewport.is_focused) {
+            let cell_metrics = self.glyph_manager.cell_metrics();
+            Self::set_ime_cursor_area(
+                &window,
+                cell_metrics,
+                viewport.origin,
+                point,
+                display_offset,
+            );
+        }
+    }
+
+    fn update_font(&mut self) {
+        let font = {
+            let config = self.config_service.read().unwrap();
+            let mut font = config.font.clone();
+            font.size = (font.size + self.font_size_delta).max(1.0);
+            if self.glyph_manager.font() == &font {
+                return;
             }
+            font
+        };
+
+        // グリフのキャッシュとセルの配置を作り直す
+        self.glyph_manager = GlyphManager::new_with_font(&font);
+        for content_plotter in self.content_plotter_table.values_mut() {
+            content_plotter.rebuild();
         }
 
-        // TODO: ↑ は背景の変更だけにして処理を切り離したい
-        // tty の差分をレンダラーに反映
-        for window_id in self.window_manager.ids() {
-            let Some(window) = self.window_manager.try_get_window(*window_id) else {
+        // すべてのセルを描画しなおす
+        for tty_ids in self.window_tty_table.values() {
+            for tty_id in tty_ids {
+                self.teletype_manager.set_dirty(*tty_id);
+            }
+        }
+
+        // セルの大きさが変わるので行数と列数を計算しなおす
+        for window_id in self.window_manager.ids().to_vec() {
+            let Some(window) = self.window_manager.try_get_window(window_id) else {
                 continue;
             };
+            let size = window.inner_size();
+            self.resize(window_id, size.width, size.height);
+        }
+    }
 
-            let Some(teletype_ids) = self.window_tty_table.get(window_id) else {
+    fn answer_terminal_query(&self, id: TeletypeId, query: TerminalQuery) -> Option<String> {
+        match query {
+            TerminalQuery::Color(index, format) => {
+                // アプリが上書きした色があればそちらを答える
+                let colors = self.teletype_manager.colors(id);
+                Some(format(self.color_palette.get_with_override(index, &colors)))
+            }
+            TerminalQuery::TextAreaSize(format) => {
+                // resize で pty に伝えたのと同じく、tty のペインの大きさとセルの大きさから求める
+                let (_, _, viewport) = self
+                    .window_manager
+                    .ids()
+                    .iter()
+                    .flat_map(|window_id| self.pane_viewports(*window_id))
+                    .find(|(_, tty_id, _)| *tty_id == id)?;
+                let (width, height) = viewport.size;
+                Some(format(
+                    self.glyph_manager.cell_metrics().window_size(width, height),
+                ))
+            }
+        }
+    }
+
+    // フォーカスしているペインのタイトルをウィンドウに表示する
+    fn update_window_titles(&mut self) {
+        let mut window_config = self.config_service.read().unwrap().window.clone();
+        if let Some(title) = &self.command_line_options.title {
+            window_config.title = title.clone();
+        }
+        let needs_cwd = window_config.title_template.contains("{cwd}");
+
+        for window_id in self.window_manager.ids().to_vec() {
+            let Some((tty_id, _)) = self.active_mode(window_id) else {
                 continue;
             };
 
-            for teletype_id in teletype_ids {
-                if !self.teletype_manager.is_dirty(*teletype_id) {
-                    continue;
-                }
+            let cwd = if needs_cwd {
+                self.teletype_manager.working_directory(tty_id)
+            } else {
+                None
+            };
+            let title = window_config.format_title(
+                self.title_table.get(&tty_id).map(String::as_str),
+                cwd.as_deref(),
+            );
+            self.window_manager.set_window_title(window_id, title);
+        }
+    }
 
-                // レンダラーに反映
-                self.teletype_manager.get_content(*teletype_id, |c| {
-                    let diff = self.content_plotter.calculate_diff(
-                        c,
-                        &mut self.glyph_manager,
-                        (window.inner_size().width, window.inner_size().height),
-                    );
-                    let update_params = RendererUpdateParams::<String>::new(
-                        window.inner_size().width,
-                        window.inner_size().height,
-                    )
-                    .with_diff(diff);
-                    self.renderer.update(*window_id, update_params);
-                });
+    fn update_color_palette(&mut self) {
+        let config = self.config_service.read().unwrap();
+        if self.color_palette.source() == &config.colors {
+            return;
+        }
 
-                // ダーティフラグを解除
-                self.teletype_manager.clear_dirty(*teletype_id);
+        // 色は解決済みの値で差分を取っているので描画しなおせば反映される
+        self.color_palette = ColorPalette::new(&config.colors);
+        drop(config);
+        for tty_ids in self.window_tty_table.values() {
+            for tty_id in tty_ids {
+                self.teletype_manager.set_dirty(*tty_id);
             }
         }
     }
 
+    // 画面の行数はセルの大きさとペインの大きさから求める
+    fn scroll_indicator(&self, id: TeletypeId, size: (u32, u32)) -> ScrollIndicator {
+        let (display_offset, history_size) = self.teletype_manager.scroll_position(id);
+        let window_size = self
+            .glyph_manager
+            .cell_metrics()
+            .window_size(size.0, size.1);
+        ScrollIndicator::new(display_offset, history_size, window_size.num_lines as usize)
+    }
+
+    // 検索中のペインの入力欄と表示中の一致
+    // 貼り付けの確認を待っているあいだは、貼り付けるペインに入力欄の代わりに確認を表示する
+    fn search_overlay(&self, window_id: WindowId, id: TeletypeId) -> Option<SearchOverlay> {
+        let pending_paste = self
+            .pending_paste_table
+            .get(&window_id)
+            .filter(|_| self.active_tty(window_id) == Some(id));
+        if let Some(text) = pending_paste {
+            let prompt = format!(
+                "Paste {} line(s)? Enter to paste, Esc to cancel",
+                text.lines().count()
+            );
+            return Some(SearchOverlay::new(prompt, Vec::new(), None));
+        }
+
+        let search_state = self.search_table.get(&id)?;
+        let matches = match search_state.regex() {
+            Some(mut regex) => self.teletype_manager.visible_matches(id, &mut regex),
+            None => Vec::new(),
+        };
+        Some(SearchOverlay::new(
+            search_state.bar(),
+            matches,
+            search_state.focused_match().cloned(),
+        ))
+    }
+
     pub fn render(&mut self, id: WindowId) {
         self.renderer.render(id);
     }
 
     pub fn resize(&mut self, id: WindowId, width: u32, height: u32) {
         // 仮想ウインドウにリサイズを反映
-        self.virtual_window_manager.resize(width, height);
+        let Some(virtual_window_id) = self.window_virtual_window_table.get(&id) else {
+            return;
+        };
+        self.virtual_window_manager
+            .resize_window(*virtual_window_id, width, height);
+        self.virtual_window_manager.uodate();
 
         self.renderer.resize(id, width, height);
 
-        let Some(tty_ids) = self.window_tty_table.get(&id) else {
+        for (_, tty_id, viewport) in self.pane_viewports(id) {
+            // tty のリサイズ
+            // 行数と列数はペインの大きさとセルの大きさから求めて描画と pty で一致させる
+            // セルはペインの行数と列数で並べるので、描く前に端末の大きさを合わせておく
+            let (pane_width, pane_height) = viewport.size;
+            let window_size = self
+                .glyph_manager
+                .cell_metrics()
+                .window_size(pane_width, pane_height);
+            self.teletype_manager.resize(tty_id, window_size);
+            self.send_to_tty(tty_id, Msg::Resize(window_size));
+
+            let update_params = RendererUpdateParams::new(width, height);
+            self.update_pane(id, tty_id, &viewport, update_params);
+        }
+
+        // 最描画要求
+        let Some(window) = self.window_manager.try_get_window(id) else {
             return;
         };
+        window.request_redraw();
+    }
+
+    // ウィンドウのペインを左上から並んでいる順に、描く場所と合わせて列挙する
+    fn pane_viewports(&self, id: WindowId) -> Vec<(VirtualWindowId, TeletypeId, PaneViewport)> {
+        let Some(virtual_window_id) = self.window_virtual_window_table.get(&id) else {
+            return Vec::default();
+        };
+        let Some(window_size) = self
+            .virtual_window_manager
+            .try_get_actual_size(*virtual_window_id)
+        else {
+            return Vec::default();
+        };
 
-        for tty_id in tty_ids {
-            self.teletype_manager.is_dirty(*tty_id);
+        let panes = self
+            .pane_ids(id)
+            .into_iter()
+            .filter_map(|pane_id| {
+                let tty_id = *self.virtual_window_tty_table.get(&pane_id)?;
+                let origin = self
+                    .virtual_window_manager
+                    .try_get_actual_position(pane_id)?;
+                let size = self.virtual_window_manager.try_get_actual_size(pane_id)?;
+                Some((pane_id, tty_id, origin, size))
+            })
+            .collect::<Vec<_>>();
 
-            self.teletype_manager.get_content(*tty_id, |c| {
-                let diff = self.content_plotter.calculate_diff(
-                    c,
-                    &mut self.glyph_manager,
-                    (width, height),
-                );
-                let update_params =
-                    RendererUpdateParams::<String>::new(width, height).with_diff(diff);
-                self.renderer.update(id, update_params);
-            });
+        // セルはウィンドウのペインを並べた順に続けて置く
+        let cell_metrics = self.glyph_manager.cell_metrics();
+        let cell_counts = panes
+            .iter()
+            .map(|(_, _, _, (width, height))| {
+                cell_metrics.lines(*height) * cell_metrics.columns(*width)
+            })
+            .collect::<Vec<usize>>();
+        let window_cell_count = cell_counts.iter().sum();
 
-            // tty のリサイズ
-            self.teletype_manager.resize(*tty_id, width, height);
-
-            let lines = height as u16 / 16;
-            let columns = width as u16 / 16;
-            self.sender
-                .as_mut()
-                .unwrap()
-                .send(Msg::Resize(WindowSize {
-                    num_lines: lines,
-                    num_cols: columns,
-                    cell_width: 8,
-                    cell_height: 8,
-                }))
-                .unwrap();
+        let active_pane_id = self.active_window_table.get(&id);
+        let mut first_cell_index = 0;
+        panes
+            .into_iter()
+            .zip(cell_counts)
+            .map(|((pane_id, tty_id, origin, size), cell_count)| {
+                let viewport = PaneViewport {
+                    window_size,
+                    origin,
+                    size,
+                    first_cell_index,
+                    window_cell_count,
+                    is_focused: active_pane_id == Some(&pane_id),
+                };
+                first_cell_index += cell_count;
+                (pane_id, tty_id, viewport)
+            })
+            .collect()
+    }
+
+    // tty を割り当てたペインを左上から並んでいる順に列挙する
+    fn pane_ids(&self, id: WindowId) -> Vec<VirtualWindowId> {
+        let Some(virtual_window_id) = self.window_virtual_window_table.get(&id) else {
+            return Vec::default();
+        };
+        self.virtual_window_manager
+            .leaves(*virtual_window_id)
+            .into_iter()
+            .filter(|pane_id| self.virtual_window_tty_table.contains_key(pane_id))
+            .collect()
+    }
+
+    // ペインを増やしたり減らしたりしたら、ウィンドウの大きさを配りなおしてすべて描きなおす
+    fn relayout(&mut self, id: WindowId) {
+        let tty_ids = self
+            .pane_ids(id)
+            .iter()
+            .filter_map(|pane_id| self.virtual_window_tty_table.get(pane_id).copied())
+            .collect::<Vec<TeletypeId>>();
+        for tty_id in &tty_ids {
+            self.teletype_manager.set_dirty(*tty_id);
         }
+        self.window_tty_table.insert(id, tty_ids);
 
-        // 最描画要求
         let Some(window) = self.window_manager.try_get_window(id) else {
             return;
         };
-        window.request_redraw();
+        let size = window.inner_size();
+        self.resize(id, size.width, size.height);
+    }
+
+    // フォーカスしているペインを分けて、新しいペインでシェルを起動してフォーカスを移す
+    fn split_pane(&mut self, id: WindowId, direction: SplitDirection) {
+        let Some(pane_id) = self.active_window_table.get(&id) else {
+            return;
+        };
+        let Some(new_pane_id) = self.virtual_window_manager.split(*pane_id, direction) else {
+            return;
+        };
+
+        let shell = self.shell_for(id);
+        let (tty_id, sender) = self.teletype_manager.create_teletype(&shell);
+        self.sender_table.insert(tty_id, sender);
+        self.virtual_window_tty_table.insert(new_pane_id, tty_id);
+
+        self.focus_pane(id, new_pane_id);
+        self.relayout(id);
+    }
+
+    // ウィンドウを開いたプロファイルのシェル
+    fn shell_for(&self, id: WindowId) -> Shell {
+        let profile = self.window_profile_table.get(&id).map(String::as_str);
+        self.config_service.read().unwrap().shell_for(profile)
+    }
+
+    // フォーカスしているペインの tty を終了してペインを取り除き、前のペインにフォーカスを移す
+    // 最後のペインを閉じたらウィンドウも閉じる
+    fn close_pane(&mut self, id: WindowId) {
+        let Some(pane_id) = self.active_window_table.get(&id).copied() else {
+            return;
+        };
+        let index = self
+            .pane_ids(id)
+            .iter()
+            .position(|other_id| *other_id == pane_id)
+            .unwrap_or_default();
+        self.remove_pane(pane_id);
+
+        let pane_ids = self.pane_ids(id);
+        let Some(next_pane_id) = pane_ids.get(index.saturating_sub(1)) else {
+            self.close_window(id);
+            return;
+        };
+        self.focus_pane(id, *next_pane_id);
+        self.relayout(id);
+    }
+
+    fn remove_pane(&mut self, pane_id: VirtualWindowId) {
+        if let Some(tty_id) = self.virtual_window_tty_table.remove(&pane_id) {
+            self.send_to_tty(tty_id, Msg::Shutdown);
+            self.sender_table.remove(&tty_id);
+            self.search_table.remove(&tty_id);
+            self.title_table.remove(&tty_id);
+            self.teletype_manager.remove_teletype(tty_id);
+        }
+        self.virtual_window_manager.remove_virtual_window(pane_id);
+    }
+
+    // ウィンドウのペインをすべて閉じて、ウィンドウのリソースを捨てる
+    fn close_window(&mut self, id: WindowId) {
+        for pane_id in self.pane_ids(id) {
+            self.remove_pane(pane_id);
+        }
+        if let Some(virtual_window_id) = self.window_virtual_window_table.remove(&id) {
+            self.virtual_window_manager
+                .remove_virtual_window(virtual_window_id);
+        }
+
+        self.active_window_table.remove(&id);
+        self.window_tty_table.remove(&id);
+        self.content_plotter_table.remove(&id);
+        self.mouse_state_table.remove(&id);
+        self.bound_key_table.remove(&id);
+        self.preedit_table.remove(&id);
+        self.pending_paste_table.remove(&id);
+        self.window_profile_table.remove(&id);
+        self.renderer.unregister(id);
+        self.window_manager.remove_window(id);
     }
 
-    pub fn send(&mut self, _id: WindowId, text: &str) {
-        let mut bytes = Vec::with_capacity(text.len() + 1);
-        bytes.extend_from_slice(text.as_bytes());
-        if text.is_empty() {
-            bytes.push(b'\x1b');
+    pub fn has_window(&self) -> bool {
+        !self.window_manager.ids().is_empty()
+    }
+
+    // キー入力を端末のモードに合わせたバイト列にして送る
+    pub fn send_key(&mut self, id: WindowId, event: &KeyEvent, modifiers: ModifiersState) {
+        // 貼り付けの確認中は Enter で貼り付け、Esc で取り消して、ほかのキーは送らない
+        if self.pending_paste_table.contains_key(&id) {
+            if event.state == ElementState::Pressed {
+                match event.logical_key {
+                    Key::Named(NamedKey::Enter) => self.confirm_paste(id, true),
+                    Key::Named(NamedKey::Escape) => self.confirm_paste(id, false),
+                    _ => {}
+                }
+            }
+            return;
+        }
+
+        // 検索中は入力欄に打ち込んで、端末には送らない
+        if self.searching_tty(id).is_some() {
+            if event.state == ElementState::Pressed {
+                self.send_search_key(id, event, modifiers);
+            }
+            return;
         }
 
-        let send_data: std::borrow::Cow<[u8]> = match text {
-            // 上
-            "\u{f700}" => std::borrow::Cow::Borrowed(&[0x1b, 0x5b, 0x41]),
-            // 下
-            "\u{f701}" => std::borrow::Cow::Borrowed(&[0x1b, 0x5b, 0x42]),
-            // 左
-            "\u{f702}" => std::borrow::Cow::Borrowed(&[0x1b, 0x5b, 0x44]),
-            // 右
-            "\u{f703}" => std::borrow::Cow::Borrowed(&[0x1b, 0x5b, 0x43]),
-            _ => std::borrow::Cow::Owned(bytes),
+        let Some((_, mode)) = self.active_mode(id) else {
+            return;
         };
 
-        self.sender
-            .as_mut()
-            .unwrap()
-            .send(Msg::Input(send_data))
-            .unwrap();
+        // 割り当てのあるキーは端末に送らない
+        // 押したことを送っていないキーのリピートや離したことも送らない
+        let bound_keys = self.bound_key_table.entry(id).or_default();
+        match event.state {
+            ElementState::Pressed => {
+                let action = self.key_binding_table.find(
+                    &event.logical_key,
+                    &event.key_without_modifiers(),
+                    modifiers,
+                    mode,
+                );
+                if let Some(action) = action.cloned() {
+                    bound_keys.insert(event.physical_key);
+                    return self.perform_action(id, action);
+                }
+                if event.repeat && bound_keys.contains(&event.physical_key) {
+                    return;
+                }
+                bound_keys.remove(&event.physical_key);
+            }
+            ElementState::Released => {
+                if bound_keys.remove(&event.physical_key) {
+                    return;
+                }
+            }
+        }
+
+        let Some(bytes) = KeyEncoder::encode_event(event, modifiers, mode) else {
+            return;
+        };
+
+        // 入力したら最新の行に戻る
+        self.scroll_display(id, Scroll::Bottom);
+        self.send(id, bytes);
+    }
+
+    fn scroll_display(&mut self, id: WindowId, scroll: Scroll) {
+        if let Some((tty_id, _)) = self.active_mode(id) {
+            self.teletype_manager.scroll_display(tty_id, scroll);
+        }
+    }
+
+    fn perform_action(&mut self, id: WindowId, action: Action) {
+        match action {
+            Action::Copy => self.copy_selection(id),
+            Action::Paste => self.paste(id, ClipboardType::Clipboard),
+            Action::SpawnWindow => self.is_spawn_window_requested = true,
+            Action::SpawnWindowWithProfile(profile) => {
+                self.spawn_profile = Some(profile);
+                self.is_spawn_window_requested = true;
+            }
+            Action::SplitHorizontal => self.split_pane(id, SplitDirection::Horizontal),
+            Action::SplitVertical => self.split_pane(id, SplitDirection::Vertical),
+            Action::FocusNext => self.focus_next(id),
+            Action::ClosePane => self.close_pane(id),
+            Action::IncreaseFontSize => self.font_size_delta += 1.0,
+            Action::DecreaseFontSize => self.font_size_delta -= 1.0,
+            Action::ResetFontSize => self.font_size_delta = 0.0,
+            Action::ScrollPageUp => self.scroll_display(id, Scroll::PageUp),
+            Action::ScrollPageDown => self.scroll_display(id, Scroll::PageDown),
+            Action::ScrollToTop => self.scroll_display(id, Scroll::Top),
+            Action::ScrollToBottom => self.scroll_display(id, Scroll::Bottom),
+            Action::SearchForward => self.start_search(id, Direction::Right),
+            Action::SearchBackward => self.start_search(id, Direction::Left),
+            Action::ToggleSearchCaseSensitive => {
+                if let Some(tty_id) = self.searching_tty(id) {
+                    self.edit_search(tty_id, SearchState::toggle_case_sensitive);
+                }
+            }
+            Action::ToggleSearchRegex => {
+                if let Some(tty_id) = self.searching_tty(id) {
+                    self.edit_search(tty_id, SearchState::toggle_regex);
+                }
+            }
+            Action::ToggleFullscreen => {
+                if let Some(window) = self.window_manager.try_get_window(id) {
+                    let fullscreen = match window.fullscreen() {
+                        Some(_) => None,
+                        None => Some(Fullscreen::Borderless(None)),
+                    };
+                    window.set_fullscreen(fullscreen);
+                }
+            }
+            Action::SendBytes(bytes) => self.send(id, bytes),
+            Action::SendString(text) => self.send(id, text.into_bytes()),
+            Action::None => {}
+        }
+    }
+
+    // 検索中なら向きを変えて次を探す
+    fn start_search(&mut self, id: WindowId, direction: Direction) {
+        let Some((tty_id, _)) = self.active_mode(id) else {
+            return;
+        };
+        match self.search_table.get_mut(&tty_id) {
+            Some(search_state) => {
+                search_state.set_direction(direction);
+                self.search_next(tty_id, direction);
+            }
+            None => {
+                let origin = self.teletype_manager.search_origin(tty_id, direction);
+                self.search_table
+                    .insert(tty_id, SearchState::new(direction, origin));
+                self.teletype_manager.set_dirty(tty_id);
+            }
+        }
+    }
+
+    // 検索中ならアクティブな tty
+    fn searching_tty(&self, id: WindowId) -> Option<TeletypeId> {
+        let (tty_id, _) = self.active_mode(id)?;
+        self.search_table.contains_key(&tty_id).then_some(tty_id)
+    }
+
+    // Enter で次、Shift+Enter で逆向きに探し、Esc で検索をやめる
+    // Alt+C で大文字と小文字の区別、Alt+R で正規表現を切り替える
+    fn send_search_key(&mut self, id: WindowId, event: &KeyEvent, modifiers: ModifiersState) {
+        let Some(tty_id) = self.searching_tty(id) else {
+            return;
+        };
+
+        // 検索の操作に割り当てたキーはそのまま使う
+        let action = self.key_binding_table.find(
+            &event.logical_key,
+            &event.key_without_modifiers(),
+            modifiers,
+            self.teletype_manager.mode(tty_id),
+        );
+        if let Some(
+            action @ (Action::SearchForward
+            | Action::SearchBackward
+            | Action::ToggleSearchCaseSensitive
+            | Action::ToggleSearchRegex),
+        ) = action.cloned()
+        {
+            return self.perform_action(id, action);
+        }
+
+        let Some(direction) = self.search_table.get(&tty_id).map(SearchState::direction) else {
+            return;
+        };
+        let unmodified_key = event.key_without_modifiers();
+        let is_alt = |c: &str| {
+            modifiers == ModifiersState::ALT
+                && matches!(&unmodified_key, Key::Character(key) if key.as_str() == c)
+        };
+        match &event.logical_key {
+            Key::Named(NamedKey::Escape) => {
+                self.search_table.remove(&tty_id);
+                self.teletype_manager.set_dirty(tty_id);
+            }
+            Key::Named(NamedKey::Enter) if modifiers.shift_key() => {
+                self.search_next(tty_id, direction.opposite())
+            }
+            Key::Named(NamedKey::Enter) => self.search_next(tty_id, direction),
+            Key::Named(NamedKey::Backspace) => self.edit_search(tty_id, SearchState::pop),
+            _ if is_alt("c") => self.edit_search(tty_id, SearchState::toggle_case_sensitive),
+            _ if is_alt("r") => self.edit_search(tty_id, SearchState::toggle_regex),
+            _ if modifiers.control_key() || modifiers.alt_key() || modifiers.super_key() => {}
+            _ => {
+                let Some(text) = event.text.as_ref() else {
+                    return;
+                };
+                if !text.chars().any(char::is_control) {
+                    self.edit_search(tty_id, |search_state| search_state.push_str(text));
+                }
+            }
+        }
+    }
+
+    // 入力が変わったら検索を始めた位置から探しなおす
+    fn edit_search<F: FnOnce(&mut SearchState)>(&mut self, tty_id: TeletypeId, func: F) {
+        let Some(search_state) = self.search_table.get_mut(&tty_id) else {
+            return;
+        };
+        func(search_state);
+
+        let (origin, direction) = (search_state.origin(), search_state.direction());
+        let focused_match = search_state.regex().and_then(|mut regex| {
+            self.teletype_manager
+                .search_from(tty_id, &mut regex, origin, direction)
+        });
+        search_state.set_focused_match(focused_match);
+        self.teletype_manager.set_dirty(tty_id);
+    }
+
+    // フォーカスしている一致の次を探す。まだなければ検索を始めた位置から探す
+    fn search_next(&mut self, tty_id: TeletypeId, direction: Direction) {
+        let Some(search_state) = self.search_table.get_mut(&tty_id) else {
+            return;
+        };
+        let Some(mut regex) = search_state.regex() else {
+            return;
+        };
+        let focused_match = match search_state.focused_match() {
+            Some(focused_match) => {
+                self.teletype_manager
+                    .search_after(tty_id, &mut regex, focused_match, direction)
+            }
+            None => self.teletype_manager.search_from(
+                tty_id,
+                &mut regex,
+                search_state.origin(),
+                direction,
+            ),
+        };
+        if focused_match.is_some() {
+            search_state.set_focused_match(focused_match);
+        }
+        self.teletype_manager.set_dirty(tty_id);
+    }
+
+    // ウィンドウの中で左上から並んでいる順に次のペインにフォーカスを移す
+    fn focus_next(&mut self, id: WindowId) {
+        let pane_ids = self.pane_ids(id);
+        let Some(index) = self
+            .active_window_table
+            .get(&id)
+            .and_then(|pane_id| pane_ids.iter().position(|other_id| other_id == pane_id))
+        else {
+            return;
+        };
+        let next_pane_id = pane_ids[(index + 1) % pane_ids.len()];
+        self.focus_pane(id, next_pane_id);
+    }
+
+    // クリックした位置のペインにフォーカスを移す
+    fn focus_pane_at(&mut self, id: WindowId, x: f64, y: f64) {
+        let pane_id = self
+            .pane_viewports(id)
+            .into_iter()
+            .find(|(_, _, viewport)| {
+                let (left, top) = (viewport.origin.0 as f64, viewport.origin.1 as f64);
+                let (right, bottom) = (left + viewport.size.0 as f64, top + viewport.size.1 as f64);
+                left <= x && x < right && top <= y && y < bottom
+            })
+            .map(|(pane_id, _, _)| pane_id);
+        if let Some(pane_id) = pane_id {
+            self.focus_pane(id, pane_id);
+        }
+    }
+
+    // カーソルを描くペインが変わるので、ウィンドウのペインをすべて描きなおす
+    // 貼り付けの確認はフォーカスしていたペインへのものなので取り消す
+    fn focus_pane(&mut self, id: WindowId, pane_id: VirtualWindowId) {
+        if self.active_window_table.insert(id, pane_id) == Some(pane_id) {
+            return;
+        }
+        self.pending_paste_table.remove(&id);
+        for (_, tty_id, _) in self.pane_viewports(id) {
+            self.teletype_manager.set_dirty(tty_id);
+        }
+    }
+
+    // 新しいウィンドウを要求されていたら、要求を取り消して true を返す
+    pub fn take_spawn_window_request(&mut self) -> bool {
+        std::mem::take(&mut self.is_spawn_window_requested)
+    }
+
+    // 選択中の文字列をクリップボードにコピーする
+    pub fn copy_selection(&mut self, id: WindowId) {
+        let Some((tty_id, _)) = self.active_mode(id) else {
+            return;
+        };
+        if let Some(text) = self.teletype_manager.selection_to_string(tty_id) {
+            self.clipboard_service.store(ClipboardType::Clipboard, text);
+        }
+    }
+
+    // カーソルの位置を記録して、ドラッグ中なら選択範囲を広げ、マウスモードならセルをまたいだ移動を送る
+    pub fn mouse_moved(&mut self, id: WindowId, x: f64, y: f64, modifiers: ModifiersState) {
+        let cell = self.pane_cell(id, x, y);
+        let mouse_state = self.mouse_state_table.entry(id).or_default();
+        mouse_state.position = (x, y);
+        let Some((point, side)) = cell else {
+            return;
+        };
+        let (is_selecting, button) = (mouse_state.is_selecting, mouse_state.pressed_button);
+        let is_moved = mouse_state.last_point != Some(point);
+        mouse_state.last_point = Some(point);
+
+        let Some((tty_id, mode)) = self.active_mode(id) else {
+            return;
+        };
+        if is_selecting {
+            self.teletype_manager.update_selection(tty_id, point, side);
+            return;
+        }
+        if !is_moved || !Self::is_mouse_reported(mode, modifiers) {
+            return;
+        }
+        if let Some(bytes) = MouseEncoder::encode_motion(button, point, modifiers, mode) {
+            self.send(id, bytes);
+        }
+    }
+
+    // マウスモードなら端末に送り、そうでなければ左ボタンで選択して中ボタンで貼り付ける
+    pub fn mouse_input(
+        &mut self,
+        id: WindowId,
+        state: ElementState,
+        button: MouseButton,
+        modifiers: ModifiersState,
+    ) {
+        let mouse_state = self.mouse_state_table.entry(id).or_default();
+        let (x, y) = mouse_state.position;
+        match state {
+            ElementState::Pressed => mouse_state.pressed_button = Some(button),
+            ElementState::Released if mouse_state.pressed_button == Some(button) => {
+                mouse_state.pressed_button = None
+            }
+            ElementState::Released => {}
+        }
+
+        // ボタンを押したらその位置のペインを操作する
+        if state == ElementState::Pressed {
+            self.focus_pane_at(id, x, y);
+        }
+
+        let (Some((point, side)), Some((tty_id, mode))) =
+            (self.pane_cell(id, x, y), self.active_mode(id))
+        else {
+            return;
+        };
+        if Self::is_mouse_reported(mode, modifiers) {
+            if let Some(bytes) = MouseEncoder::encode_button(button, state, point, modifiers, mode)
+            {
+                self.send(id, bytes);
+            }
+            return;
+        }
+
+        let mouse_state = self.mouse_state_table.entry(id).or_default();
+        match (button, state) {
+            (MouseButton::Left, ElementState::Pressed) => {
+                // Alt を押していれば矩形、ダブルクリックで単語、トリプルクリックで行を選択する
+                let click_count = mouse_state.click(point, Instant::now());
+                let ty = match click_count {
+                    _ if modifiers.alt_key() => SelectionType::Block,
+                    2 => SelectionType::Semantic,
+                    3 => SelectionType::Lines,
+                    _ => SelectionType::Simple,
+                };
+                mouse_state.is_selecting = true;
+                self.teletype_manager
+                    .start_selection(tty_id, ty, point, side);
+            }
+            (MouseButton::Left, ElementState::Released) if mouse_state.is_selecting => {
+                // 選択した文字列はプライマリセレクションに入れておく
+                mouse_state.is_selecting = false;
+                if let Some(text) = self.teletype_manager.selection_to_string(tty_id) {
+                    self.clipboard_service.store(ClipboardType::Selection, text);
+                }
+            }
+            (MouseButton::Middle, ElementState::Pressed) => {
+                self.paste(id, ClipboardType::Selection);
+            }
+            _ => {}
+        }
+    }
+
+    // マウスモードなら端末に送り、代替スクリーンなら矢印キーにして、それ以外はスクロールバックを動かす
+    // クリックと同じくシフトを押していればマウスモードでも端末には送らない
+    pub fn mouse_wheel(
+        &mut self,
+        id: WindowId,
+        delta: MouseScrollDelta,
+        modifiers: ModifiersState,
+    ) {
+        // ホイールは設定の倍率をかけ、タッチパッドは 1 行分たまるまで待つ
+        let lines = match delta {
+            MouseScrollDelta::LineDelta(_, y) => {
+                let multiplier = self.config_service.read().unwrap().scrolling.multiplier;
+                y.round() as i32 * multiplier as i32
+            }
+            MouseScrollDelta::PixelDelta(position) => {
+                let cell_height = self.glyph_manager.cell_metrics().cell_height();
+                self.mouse_state_table
+                    .entry(id)
+                    .or_default()
+                    .scroll_pixels(position.y, cell_height as f64)
+            }
+        };
+        if lines == 0 {
+            return;
+        }
+
+        let Some((tty_id, mode)) = self.active_mode(id) else {
+            return;
+        };
+        if Self::is_mouse_reported(mode, modifiers) {
+            let (x, y) = self.mouse_state_table.entry(id).or_default().position;
+            let Some((point, _)) = self.pane_cell(id, x, y) else {
+                return;
+            };
+            if let Some(bytes) = MouseEncoder::encode_wheel(lines, point, modifiers, mode) {
+                self.send(id, bytes);
+            }
+        } else if let Some(bytes) = MouseEncoder::encode_alternate_scroll(lines, mode) {
+            self.send(id, bytes);
+        } else if !mode.contains(TermMode::ALT_SCREEN) {
+            self.teletype_manager
+                .scroll_display(tty_id, Scroll::Delta(lines));
+        }
+    }
+
+    // 変換中の文字列はカーソルの位置に重ねて描き、確定した文字列を送る
+    pub fn ime(&mut self, id: WindowId, ime: Ime) {
+        match ime {
+            Ime::Preedit(text, cursor) if !text.is_empty() => {
+                let cursor = cursor.map(|(begin, _end)| begin);
+                self.preedit_table.insert(id, Preedit::new(text, cursor));
+            }
+            Ime::Commit(text) => {
+                // 貼り付けの確認中はキー入力と同じく送らない
+                self.preedit_table.remove(&id);
+                if !self.pending_paste_table.contains_key(&id) {
+                    self.scroll_display(id, Scroll::Bottom);
+                    self.send(id, text.into_bytes());
+                }
+            }
+            Ime::Preedit(..) | Ime::Enabled | Ime::Disabled => {
+                self.preedit_table.remove(&id);
+            }
+        }
+
+        if let Some(tty_ids) = self.window_tty_table.get(&id) {
+            for tty_id in tty_ids {
+                self.teletype_manager.set_dirty(*tty_id);
+            }
+        }
+    }
+
+    // 候補ウィンドウの位置はウィンドウの左上からなのでペインの位置を足す
+    fn set_ime_cursor_area(
+        window: &Window,
+        cell_metrics: &CellMetrics,
+        origin: (u32, u32),
+        cursor: Point,
+        display_offset: usize,
+    ) {
+        // スクロールバックをさかのぼってカーソルが画面外にあるときはそのまま
+        let line = cursor.line.0 + display_offset as i32;
+        if line < 0 {
+            return;
+        }
+
+        let (width, height) = (cell_metrics.cell_width(), cell_metrics.cell_height());
+        window.set_ime_cursor_area(
+            PhysicalPosition::new(
+                origin.0 + cursor.column.0 as u32 * width,
+                origin.1 + line as u32 * height,
+            ),
+            PhysicalSize::new(width, height),
+        );
+    }
+
+    // 入力を送る tty とそのモード
+    fn active_mode(&self, id: WindowId) -> Option<(TeletypeId, TermMode)> {
+        let tty_id = self.active_tty(id)?;
+        Some((tty_id, self.teletype_manager.mode(tty_id)))
+    }
+
+    // フォーカスしているペインの tty
+    fn active_tty(&self, id: WindowId) -> Option<TeletypeId> {
+        let pane_id = self.active_window_table.get(&id)?;
+        self.virtual_window_tty_table.get(pane_id).copied()
+    }
+
+    // アプリがマウスを使っていてもシフトを押していれば選択に使う
+    fn is_mouse_reported(mode: TermMode, modifiers: ModifiersState) -> bool {
+        mode.intersects(TermMode::MOUSE_MODE) && !modifiers.shift_key()
+    }
+
+    // アクティブなペインの大きさでピクセル座標をセルとその左右どちら側かに変換する
+    // ペインの外はペインの端のセルにする
+    fn pane_cell(&self, id: WindowId, x: f64, y: f64) -> Option<(Point<usize>, Side)> {
+        let viewport = self.active_viewport(id)?;
+        let (x, y) = (x - viewport.origin.0 as f64, y - viewport.origin.1 as f64);
+        let (width, height) = viewport.size;
+        let cell_metrics = self.glyph_manager.cell_metrics();
+        let (column, line) = cell_metrics.cell_at(x, y, width, height);
+        let side = cell_metrics.cell_side(x, width);
+        Some((Point::new(line, Column(column)), side))
+    }
+
+    // フォーカスしているペインの場所
+    fn active_viewport(&self, id: WindowId) -> Option<PaneViewport> {
+        self.pane_viewports(id)
+            .into_iter()
+            .find(|(_, _, viewport)| viewport.is_focused)
+            .map(|(_, _, viewport)| viewport)
+    }
+
+    pub fn send(&mut self, id: WindowId, bytes: Vec<u8>) {
+        let Some((tty_id, _)) = self.active_mode(id) else {
+            return;
+        };
+        self.send_to_tty(tty_id, Msg::Input(Cow::Owned(bytes)));
+    }
+
+    // 終了した tty には送らない
+    fn send_to_tty(&self, id: TeletypeId, msg: Msg) {
+        let Some(sender) = self.sender_table.get(&id) else {
+            return;
+        };
+        let _ = sender.send(msg);
+    }
+
+    pub fn paste(&mut self, id: WindowId, ty: ClipboardType) {
+        let Some(text) = self.clipboard_service.load(ty) else {
+            return;
+        };
+        self.paste_text(id, text);
+    }
+
+    // ブラケットペーストが無効なら、コマンドが実行されかねない文字列は貼り付ける前に確認する
+    // 確認は検索の入力欄と同じく最下行に重ねて表示して Enter か Esc を待つ
+    pub fn paste_text(&mut self, id: WindowId, text: String) {
+        let Some((tty_id, mode)) = self.active_mode(id) else {
+            return;
+        };
+        let confirm = self.config_service.read().unwrap().paste.confirm;
+        if confirm
+            && !mode.contains(TermMode::BRACKETED_PASTE)
+            && PasteEncoder::needs_confirmation(&text)
+        {
+            self.pending_paste_table.insert(id, text);
+            self.teletype_manager.set_dirty(tty_id);
+            return;
+        }
+
+        self.send_paste(id, &text);
+    }
+
+    fn confirm_paste(&mut self, id: WindowId, is_accepted: bool) {
+        let Some(text) = self.pending_paste_table.remove(&id) else {
+            return;
+        };
+        if let Some((tty_id, _)) = self.active_mode(id) {
+            self.teletype_manager.set_dirty(tty_id);
+        }
+        if is_accepted {
+            self.send_paste(id, &text);
+        }
+    }
+
+    fn send_paste(&mut self, id: WindowId, text: &str) {
+        let Some((_, mode)) = self.active_mode(id) else {
+            return;
+        };
+        let newline = self.config_service.read().unwrap().paste.newline;
+        self.send(id, PasteEncoder::encode(text, newline, mode));
     }
 
     pub fn is_empty(&self) -> bool {